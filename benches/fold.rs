@@ -0,0 +1,31 @@
+// Compares the lane folds used for long operand lists with a plain left-to-right fold.
+// Run with `cargo bench`.
+use std::hint::black_box;
+use std::time::Instant;
+
+#[path = "../src/fold.rs"]
+mod fold;
+
+fn time(name: &str, runs: u32, f: impl Fn() -> f64) {
+    let start = Instant::now();
+    for _ in 0..runs {
+        black_box(f());
+    }
+    println!("{:<12} {:>10.2?} per run", name, start.elapsed() / runs);
+}
+
+fn main() {
+    let values: Vec<f64> = (0..100_000).map(|i| 1.0 + (i % 97) as f64 * 1e-6).collect();
+    let values = black_box(values);
+    let runs = 200;
+    println!("{} values, {} lanes; qqc folds lines of {}+ numbers this way", values.len(), fold::LANES, fold::THRESHOLD);
+
+    time("naive sum", runs, || values.iter().copied().reduce(|a, b| a + b).unwrap());
+    time("lane sum", runs, || fold::sum(&values));
+    time("naive prod", runs, || values.iter().copied().reduce(|a, b| a * b).unwrap());
+    time("lane prod", runs, || fold::product(&values));
+    time("naive min", runs, || values.iter().copied().reduce(f64::min).unwrap());
+    time("lane min", runs, || fold::min(&values));
+    time("naive max", runs, || values.iter().copied().reduce(f64::max).unwrap());
+    time("lane max", runs, || fold::max(&values));
+}
@@ -0,0 +1,140 @@
+#!/usr/bin/env python3
+"""A Jupyter kernel for qqc.
+
+Jupyter starts this script with a connection file. It speaks the Jupyter wire protocol over
+ZeroMQ (signed multipart messages on the shell, control, iopub, stdin and heartbeat sockets)
+and relays each request to `qqc kernel`, which evaluates the cells and answers in a
+line-delimited JSON protocol: one `{"msg_type", "content"}` object per line, any messages for
+the frontend's output first and then exactly one `*_reply`.
+
+Needs Python 3 with pyzmq, and a qqc built with `--features jupyter` on the PATH (or named by
+the QQC environment variable). Arguments after the connection file are passed on to
+`qqc kernel`, e.g. `--sandbox`.
+"""
+import datetime
+import hashlib
+import hmac
+import json
+import os
+import subprocess
+import sys
+import threading
+import uuid
+
+import zmq
+
+DELIMITER = b"<IDS|MSG>"
+PROTOCOL_VERSION = "5.3"
+
+
+class Session:
+    def __init__(self, key, scheme):
+        self.key = key.encode()
+        self.digest = getattr(hashlib, scheme.split("-", 1)[1])
+        self.id = uuid.uuid4().hex
+
+    def sign(self, frames):
+        if not self.key:
+            return b""
+        mac = hmac.new(self.key, digestmod=self.digest)
+        for frame in frames:
+            mac.update(frame)
+        return mac.hexdigest().encode()
+
+    # The identities, header and content of a message, or None for one with a bad signature.
+    def receive(self, socket):
+        parts = socket.recv_multipart()
+        split = parts.index(DELIMITER)
+        identities, signature, frames = parts[:split], parts[split + 1], parts[split + 2 : split + 6]
+        if not hmac.compare_digest(signature, self.sign(frames)):
+            return None
+        header, _, _, content = (json.loads(frame) for frame in frames)
+        return identities, header, content
+
+    def send(self, socket, msg_type, content, parent, identities=()):
+        header = {
+            "msg_id": uuid.uuid4().hex,
+            "session": self.id,
+            "username": "qqc",
+            "date": datetime.datetime.now(datetime.timezone.utc).isoformat(),
+            "msg_type": msg_type,
+            "version": PROTOCOL_VERSION,
+        }
+        frames = [json.dumps(part).encode() for part in (header, parent, {}, content)]
+        socket.send_multipart([*identities, DELIMITER, self.sign(frames), *frames])
+
+
+# Echoes every ping on a socket of its own, so a long evaluation doesn't look like a dead kernel.
+def heartbeat(context, url):
+    socket = context.socket(zmq.REP)
+    socket.bind(url)
+    while True:
+        socket.send(socket.recv())
+
+
+# Sends one request to `qqc kernel` and yields its answers up to and including the reply.
+def relay(qqc, msg_type, content):
+    qqc.stdin.write(json.dumps({"msg_type": msg_type, "content": content}) + "\n")
+    qqc.stdin.flush()
+    while True:
+        line = qqc.stdout.readline()
+        if not line:
+            raise SystemExit("qqc kernel exited")
+        answer = json.loads(line)
+        yield answer
+        if answer["msg_type"].endswith("_reply"):
+            return
+
+
+def main():
+    with open(sys.argv[1]) as file:
+        config = json.load(file)
+    session = Session(config["key"], config.get("signature_scheme", "hmac-sha256"))
+    context = zmq.Context()
+
+    def url(port):
+        return "{}://{}:{}".format(config["transport"], config["ip"], config[port])
+
+    shell, control, stdin = (context.socket(zmq.ROUTER) for _ in range(3))
+    shell.bind(url("shell_port"))
+    control.bind(url("control_port"))
+    stdin.bind(url("stdin_port"))
+    iopub = context.socket(zmq.PUB)
+    iopub.bind(url("iopub_port"))
+    threading.Thread(target=heartbeat, args=(context, url("hb_port")), daemon=True).start()
+
+    qqc = subprocess.Popen(
+        [os.environ.get("QQC", "qqc"), "kernel", *sys.argv[2:]],
+        stdin=subprocess.PIPE,
+        stdout=subprocess.PIPE,
+        text=True,
+    )
+    poller = zmq.Poller()
+    poller.register(shell, zmq.POLLIN)
+    poller.register(control, zmq.POLLIN)
+    execution_count = 0
+    running = True
+    while running:
+        for socket, _ in poller.poll():
+            message = session.receive(socket)
+            if message is None:
+                continue
+            identities, header, content = message
+            msg_type = header["msg_type"]
+            session.send(iopub, "status", {"execution_state": "busy"}, header)
+            if msg_type == "execute_request":
+                execution_count += 1
+                session.send(iopub, "execute_input", {"code": content.get("code", ""), "execution_count": execution_count}, header)
+            for answer in relay(qqc, msg_type, content):
+                if answer["msg_type"].endswith("_reply"):
+                    session.send(socket, answer["msg_type"], answer["content"], header, identities)
+                else:
+                    session.send(iopub, answer["msg_type"], answer["content"], header)
+            session.send(iopub, "status", {"execution_state": "idle"}, header)
+            if msg_type == "shutdown_request":
+                running = False
+    qqc.wait()
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,40 @@
+// `--cells` runs a worksheet as a spreadsheet: the names bound with `defer` are cells whose
+// values are kept until a variable they read changes, and a change recomputes only the cells
+// downstream of it. Inputs given with `-D` are pinned, so they win over the worksheet's own
+// `= name` lines.
+use core::cell::RefCell;
+
+use crate::prelude::*;
+use crate::{Deferred, Value};
+
+#[derive(Clone, Default)]
+pub struct Cells {
+    pub values: RefCell<HashMap<String, Value>>, // Worked out since their inputs last changed
+    pub pinned: HashSet<String>,                 // Set with `-D`
+    pub stale: Vec<String>,                      // Invalidated since the REPL last showed them
+}
+
+impl Cells {
+    // Forgets the value of every cell that reads `name`, directly or through other cells.
+    pub fn invalidate(&mut self, name: &str, deferred: &HashMap<String, Rc<Deferred>>) {
+        let mut changed = vec![name.to_string()];
+        let mut seen = HashSet::new();
+        while let Some(input) = changed.pop() {
+            for (cell, definition) in deferred {
+                if definition.inputs.contains(&input) && seen.insert(cell.clone()) {
+                    self.values.get_mut().remove(cell);
+                    self.stale.push(cell.clone());
+                    changed.push(cell.clone());
+                }
+            }
+        }
+    }
+
+    // The invalidated cells in name order, once each, to show again.
+    pub fn take_stale(&mut self) -> Vec<String> {
+        let mut stale = core::mem::take(&mut self.stale);
+        stale.sort();
+        stale.dedup();
+        stale
+    }
+}
@@ -0,0 +1,32 @@
+// `--copy` hands the answer to the platform's clipboard tool, so no windowing library is linked.
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::EngineError;
+
+// Clipboard commands to try in order; on Linux Wayland's comes first when a Wayland session is running.
+pub fn backends(wayland: bool) -> Vec<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        vec![&["pbcopy"]]
+    } else if cfg!(windows) {
+        vec![&["clip"]]
+    } else {
+        let x11: [&'static [&'static str]; 2] = [&["xclip", "-selection", "clipboard"], &["xsel", "--clipboard", "--input"]];
+        let mut backends = if wayland { vec![&["wl-copy"] as &[&str]] } else { vec![] };
+        backends.extend(x11);
+        backends
+    }
+}
+
+pub fn copy(text: &str) -> Result<(), EngineError> {
+    for backend in backends(std::env::var_os("WAYLAND_DISPLAY").is_some()) {
+        let Ok(mut child) = Command::new(backend[0]).args(&backend[1..]).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null()).spawn() else {
+            continue;
+        };
+        let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+        if written && child.wait().is_ok_and(|status| status.success()) {
+            return Ok(());
+        }
+    }
+    Err(EngineError::CannotWrite("clipboard".into()))
+}
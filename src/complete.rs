@@ -0,0 +1,69 @@
+// Completions for the word at a cursor, from the operator table, the constants, the unit
+// conversions and whatever the evaluator has bound so far. The REPL's `:complete` uses it,
+// and so can any editor front-end, so they all suggest the same things.
+use crate::operators::{Position, OPERATORS};
+use crate::prelude::*;
+use crate::{constants, units, Evaluator};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Kind {
+    Variable,
+    Function,
+    Operator,
+    Keyword, // Prefix operators, which only start a line
+    Constant,
+    Unit, // `to-<unit>` conversions
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Variable => "variable",
+            Kind::Function => "function",
+            Kind::Operator => "operator",
+            Kind::Keyword => "keyword",
+            Kind::Constant => "constant",
+            Kind::Unit => "unit",
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Completion {
+    pub text: String,
+    pub kind: Kind,
+}
+
+// Everything the word ending at `cursor`, a byte offset into `line`, could become. Bound
+// names come first, then the built-ins, each in name order.
+pub fn complete(line: &str, cursor: usize, engine: &Evaluator) -> Vec<Completion> {
+    let before = line.get(..cursor).unwrap_or(line);
+    let start = before.rfind(char::is_whitespace).map_or(0, |space| space + 1);
+    let word = &before[start..];
+    let previous = before[..start].split_whitespace().last();
+    let first = previous.is_none();
+
+    let mut candidates: Vec<(String, Kind)> = vec![];
+    candidates.extend(engine.vars.keys().chain(engine.deferred.keys().map(String::as_str)).map(|name| (name.to_string(), Kind::Variable)));
+    // After `=` or `const` only a variable name fits.
+    if !matches!(previous, Some("=" | "const")) {
+        candidates.extend(engine.functions.keys().map(|name| (name.clone(), Kind::Function)));
+        for op in OPERATORS.iter().filter(|op| op.parse.is_some() || op.position == Position::Prefix) {
+            match op.position {
+                Position::Postfix => candidates.extend(core::iter::once(op.name).chain(op.aliases.iter().copied()).map(|name| (name.to_string(), Kind::Operator))),
+                Position::Prefix if first => candidates.push((op.name.to_string(), Kind::Keyword)),
+                Position::Prefix => {}
+            }
+        }
+        candidates.extend(constants::names().map(|name| (format!("{}{}", constants::PREFIX, name), Kind::Constant)));
+        candidates.extend(units::names().map(|unit| (format!("to-{}", unit), Kind::Unit)));
+    }
+
+    let mut completions: Vec<_> = candidates
+        .into_iter()
+        .filter(|(text, _)| text.starts_with(word))
+        .map(|(text, kind)| Completion { text, kind })
+        .collect();
+    completions.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.text.cmp(&b.text)));
+    completions
+}
@@ -0,0 +1,271 @@
+// `load-config Cargo.toml package.metadata.budget` reads a number out of a project config
+// file. TOML and YAML documents are read into the same tree as JSON ones, so one selector
+// syntax covers all three. Only the common subset of each format is understood: enough for
+// config files, not for every document the specifications allow.
+use crate::json::{self, Json};
+use crate::prelude::*;
+use crate::EngineError;
+
+fn invalid(line: usize, what: &str) -> EngineError {
+    EngineError::InvalidConfig(format!("line {}: {}", line + 1, what))
+}
+
+// Picks the format from the file extension.
+pub fn parse(path: &str, text: &str) -> Result<Json, EngineError> {
+    match path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()).as_deref() {
+        Some("toml") => toml(text),
+        Some("yaml" | "yml") => yaml(text),
+        Some("json") => json::parse(text),
+        _ => Err(EngineError::InvalidConfig(format!("{} is not a .toml, .yaml or .json file", path))),
+    }
+}
+
+// The text before a `#` that isn't inside quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (at, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('#', None) => return &line[..at],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Splits on `separator` outside quotes and brackets.
+fn split_top(text: &str, separator: char) -> Vec<&str> {
+    let (mut parts, mut start, mut depth, mut quote) = (vec![], 0, 0, None);
+    for (at, c) in text.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('[' | '{', None) => depth += 1,
+            (']' | '}', None) => depth -= 1,
+            (c, None) if c == separator && depth == 0 => {
+                parts.push(&text[start..at]);
+                start = at + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn unquote(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.len() >= 2 && (text.starts_with('"') && text.ends_with('"') || text.starts_with('\'') && text.ends_with('\'')) {
+        let inner = &text[1..text.len() - 1];
+        return Some(match text.starts_with('"') {
+            true => inner.replace("\\\"", "\"").replace("\\n", "\n").replace("\\t", "\t").replace("\\\\", "\\"),
+            false => inner.to_string(),
+        });
+    }
+    None
+}
+
+fn keys(text: &str) -> Vec<String> {
+    split_top(text, '.').into_iter().map(|key| unquote(key).unwrap_or_else(|| key.trim().to_string())).collect()
+}
+
+// Scalars both formats share; anything else, like a date, is kept as its text.
+fn scalar(text: &str) -> Json {
+    let text = text.trim();
+    if let Some(string) = unquote(text) {
+        return Json::String(string);
+    }
+    match text {
+        "true" => Json::Bool(true),
+        "false" => Json::Bool(false),
+        "null" | "~" | "" => Json::Null,
+        _ => match text.replace('_', "").parse() {
+            Ok(n) if text.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) => Json::Number(n),
+            _ => Json::String(text.to_string()),
+        },
+    }
+}
+
+// A value on the right of `=` or `:`, with inline arrays (and TOML inline tables).
+fn flow(text: &str) -> Result<Json, String> {
+    let text = text.trim();
+    if let Some(inner) = text.strip_prefix('[') {
+        let inner = inner.strip_suffix(']').ok_or("unterminated array")?;
+        let items = split_top(inner, ',').into_iter().filter(|item| !item.trim().is_empty());
+        return items.map(flow).collect::<Result<_, _>>().map(Json::Array);
+    }
+    if let Some(inner) = text.strip_prefix('{') {
+        let inner = inner.strip_suffix('}').ok_or("unterminated table")?;
+        let mut table = Json::Object(vec![]);
+        for field in split_top(inner, ',').into_iter().filter(|field| !field.trim().is_empty()) {
+            let [key, value] = split_top(field, '=')[..] else { return Err("expected key = value".into()) };
+            insert(&mut table, &keys(key), flow(value)?)?;
+        }
+        return Ok(table);
+    }
+    Ok(scalar(text))
+}
+
+// The object at `path`, creating tables on the way; an array of tables means its last one.
+fn table<'a>(mut node: &'a mut Json, path: &[String]) -> Result<&'a mut Vec<(String, Json)>, String> {
+    for key in path {
+        let Json::Object(fields) = node else { return Err(format!("{} is not a table", key)) };
+        let index = match fields.iter().position(|(k, _)| k == key) {
+            Some(index) => index,
+            None => {
+                fields.push((key.clone(), Json::Object(vec![])));
+                fields.len() - 1
+            }
+        };
+        node = &mut fields[index].1;
+        if let Json::Array(items) = node {
+            node = items.last_mut().ok_or_else(|| format!("{} is an empty array", key))?;
+        }
+    }
+    match node {
+        Json::Object(fields) => Ok(fields),
+        _ => Err("not a table".into()),
+    }
+}
+
+fn insert(root: &mut Json, path: &[String], value: Json) -> Result<(), String> {
+    let (last, parents) = path.split_last().ok_or("empty key")?;
+    let fields = table(root, parents)?;
+    if fields.iter().any(|(k, _)| k == last) {
+        return Err(format!("{} is defined twice", last));
+    }
+    fields.push((last.clone(), value));
+    Ok(())
+}
+
+fn toml(text: &str) -> Result<Json, EngineError> {
+    let mut root = Json::Object(vec![]);
+    let mut current: Vec<String> = vec![];
+    let mut lines = text.lines().map(strip_comment).enumerate();
+    while let Some((number, line)) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix("[[").and_then(|line| line.strip_suffix("]]")) {
+            current = keys(header);
+            let (last, parents) = current.split_last().ok_or_else(|| invalid(number, "empty table name"))?;
+            let fields = table(&mut root, parents).map_err(|err| invalid(number, &err))?;
+            match fields.iter_mut().find(|(k, _)| k == last) {
+                Some((_, Json::Array(items))) => items.push(Json::Object(vec![])),
+                Some(_) => return Err(invalid(number, &format!("{} is not an array of tables", last))),
+                None => fields.push((last.clone(), Json::Array(vec![Json::Object(vec![])]))),
+            }
+        } else if let Some(header) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            current = keys(header);
+            table(&mut root, &current).map_err(|err| invalid(number, &err))?;
+        } else {
+            let (key, value) = line.split_once('=').ok_or_else(|| invalid(number, "expected key = value"))?;
+            let mut value = value.trim().to_string();
+            // An array may run over several lines until its brackets balance.
+            while value.starts_with('[') && value.matches('[').count() > value.matches(']').count() {
+                let (_, next) = lines.next().ok_or_else(|| invalid(number, "unterminated array"))?;
+                value.push_str(next.trim());
+            }
+            let path: Vec<String> = current.iter().cloned().chain(keys(key)).collect();
+            insert(&mut root, &path, flow(&value).map_err(|err| invalid(number, &err))?).map_err(|err| invalid(number, &err))?;
+        }
+    }
+    Ok(root)
+}
+
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+fn yaml(text: &str) -> Result<Json, EngineError> {
+    let mut lines = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim_end();
+        let content = line.trim_start();
+        if content.is_empty() || content == "---" {
+            continue;
+        }
+        lines.push(Line { number, indent: line.len() - content.len(), text: content.to_string() });
+    }
+    if lines.is_empty() {
+        return Ok(Json::Null);
+    }
+    let mut position = 0;
+    let indent = lines[0].indent;
+    let document = block(&mut lines, &mut position, indent)?;
+    match lines.get(position) {
+        Some(line) => Err(invalid(line.number, "unexpected indentation")),
+        None => Ok(document),
+    }
+}
+
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+// The mapping or sequence whose entries start at `indent`.
+fn block(lines: &mut [Line], position: &mut usize, indent: usize) -> Result<Json, EngineError> {
+    if is_item(&lines[*position].text) {
+        let mut items = vec![];
+        while lines.get(*position).is_some_and(|line| line.indent == indent && is_item(&line.text)) {
+            let line = &mut lines[*position];
+            let rest = line.text[1..].trim_start().to_string();
+            if rest.is_empty() {
+                *position += 1;
+                items.push(nested(lines, position, indent)?);
+            } else if rest.starts_with('[') || unquote(&rest).is_some() || !rest.contains(": ") && !rest.ends_with(':') {
+                items.push(flow(&rest).map_err(|err| invalid(line.number, &err))?);
+                *position += 1;
+            } else {
+                // `- key: value` starts a mapping indented to where the key is.
+                line.indent += line.text.len() - rest.len();
+                line.text = rest;
+                let indent = line.indent;
+                items.push(block(lines, position, indent)?);
+            }
+        }
+        return Ok(Json::Array(items));
+    }
+
+    let mut fields = vec![];
+    while lines.get(*position).is_some_and(|line| line.indent == indent && !is_item(&line.text)) {
+        let line = &lines[*position];
+        let (key, value) = match split_top(&line.text, ':').as_slice() {
+            [key, value @ ..] if !value.is_empty() => (unquote(key).unwrap_or_else(|| key.trim().to_string()), value.join(":")),
+            _ => return Err(invalid(line.number, "expected key: value")),
+        };
+        let number = line.number;
+        *position += 1;
+        let value = match value.trim() {
+            "" => nested(lines, position, indent)?,
+            value => flow(value).map_err(|err| invalid(number, &err))?,
+        };
+        if fields.iter().any(|(k, _)| *k == key) {
+            return Err(invalid(number, &format!("{} is defined twice", key)));
+        }
+        fields.push((key, value));
+    }
+    Ok(Json::Object(fields))
+}
+
+// The value under a key or `-` with nothing after it: a deeper block, a sequence at the same
+// indentation as a key, or null.
+fn nested(lines: &mut [Line], position: &mut usize, indent: usize) -> Result<Json, EngineError> {
+    match lines.get(*position) {
+        Some(line) if line.indent > indent || line.indent == indent && is_item(&line.text) && !is_item_parent(lines, *position) => {
+            let indent = line.indent;
+            block(lines, position, indent)
+        }
+        _ => Ok(Json::Null),
+    }
+}
+
+// Whether the line before `position` was itself a `-` item, in which case a `-` at the same
+// indentation is its sibling rather than its value.
+fn is_item_parent(lines: &[Line], position: usize) -> bool {
+    position > 0 && lines[position - 1].indent == lines[position].indent && is_item(&lines[position - 1].text)
+}
@@ -0,0 +1,89 @@
+// The canonical behaviour of the language as (program, result) cases, for front-ends that
+// embed the evaluator some other way, like WASM or FFI bindings, to check themselves against.
+// `qqc conformance` runs them on this build, or prints them as JSON lines for another one.
+use crate::logging::json_string;
+use crate::prelude::*;
+use crate::{parse, Evaluator};
+
+pub struct Case {
+    pub name: &'static str,
+    pub program: &'static str,
+    // The answer as `qqc` prints it, or the code of the error the program fails with.
+    pub expected: Result<&'static str, &'static str>,
+}
+
+const fn answer(name: &'static str, program: &'static str, answer: &'static str) -> Case {
+    Case { name, program, expected: Ok(answer) }
+}
+
+const fn error(name: &'static str, program: &'static str, code: &'static str) -> Case {
+    Case { name, program, expected: Err(code) }
+}
+
+pub const CASES: &[Case] = &[
+    answer("chaining", "5 12 66 *\n15 -\n5 +", "3950"),
+    answer("divide", "7 2 /", "3.5"),
+    answer("power", "2 10 ^", "1024"),
+    answer("modulo", "17 5 %", "2"),
+    answer("infinity", "1 0 /", "inf"),
+    answer("sqrt", "16 sqrt", "4"),
+    answer("root", "27 3 root", "3"),
+    answer("odd-root", "-8 3 root", "-2"),
+    answer("degrees", "deg\n90 sin", "1"),
+    answer("logn", "81 3 logn", "4"),
+    answer("compare", "3 4 <", "true"),
+    answer("equal", "2 2 ==", "true"),
+    answer("sort", "3 1 2 sort", "[1, 2, 3]"),
+    answer("mean", "1 2 3 4 mean", "2.5"),
+    answer("min", "2 4 8 16 min", "2"),
+    answer("isprime", "91 isprime", "false"),
+    answer("factor", "360 factor", "[2, 2, 2, 3, 3, 5]"),
+    answer("choose", "5 2 choose", "10"),
+    answer("variable", "2 3 +\n= a\na a *", "25"),
+    answer("function", "def sq n\nn n *\nend\n7 sq", "49"),
+    answer("defer", "2 3 +\n= p\ndefer t \"p 4 *\"\n1 t +", "21"),
+    answer("try", "try\nerror \"no\"\nrecover\n5 1 +\nend", "6"),
+    error("missing-variable", "price 2 *", "missing-variable"),
+    error("unknown-command", "1 2 3 pluss", "unknown-command"),
+    error("operand-count", "10 km", "operand-count"),
+    error("constant", "10 0 +\n= rate const\n= rate", "constant-reassigned"),
+    error("even-root", "-4 sqrt", "out-of-range"),
+    error("name-taken", "def sum a b\na b +\nend", "name-taken"),
+];
+
+pub struct Failure {
+    pub name: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+// Runs every case through `run`, which returns the printed answer or an error code.
+pub fn check(mut run: impl FnMut(&str) -> Result<String, String>) -> Vec<Failure> {
+    let shown = |result: Result<&str, &str>| match result {
+        Ok(answer) => answer.to_string(),
+        Err(code) => format!("error {}", code),
+    };
+    CASES
+        .iter()
+        .filter_map(|case| {
+            let actual = run(case.program);
+            let actual = actual.as_deref().map_err(String::as_str);
+            (actual != case.expected).then(|| Failure { name: case.name, expected: shown(case.expected), actual: shown(actual) })
+        })
+        .collect()
+}
+
+// This build's own front-end: a fresh evaluator per program.
+pub fn evaluate(program: &str) -> Result<String, String> {
+    let mut engine = Evaluator::new();
+    let answer = parse(program).and_then(|commands| engine.evaluate(&commands));
+    answer.and_then(|outcome| engine.format(&outcome.value)).map_err(|err| err.code().to_string())
+}
+
+pub fn to_json(case: &Case) -> String {
+    let (key, value) = match case.expected {
+        Ok(answer) => ("answer", answer),
+        Err(code) => ("error", code),
+    };
+    format!("{{\"name\":{},\"program\":{},\"{}\":{}}}", json_string(case.name), json_string(case.program), key, json_string(value))
+}
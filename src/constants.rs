@@ -0,0 +1,32 @@
+// Physical constants in SI units, from CODATA 2018.
+const CONSTANTS: &[(&str, f64)] = &[
+    ("c", 299792458.0),
+    ("G", 6.67430e-11),
+    ("h", 6.62607015e-34),
+    ("hbar", 1.054571817e-34),
+    ("k_B", 1.380649e-23),
+    ("N_A", 6.02214076e23),
+    ("R", 8.314462618),
+    ("e", 1.602176634e-19),
+    ("m_e", 9.1093837015e-31),
+    ("m_p", 1.67262192369e-27),
+    ("m_n", 1.67492749804e-27),
+    ("epsilon_0", 8.8541878128e-12),
+    ("mu_0", 1.25663706212e-6),
+    ("sigma", 5.670374419e-8),
+    ("g", 9.80665),
+    ("atm", 101325.0),
+];
+
+pub const PREFIX: &str = "const.";
+
+// The value of `const.<name>`, matched case-sensitively since `g` and `G` differ.
+pub fn lookup(name: &str) -> Option<f64> {
+    let name = name.strip_prefix(PREFIX)?;
+    CONSTANTS.iter().find(|(symbol, _)| *symbol == name).map(|(_, value)| *value)
+}
+
+// Every constant name, without the prefix.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    CONSTANTS.iter().map(|(symbol, _)| *symbol)
+}
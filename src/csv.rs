@@ -0,0 +1,101 @@
+// A CSV file loaded with `load-csv`, read through spreadsheet references like `A1` or `B3:B10`.
+use crate::prelude::*;
+use crate::{EngineError, Value};
+
+pub struct Sheet {
+    rows: Vec<Vec<String>>,
+}
+
+// Splits CSV text into rows of fields, with quoted fields and doubled quotes inside them.
+pub fn parse(text: &str) -> Sheet {
+    let mut rows = vec![];
+    let (mut row, mut field) = (vec![], String::new());
+    let (mut quoted, mut chars) = (false, text.chars().peekable());
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(core::mem::take(&mut field)),
+            ('\n', false) => {
+                row.push(core::mem::take(&mut field));
+                rows.push(core::mem::take(&mut row));
+            }
+            ('\r', false) => {}
+            (c, _) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Sheet { rows }
+}
+
+// `B3` as a zero-based (column, row); columns run A to Z, then AA, AB, …
+pub fn cell(reference: &str) -> Option<(usize, usize)> {
+    let split = reference.find(|c: char| !c.is_ascii_uppercase())?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let column = letters.bytes().try_fold(0usize, |n, b| n.checked_mul(26)?.checked_add((b - b'A' + 1) as usize))?;
+    let row = digits.parse::<usize>().ok().filter(|row| *row > 0)?;
+    Some((column - 1, row - 1))
+}
+
+// Whether a token is a cell or a range, the only names a sheet answers to.
+pub fn is_reference(token: &str) -> bool {
+    match token.split_once(':') {
+        Some((from, to)) => cell(from).is_some() && cell(to).is_some(),
+        None => cell(token).is_some(),
+    }
+}
+
+impl Sheet {
+    #[cfg(feature = "xlsx")]
+    pub fn from_rows(rows: Vec<Vec<String>>) -> Sheet {
+        Sheet { rows }
+    }
+
+    fn number(&self, (column, row): (usize, usize)) -> Result<Option<f64>, EngineError> {
+        let text = self.rows.get(row).and_then(|cells| cells.get(column)).map_or("", |text| text.trim());
+        if text.is_empty() {
+            return Ok(None);
+        }
+        let name = || format!("{}{}", column_name(column), row + 1);
+        text.parse().map(Some).map_err(|_| EngineError::InvalidCell(name()))
+    }
+
+    // A cell as a number, or a range as the list of its numbers row by row, skipping empty cells.
+    pub fn lookup(&self, reference: &str) -> Result<Value, EngineError> {
+        let missing = || EngineError::MissingVariable(reference.into());
+        match reference.split_once(':') {
+            None => self.number(cell(reference).ok_or_else(missing)?)?.map(Value::Operand).ok_or_else(missing),
+            Some((from, to)) => {
+                let ((c1, r1), (c2, r2)) = (cell(from).ok_or_else(missing)?, cell(to).ok_or_else(missing)?);
+                let mut values = vec![];
+                for row in r1.min(r2)..=r1.max(r2) {
+                    for column in c1.min(c2)..=c1.max(c2) {
+                        values.extend(self.number((column, row))?);
+                    }
+                }
+                Ok(Value::List(values))
+            }
+        }
+    }
+}
+
+fn column_name(mut column: usize) -> String {
+    let mut name = vec![];
+    loop {
+        name.insert(0, b'A' + (column % 26) as u8);
+        if column < 26 {
+            break;
+        }
+        column = column / 26 - 1;
+    }
+    String::from_utf8(name).unwrap()
+}
@@ -0,0 +1,53 @@
+use crate::logging::json_string;
+
+// One finding about a script, shown as `severity: message` or as a JSON record.
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub severity: &'static str,
+    pub code: &'static str,
+    pub message: String,
+    pub suggestion: Option<String>,
+    pub included_from: Vec<String>, // `file:line` of each include leading to the error, innermost first
+}
+
+impl Diagnostic {
+    // Notes, like the steps of `--explain`, trace a run across its files, so they always name theirs.
+    pub fn to_text(&self) -> String {
+        match self.included_from.is_empty() && self.severity != "note" {
+            true => self.unlocated_text(),
+            false => self.to_located_text(),
+        }
+    }
+
+    // Names the file whenever it is known, for reports that cover many files.
+    pub fn to_located_text(&self) -> String {
+        let (Some(file), Some(line)) = (&self.file, self.line) else { return self.unlocated_text() };
+        let chain: Vec<_> = self.included_from.iter().map(|at| format!(", included from {}", at)).collect();
+        format!("{} at {}:{}{}: {}", self.severity, file, line, chain.concat(), self.message)
+    }
+
+    fn unlocated_text(&self) -> String {
+        match self.line {
+            Some(line) => format!("{}: line {}: {}", self.severity, line, self.message),
+            None => format!("{}: {}", self.severity, self.message),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let string = |s: &Option<String>| s.as_deref().map_or("null".to_string(), json_string);
+        let number = |n: Option<usize>| n.map_or("null".to_string(), |n| n.to_string());
+        format!(
+            "{{\"file\":{},\"line\":{},\"column\":{},\"severity\":{},\"code\":{},\"message\":{},\"suggestion\":{},\"included_from\":[{}]}}",
+            string(&self.file),
+            number(self.line),
+            number(self.column),
+            json_string(self.severity),
+            json_string(self.code),
+            json_string(&self.message),
+            string(&self.suggestion),
+            self.included_from.iter().map(|at| json_string(at)).collect::<Vec<_>>().join(","),
+        )
+    }
+}
@@ -0,0 +1,126 @@
+// `qqc doctor [dir]` checks a repository of worksheets before anyone runs them: every `.qq`
+// and `.qqc` file below the directory must parse, the data files they load must read and hold
+// what they select, the operator table must not give one name two meanings, and the external
+// tools of the compiled-in features must be installed.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::operators::OPERATORS;
+use crate::source::Source;
+use crate::{error_diagnostic, warning_diagnostic, watch, Command, Diagnostic, EngineError, Evaluator};
+
+pub const EXTENSIONS: &[&str] = &["qq", "qqc"];
+
+// Worksheets below `dir` in path order, skipping hidden directories and `target`. A file is
+// taken as the only worksheet.
+pub fn worksheets(dir: &Path) -> Result<Vec<PathBuf>, EngineError> {
+    if dir.is_file() {
+        return Ok(vec![dir.to_path_buf()]);
+    }
+    let mut found = vec![];
+    let entries = std::fs::read_dir(dir).map_err(|_| EngineError::CannotRead(dir.display().to_string()))?;
+    let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                found.extend(worksheets(&path)?);
+            }
+        } else if path.extension().is_some_and(|extension| EXTENSIONS.iter().any(|e| extension == *e)) {
+            found.push(path);
+        }
+    }
+    Ok(found)
+}
+
+pub struct Report {
+    pub problems: Vec<Diagnostic>,
+    pub worksheets: usize,
+    pub data_files: usize,
+}
+
+pub fn check(dir: &Path) -> Result<Report, EngineError> {
+    let mut report = Report { problems: operator_names(), worksheets: 0, data_files: 0 };
+    report.problems.extend(tools());
+    for path in worksheets(dir)? {
+        report.worksheets += 1;
+        check_worksheet(&path.to_string_lossy(), &mut report);
+    }
+    Ok(report)
+}
+
+fn check_worksheet(file: &str, report: &mut Report) {
+    let mut source = Source::default();
+    let statements = source.load(file).and_then(|_| watch::statements(&source.text));
+    let statements = match statements {
+        Ok(statements) => statements,
+        Err(err) => return report.problems.push(error_diagnostic(&err, Some(file), &source)),
+    };
+    for statement in statements {
+        let mut loads = vec![];
+        data_loads(&statement.commands, &mut loads);
+        for load in loads {
+            report.data_files += 1;
+            // Loading on a fresh evaluator reads and selects exactly as a run would.
+            if let Err(err) = Evaluator::new().evaluate_commands(std::slice::from_ref(load)) {
+                report.problems.push(error_diagnostic(&err.at_line(statement.first), Some(file), &source));
+            }
+        }
+    }
+}
+
+// Commands that read a local file, including those inside blocks and functions.
+fn data_loads<'a>(commands: &'a [Command], loads: &mut Vec<&'a Command>) {
+    for command in commands {
+        match command {
+            Command::LoadCsv(_) | Command::LoadJson(..) | Command::LoadConfig(..) => loads.push(command),
+            #[cfg(feature = "xlsx")]
+            Command::LoadXlsx(..) => loads.push(command),
+            Command::Try(body, recover) => {
+                data_loads(body, loads);
+                data_loads(recover, loads);
+            }
+            Command::Define(_, function) => data_loads(&function.body, loads),
+            _ => {}
+        }
+    }
+}
+
+// Every operator name and alias must mean one operator only.
+fn operator_names() -> Vec<Diagnostic> {
+    let mut owners: HashMap<&str, &str> = HashMap::new();
+    let mut problems = vec![];
+    for op in OPERATORS {
+        for token in std::iter::once(&op.name).chain(op.aliases) {
+            match owners.insert(token, op.name) {
+                Some(owner) if owner != op.name => {
+                    problems.push(warning_diagnostic(&format!("`{}` names both `{}` and `{}`", token, owner, op.name), None));
+                }
+                _ => {}
+            }
+        }
+    }
+    problems
+}
+
+// Compiled-in features that hand work to another program need it installed.
+fn tools() -> Vec<Diagnostic> {
+    #[allow(unused_mut)] // Empty without the `net` and `clipboard` features
+    let mut problems = vec![];
+    #[cfg(feature = "net")]
+    if !on_path("curl") {
+        problems.push(warning_diagnostic("curl is not installed, so `fetch` will fail", None));
+    }
+    #[cfg(feature = "clipboard")]
+    if !crate::clipboard::backends(std::env::var_os("WAYLAND_DISPLAY").is_some()).iter().any(|backend| on_path(backend[0])) {
+        problems.push(warning_diagnostic("no clipboard tool is installed, so `--copy` will fail", None));
+    }
+    problems
+}
+
+#[cfg_attr(not(any(feature = "net", feature = "clipboard")), allow(dead_code))]
+fn on_path(program: &str) -> bool {
+    let names = [program.to_string(), format!("{}.exe", program)];
+    std::env::var_os("PATH").is_some_and(|path| std::env::split_paths(&path).any(|dir| names.iter().any(|name| dir.join(name).is_file())))
+}
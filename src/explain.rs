@@ -0,0 +1,72 @@
+// `--explain` shows how every line got its result: the accumulator the line folds in, each
+// variable with the value it had, and the result, like `(ans:10) + 5 + derp(3) = 18`.
+use crate::{describe, evaluate_statements, watch, Command, EngineError, Evaluator, Value};
+
+pub struct Step {
+    pub line: usize, // The first line of the statement
+    pub text: String,
+}
+
+// Evaluates a program a statement at a time, as `Evaluator::evaluate` would, keeping an
+// explanation of each. Statements that ran before an error keep theirs.
+pub fn evaluate(engine: &mut Evaluator, statements: Vec<watch::Parsed>, steps: &mut Vec<Step>) -> Result<Value, EngineError> {
+    evaluate_statements(engine, statements, |engine, statement| {
+        let written = statement.text.lines().last().unwrap_or_default();
+        let expression = match &mut statement.commands[..] {
+            [command] => expression(engine, command, written),
+            _ => None,
+        };
+        let answer = engine.evaluate_commands(&statement.commands)?;
+        let expression = expression.unwrap_or_else(|| statement.text.lines().next().unwrap_or_default().trim().to_string());
+        let text = match (&statement.commands[0], &answer) {
+            (Command::SetVar(_) | Command::Const(..), _) => expression,
+            (_, Value::Nothing) => expression,
+            (_, answer) => format!("{} = {}", expression, shown(engine, answer)),
+        };
+        steps.push(Step { line: statement.first, text });
+        Ok(answer)
+    })
+}
+
+// The command written out with the accumulator and variables it reads, before it runs.
+fn expression(engine: &Evaluator, command: &mut Command, written: &str) -> Option<String> {
+    let accumulator = match &engine.answer {
+        Value::Nothing => None,
+        answer => Some(format!("(ans:{})", shown(engine, answer))),
+    };
+    let symbol = match command {
+        Command::SetVar(name) | Command::Const(name, _) => return Some(format!("{} = {}", name, accumulator?)),
+        Command::Add(_) => Some("+"),
+        Command::Subtract(_) => Some("-"),
+        Command::Multiply(_) => Some("*"),
+        Command::Divide(_) => Some("/"),
+        Command::Power(_) => Some("^"),
+        Command::Modulo(_) => Some("%"),
+        _ => None,
+    };
+    let name = match command {
+        Command::Call(name, _) => name.clone(),
+        _ => written.split_whitespace().take_while(|token| !token.starts_with('#')).last()?.to_string(),
+    };
+    let operands = command.operands_mut()?;
+    let parts: Vec<_> = accumulator.into_iter().chain(operands.iter().map(|operand| operand_text(engine, operand))).collect();
+    Some(match symbol {
+        Some(symbol) => parts.join(&format!(" {} ", symbol)),
+        None => format!("{}({})", name, parts.join(", ")),
+    })
+}
+
+// A literal as written, a variable as `name(value)`.
+fn operand_text(engine: &Evaluator, operand: &Value) -> String {
+    match operand {
+        Value::Variable(name) => match engine.substitute(operand.clone()) {
+            Ok(Value::Variable(_)) | Err(_) => name.to_string(),
+            Ok(value) => format!("{}({})", name, shown(engine, &value)),
+        },
+        operand => shown(engine, operand),
+    }
+}
+
+fn shown(engine: &Evaluator, value: &Value) -> String {
+    engine.format(value).unwrap_or_else(|_| describe(value))
+}
@@ -0,0 +1,40 @@
+// Reductions over long runs of plain numbers. The values are folded in LANES independent
+// accumulators that the compiler turns into SIMD adds and multiplies, then the lanes are
+// combined in a fixed order, so the result does not depend on the CPU it runs on.
+pub const LANES: usize = 8;
+
+// Below this many values the plain left-to-right fold is used, keeping short lines exact
+// to the order they were written in.
+pub const THRESHOLD: usize = 64;
+
+fn lanes(values: &[f64], identity: f64, op: impl Fn(f64, f64) -> f64) -> f64 {
+    let mut acc = [identity; LANES];
+    let chunks = values.chunks_exact(LANES);
+    let rest = chunks.remainder();
+    for chunk in chunks {
+        for (lane, x) in acc.iter_mut().zip(chunk) {
+            *lane = op(*lane, *x);
+        }
+    }
+    let mut result = acc.into_iter().reduce(&op).unwrap_or(identity);
+    for x in rest {
+        result = op(result, *x);
+    }
+    result
+}
+
+pub fn sum(values: &[f64]) -> f64 {
+    lanes(values, 0.0, |a, b| a + b)
+}
+
+pub fn product(values: &[f64]) -> f64 {
+    lanes(values, 1.0, |a, b| a * b)
+}
+
+pub fn min(values: &[f64]) -> f64 {
+    lanes(values, f64::INFINITY, f64::min)
+}
+
+pub fn max(values: &[f64]) -> f64 {
+    lanes(values, f64::NEG_INFINITY, f64::max)
+}
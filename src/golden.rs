@@ -0,0 +1,90 @@
+// `qqc test [dir]` turns worksheets into a regression suite: every `expect` and `assert` in
+// every worksheet below the directory is checked, and each file reports what passed and, for
+// what failed, the expected value next to the actual one.
+use std::path::Path;
+
+use crate::{doctor, evaluate_statements, watch, Check, EngineError, Evaluator, Source};
+
+pub enum Problem {
+    Check(Check),
+    Error(String), // The worksheet stopped here
+}
+
+pub struct Failure {
+    pub place: String, // `file:line` of the statement
+    pub problem: Problem,
+}
+
+pub struct FileResult {
+    pub file: String,
+    pub passed: usize,
+    pub failures: Vec<Failure>,
+}
+
+// Runs every worksheet below `dir`, each from a copy of `base`.
+pub fn run(dir: &Path, base: &Evaluator) -> Result<Vec<FileResult>, EngineError> {
+    doctor::worksheets(dir)?.iter().map(|path| Ok(check(&path.to_string_lossy(), base.clone()))).collect()
+}
+
+// Evaluates one worksheet a statement at a time, so each check knows its line. An error
+// ends the file as one more failure.
+pub fn check(file: &str, mut engine: Evaluator) -> FileResult {
+    let mut result = FileResult { file: file.to_string(), passed: 0, failures: vec![] };
+    let mut source = Source::default();
+    let statements = match source.load(file).and_then(|_| watch::statements(&source.text)) {
+        Ok(statements) => statements,
+        Err(err) => {
+            let place = place(&source, file, err.line());
+            result.failures.push(Failure { place, problem: Problem::Error(err.root().to_string()) });
+            return result;
+        }
+    };
+    let mut first = None; // The first line of the statement running
+    let outcome = evaluate_statements(&mut engine, statements, |engine, statement| {
+        first = Some(statement.first);
+        let outcome = engine.evaluate_commands(&statement.commands);
+        for check in engine.checks.drain(..) {
+            match check.passed {
+                true => result.passed += 1,
+                false => result.failures.push(Failure { place: place(&source, file, first), problem: Problem::Check(check) }),
+            }
+        }
+        outcome
+    });
+    if let Err(err) = outcome {
+        result.failures.push(Failure { place: place(&source, file, first), problem: Problem::Error(err.root().to_string()) });
+    }
+    result
+}
+
+fn place(source: &Source, file: &str, line: Option<usize>) -> String {
+    match line.map(|line| (line, source.origin(line))) {
+        Some((_, Some(origin))) => format!("{}:{}", origin.file, origin.line),
+        Some((line, None)) => format!("{}:{}", file, line),
+        None => file.to_string(),
+    }
+}
+
+// A line per file, each failure with its expected and actual values, then the totals, where an
+// error that stopped a file counts as one failed check.
+pub fn report(results: &[FileResult]) -> Vec<String> {
+    let mut lines = vec![];
+    for result in results {
+        let status = if result.failures.is_empty() { "PASS" } else { "FAIL" };
+        lines.push(format!("{} {}: {} passed, {} failed", status, result.file, result.passed, result.failures.len()));
+        for failure in &result.failures {
+            match &failure.problem {
+                Problem::Check(check) => {
+                    lines.push(format!("  {}: {}", failure.place, check.directive));
+                    lines.push(format!("    expected: {}", check.expected));
+                    lines.push(format!("    actual:   {}", check.actual));
+                }
+                Problem::Error(error) => lines.push(format!("  {}: error: {}", failure.place, error)),
+            }
+        }
+    }
+    let passed: usize = results.iter().map(|result| result.passed).sum();
+    let failed: usize = results.iter().map(|result| result.failures.len()).sum();
+    lines.push(format!("{} files, {} checks: {} passed, {} failed", results.len(), passed + failed, passed, failed));
+    lines
+}
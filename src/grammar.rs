@@ -0,0 +1,92 @@
+use crate::logging::json_string;
+use crate::operators::{Position, OPERATORS};
+use crate::{units, EngineError};
+
+// Words that only appear inside blocks, so they have no entry of their own.
+const BLOCK_KEYWORDS: &[&str] = &["end", "recover"];
+
+const NUMBER: &str = r"-?[0-9][0-9_]*(\.[0-9]+)?([eE][-+]?[0-9]+)?";
+const COMMENT: &str = "#.*";
+
+// Every token of an operator in the table, with `to-<unit>` spelled out per unit.
+fn tokens(position: Position) -> Vec<String> {
+    let mut tokens = vec![];
+    for op in OPERATORS.iter().filter(|op| op.position == position) {
+        if op.name == "to-<unit>" {
+            tokens.extend(units::names().map(|unit| format!("to-{}", unit.to_lowercase())));
+            continue;
+        }
+        tokens.push(op.name.to_string());
+        tokens.extend(op.aliases.iter().map(|alias| alias.to_string()));
+    }
+    if position == Position::Prefix {
+        tokens.extend(BLOCK_KEYWORDS.iter().map(|word| word.to_string()));
+    }
+    // Longest first, so `to-gib` is not taken for `to-g` followed by junk.
+    tokens.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    tokens.dedup();
+    tokens
+}
+
+fn escape_regex(token: &str) -> String {
+    token.chars().map(|c| if "\\^$.|?*+()[]{}/".contains(c) { format!("\\{}", c) } else { c.to_string() }).collect()
+}
+
+// A token standing alone between whitespace or the line ends.
+fn alternation(tokens: &[String]) -> String {
+    let tokens: Vec<_> = tokens.iter().map(|token| escape_regex(token)).collect();
+    format!("(?<=^|\\s)({})(?=\\s|$)", tokens.join("|"))
+}
+
+fn rule(name: &str, pattern: &str) -> String {
+    format!("{{\"name\":{},\"match\":{}}}", json_string(name), json_string(pattern))
+}
+
+pub fn textmate() -> String {
+    let patterns = [
+        rule("comment.line.number-sign.qqc", COMMENT),
+        rule("keyword.control.qqc", &alternation(&tokens(Position::Prefix))),
+        rule("keyword.operator.qqc", &alternation(&tokens(Position::Postfix))),
+        rule("constant.numeric.qqc", &format!("(?<=^|\\s){}(?=\\s|$)", NUMBER)),
+        rule("variable.other.qqc", r"[A-Za-z_$][A-Za-z0-9_.]*"),
+    ];
+    format!("{{\"name\":\"qqc\",\"scopeName\":\"source.qqc\",\"fileTypes\":[\"qq\"],\"patterns\":[{}]}}", patterns.join(","))
+}
+
+pub fn tree_sitter() -> String {
+    let choice = |tokens: Vec<String>| {
+        let tokens: Vec<_> = tokens.iter().map(|token| format!("'{}'", token.replace('\\', "\\\\").replace('\'', "\\'"))).collect();
+        format!("choice({})", tokens.join(", "))
+    };
+    format!(
+        "module.exports = grammar({{
+  name: 'qqc',
+  extras: $ => [/[ \\t]/],
+  word: $ => $.identifier,
+  rules: {{
+    program: $ => repeat(choice($.line, $.comment, '\\n')),
+    line: $ => seq(choice(seq($.keyword, repeat($._operand)), seq(repeat($._operand), $.operator)), optional($.comment), '\\n'),
+    _operand: $ => choice($.number, $.identifier),
+    keyword: $ => {},
+    operator: $ => {},
+    number: $ => /{}/,
+    identifier: $ => /[A-Za-z_$][A-Za-z0-9_.]*/,
+    comment: $ => /{}/,
+  }}
+}});
+",
+        choice(tokens(Position::Prefix)),
+        choice(tokens(Position::Postfix)),
+        NUMBER,
+        COMMENT
+    )
+}
+
+// The grammar in an editor format, named as on the command line.
+pub fn generate(format: &str) -> Result<String, EngineError> {
+    match format {
+        "textmate" => Ok(textmate()),
+        "tree-sitter" => Ok(tree_sitter()),
+        _ => Err(EngineError::UnknownOption(format.into())),
+    }
+}
@@ -0,0 +1,288 @@
+// Exact integer helpers for the number-theory commands.
+use crate::prelude::*;
+
+pub fn is_prime(n: u64) -> bool {
+    if n < 4 {
+        return n >= 2;
+    }
+    if n.is_multiple_of(2) || n.is_multiple_of(3) {
+        return false;
+    }
+    let mut d = 5;
+    while d * d <= n {
+        if n.is_multiple_of(d) || n.is_multiple_of(d + 2) {
+            return false;
+        }
+        d += 6;
+    }
+    true
+}
+
+// Prime factors in ascending order, repeated by multiplicity.
+pub fn factor(mut n: u64) -> Vec<u64> {
+    let mut factors = vec![];
+    for d in [2, 3] {
+        while n.is_multiple_of(d) && n > 1 {
+            factors.push(d);
+            n /= d;
+        }
+    }
+    let mut d = 5;
+    while d * d <= n {
+        for p in [d, d + 2] {
+            while n.is_multiple_of(p) {
+                factors.push(p);
+                n /= p;
+            }
+        }
+        d += 6;
+    }
+    if n > 1 {
+        factors.push(n);
+    }
+    factors
+}
+
+// An arbitrary-precision unsigned integer, stored as little-endian base 2^32 limbs
+// with no trailing zero limbs.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BigUint(Vec<u32>);
+
+impl BigUint {
+    pub fn from_u64(n: u64) -> BigUint {
+        let mut big = BigUint(vec![n as u32, (n >> 32) as u32]);
+        big.trim();
+        big
+    }
+
+    fn trim(&mut self) {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.0[..] {
+            [] => Some(0),
+            [lo] => Some(lo as u64),
+            [lo, hi] => Some(lo as u64 | (hi as u64) << 32),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.0.iter().rev().fold(0.0, |acc, limb| acc * 4294967296.0 + *limb as f64)
+    }
+
+    fn bits(&self) -> usize {
+        match self.0.last() {
+            None => 0,
+            Some(top) => self.0.len() * 32 - top.leading_zeros() as usize,
+        }
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.0.get(i / 32).is_some_and(|limb| limb >> (i % 32) & 1 == 1)
+    }
+
+    pub fn add(&self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![];
+        let mut carry = 0u64;
+        for i in 0..self.0.len().max(other.0.len()) {
+            let sum = *self.0.get(i).unwrap_or(&0) as u64 + *other.0.get(i).unwrap_or(&0) as u64 + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        let mut big = BigUint(limbs);
+        big.trim();
+        big
+    }
+
+    // `self - other`, which must not underflow.
+    fn sub(&self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![];
+        let mut borrow = 0i64;
+        for i in 0..self.0.len() {
+            let mut diff = self.0[i] as i64 - *other.0.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = (diff < 0) as i64;
+            if diff < 0 {
+                diff += 1 << 32;
+            }
+            limbs.push(diff as u32);
+        }
+        let mut big = BigUint(limbs);
+        big.trim();
+        big
+    }
+
+    pub fn mul(&self, other: &BigUint) -> BigUint {
+        let mut limbs = vec![0u32; self.0.len() + other.0.len()];
+        for (i, a) in self.0.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.0.iter().enumerate() {
+                let cur = limbs[i + j] as u64 + *a as u64 * *b as u64 + carry;
+                limbs[i + j] = cur as u32;
+                carry = cur >> 32;
+            }
+            limbs[i + other.0.len()] = carry as u32;
+        }
+        let mut big = BigUint(limbs);
+        big.trim();
+        big
+    }
+
+    pub fn mul_small(&self, n: u64) -> BigUint {
+        self.mul(&BigUint::from_u64(n))
+    }
+
+    // Division by a single limb, returning the quotient and remainder.
+    fn divrem_small(&self, d: u32) -> (BigUint, u32) {
+        let mut limbs = vec![0u32; self.0.len()];
+        let mut rem = 0u64;
+        for i in (0..self.0.len()).rev() {
+            let cur = rem << 32 | self.0[i] as u64;
+            limbs[i] = (cur / d as u64) as u32;
+            rem = cur % d as u64;
+        }
+        let mut big = BigUint(limbs);
+        big.trim();
+        (big, rem as u32)
+    }
+
+    pub fn div_small(&self, d: u64) -> BigUint {
+        self.divrem(&BigUint::from_u64(d)).0
+    }
+
+    // Binary long division; `d` must be non-zero.
+    pub fn divrem(&self, d: &BigUint) -> (BigUint, BigUint) {
+        if let (Some(n), Some(m)) = (self.to_u64(), d.to_u64()) {
+            return (BigUint::from_u64(n / m), BigUint::from_u64(n % m));
+        }
+        let mut quotient = BigUint(vec![0; self.0.len()]);
+        let mut rem = BigUint(vec![]);
+        for i in (0..self.bits()).rev() {
+            rem = rem.add(&rem);
+            if self.bit(i) {
+                rem = rem.add(&BigUint::from_u64(1));
+            }
+            if rem >= *d {
+                rem = rem.sub(d);
+                quotient.0[i / 32] |= 1 << (i % 32);
+            }
+        }
+        quotient.trim();
+        (quotient, rem)
+    }
+
+    // `self^exp mod modulus` by square-and-multiply; `modulus` must be non-zero.
+    pub fn pow_mod(&self, exp: &BigUint, modulus: &BigUint) -> BigUint {
+        let mut result = BigUint::from_u64(1).divrem(modulus).1;
+        let mut base = self.divrem(modulus).1;
+        for i in 0..exp.bits() {
+            if exp.bit(i) {
+                result = result.mul(&base).divrem(modulus).1;
+            }
+            base = base.mul(&base).divrem(modulus).1;
+        }
+        result
+    }
+}
+
+impl BigUint {
+    // Reads digits `0-9A-Z` (either case) in the given radix, 2 through 36.
+    pub fn from_radix(digits: &str, radix: u32) -> Option<BigUint> {
+        if digits.is_empty() {
+            return None;
+        }
+        digits.chars().try_fold(BigUint(vec![]), |acc, c| {
+            let digit = c.to_digit(radix)?;
+            Some(acc.mul_small(radix as u64).add(&BigUint::from_u64(digit as u64)))
+        })
+    }
+
+    pub fn to_radix(&self, radix: u32) -> String {
+        if self.is_zero() {
+            return "0".into();
+        }
+        let mut digits = vec![];
+        let mut n = self.clone();
+        while !n.is_zero() {
+            let (q, r) = n.divrem_small(radix);
+            digits.push(core::char::from_digit(r, radix).unwrap().to_ascii_uppercase());
+            n = q;
+        }
+        digits.iter().rev().collect()
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.0.len().cmp(&other.0.len()).then_with(|| self.0.iter().rev().cmp(other.0.iter().rev()))
+    }
+}
+
+impl core::fmt::Display for BigUint {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut chunks = vec![];
+        let mut n = self.clone();
+        while !n.is_zero() {
+            let (q, r) = n.divrem_small(1_000_000_000);
+            chunks.push(r);
+            n = q;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+// n! / (n - k)!, the ordered selections of k from n.
+pub fn permute(n: u64, k: u64) -> BigUint {
+    if k > n {
+        return BigUint::from_u64(0);
+    }
+    (n - k + 1..=n).fold(BigUint::from_u64(1), |acc, i| acc.mul_small(i))
+}
+
+// n! / (k! (n - k)!), building up C(n - k + i, i) so each division is exact.
+pub fn choose(n: u64, k: u64) -> BigUint {
+    if k > n {
+        return BigUint::from_u64(0);
+    }
+    let k = k.min(n - k);
+    (1..=k).fold(BigUint::from_u64(1), |acc, i| acc.mul_small(n - k + i).div_small(i))
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+// Reduces `num/den` to lowest terms with a positive denominator, if it fits in i64.
+pub fn reduce_ratio(num: i128, den: i128) -> Option<(i64, i64)> {
+    if den == 0 {
+        return None;
+    }
+    let g = gcd(num, den);
+    let sign = if den < 0 { -1 } else { 1 };
+    let (num, den) = (num.checked_mul(sign)? / g, den.checked_mul(sign)? / g);
+    Some((i64::try_from(num).ok()?, i64::try_from(den).ok()?))
+}
@@ -0,0 +1,87 @@
+// Ctrl-C in the REPL and `--watch`. The handler only sets a flag: the REPL drops the line or
+// block being typed and cancels a running evaluation between commands, and `--watch` leaves
+// its loop and exits normally. Everywhere else Ctrl-C keeps its default of ending the process.
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// Set while the REPL waits for a line, so the handler can start a fresh prompt.
+static READING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(all(feature = "std", unix))]
+mod platform {
+    use core::sync::atomic::Ordering;
+
+    const SIGINT: i32 = 2;
+    const PROMPT: &[u8] = b"\n> ";
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+        fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    }
+
+    extern "C" fn on_interrupt(_: i32) {
+        super::INTERRUPTED.store(true, Ordering::Relaxed);
+        if super::READING.load(Ordering::Relaxed) {
+            // SAFETY: write(2) is async-signal-safe and PROMPT is a static buffer.
+            unsafe { write(2, PROMPT.as_ptr(), PROMPT.len()) };
+        }
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only touches atomics and calls write(2).
+        unsafe { signal(SIGINT, on_interrupt) };
+    }
+}
+
+#[cfg(all(feature = "std", windows))]
+mod platform {
+    use core::sync::atomic::Ordering;
+
+    const CTRL_C_EVENT: u32 = 0;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: unsafe extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    unsafe extern "system" fn on_interrupt(event: u32) -> i32 {
+        if event != CTRL_C_EVENT {
+            return 0;
+        }
+        super::INTERRUPTED.store(true, Ordering::Relaxed);
+        // Windows runs the handler on a thread of its own, so it may print.
+        if super::READING.load(Ordering::Relaxed) {
+            eprint!("\n> ");
+        }
+        1
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only touches atomics and stderr.
+        unsafe { SetConsoleCtrlHandler(on_interrupt, 1) };
+    }
+}
+
+#[cfg(not(all(feature = "std", any(unix, windows))))]
+mod platform {
+    pub fn install() {}
+}
+
+pub fn install() {
+    platform::install();
+}
+
+// Whether Ctrl-C was pressed since the last call.
+pub fn take() -> bool {
+    INTERRUPTED.swap(false, Ordering::Relaxed)
+}
+
+pub fn set_reading(reading: bool) {
+    READING.store(reading, Ordering::Relaxed);
+}
+
+// Stands in for the handler, for tests.
+#[cfg(test)]
+pub fn raise() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
@@ -0,0 +1,97 @@
+// The REPL writes every entry that ran to a recovery file as it goes, and removes the file
+// when the session ends normally. A file left behind means its session was lost to a crash
+// or a closed terminal, and the next one offers to replay it. Each session has its own file,
+// named by its pid and locked while it runs, so sessions running side by side leave each
+// other's journals alone.
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+// A journal whose session is gone, kept locked while this one decides what to do with it.
+pub struct Leftover {
+    path: PathBuf,
+    file: File,
+    pub text: String,
+}
+
+// `$XDG_STATE_HOME/qqc`, falling back to `~/.local/state`.
+pub fn default_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("LOCALAPPDATA")?),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("state"),
+    };
+    Some(base.join("qqc"))
+}
+
+// This session's journal in `dir`, like `session-4242.qq`.
+pub fn path_in(dir: &Path) -> PathBuf {
+    dir.join(format!("session-{}.qq", std::process::id()))
+}
+
+// What an unfinished session left in `dir`, if it got as far as running anything. A journal
+// that can't be locked belongs to a session that is still running. Empty ones are removed.
+pub fn leftover(dir: &Path) -> Option<Leftover> {
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !(name.starts_with("session-") && name.ends_with(".qq")) {
+            continue;
+        }
+        let path = entry.path();
+        let Ok(mut file) = OpenOptions::new().read(true).write(true).open(&path) else {
+            continue;
+        };
+        if file.try_lock().is_err() {
+            continue;
+        }
+        let mut text = String::new();
+        file.read_to_string(&mut text).ok();
+        let leftover = Leftover { path, file, text };
+        if !leftover.text.trim().is_empty() {
+            return Some(leftover);
+        }
+        leftover.discard();
+    }
+    None
+}
+
+// Removed while still locked where the OS allows it, so no other session can pick it up.
+fn remove(path: &Path, file: File) {
+    if std::fs::remove_file(path).is_err() {
+        drop(file);
+        std::fs::remove_file(path).ok();
+    }
+}
+
+impl Leftover {
+    pub fn discard(self) {
+        remove(&self.path, self.file);
+    }
+}
+
+impl Journal {
+    // Starts the file at `path` over, locked until the session ends.
+    pub fn open(path: &Path) -> Option<Journal> {
+        std::fs::create_dir_all(path.parent()?).ok()?;
+        let file = OpenOptions::new().create(true).write(true).truncate(true).open(path).ok()?;
+        file.try_lock().ok()?;
+        Some(Journal { path: path.to_path_buf(), file })
+    }
+
+    // Flushed right away, since the point is to survive the process going away.
+    pub fn record(&mut self, entry: &str) {
+        if self.file.write_all(entry.as_bytes()).and_then(|_| self.file.flush()).is_err() {
+            crate::logging::warn("journal", &[("path", &self.path.display())]);
+        }
+    }
+
+    pub fn finish(self) {
+        remove(&self.path, self.file);
+    }
+}
@@ -0,0 +1,204 @@
+use core::fmt;
+
+use crate::logging::json_string;
+use crate::prelude::*;
+use crate::EngineError;
+
+// Just enough JSON for reading structured input; objects keep their key order.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    // Follows a path like `.items[3].price` down from this value; `.` is the value itself.
+    pub fn select(&self, path: &str) -> Option<&Json> {
+        let (mut value, mut rest) = (self, path);
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('[') {
+                let (index, after) = after.split_once(']')?;
+                value = match value {
+                    Json::Array(items) => items.get(index.parse::<usize>().ok()?)?,
+                    _ => return None,
+                };
+                rest = after;
+            } else {
+                let after = rest.strip_prefix('.').unwrap_or(rest);
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end > 0 {
+                    value = value.get(&after[..end])?;
+                }
+                rest = &after[end..];
+            }
+        }
+        Some(value)
+    }
+
+    #[cfg(feature = "jupyter")]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write!(f, "{}", json_string(s)),
+            Json::Array(items) => {
+                let items: Vec<_> = items.iter().map(|item| item.to_string()).collect();
+                write!(f, "[{}]", items.join(","))
+            }
+            Json::Object(fields) => {
+                let fields: Vec<_> = fields.iter().map(|(k, v)| format!("{}:{}", json_string(k), v)).collect();
+                write!(f, "{{{}}}", fields.join(","))
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: core::iter::Peekable<core::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> EngineError {
+        EngineError::InvalidJson(what.into())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), EngineError> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, EngineError> {
+        for expected in word.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(self.error(&format!("expected '{}'", word)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, EngineError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.chars.next();
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&']') {
+                    self.chars.next();
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut fields = vec![];
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&'}') {
+                    self.chars.next();
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.peek().filter(|c| c.is_ascii_digit() || "+-.eE".contains(**c)) {
+                    number.push(*c);
+                    self.chars.next();
+                }
+                number.parse().map(Json::Number).map_err(|_| self.error("invalid number"))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, EngineError> {
+        if self.chars.next() != Some('"') {
+            return Err(self.error("expected a string"));
+        }
+        let mut out = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| self.error("invalid \\u escape"))?;
+                        out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    Some(c) => out.push(c),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => out.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json, EngineError> {
+    let mut parser = Parser { chars: text.chars().peekable() };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some(_) => Err(parser.error("trailing characters")),
+    }
+}
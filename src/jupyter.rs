@@ -0,0 +1,136 @@
+// The evaluating half of a Jupyter kernel for qqc. Frontends talk to kernels over ZeroMQ,
+// which this dependency-free build cannot open, so `qqc kernel` speaks a line-delimited JSON
+// protocol instead: each request is one `{"msg_type", "content"}` object per line on stdin,
+// answered on stdout by any messages for the frontend's output (`execute_result`, `error`)
+// and then exactly one `*_reply`. `jupyter/qqc_kernel.py` is the other half, relaying the
+// Jupyter wire protocol to it.
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+
+use crate::json::{self, Json};
+use crate::{describe, evaluate_statement, evaluate_statements, watch, EngineError, Evaluator, Value};
+
+pub struct Kernel {
+    engine: Evaluator,
+    functions: HashSet<String>,
+    execution_count: usize,
+}
+
+fn string(s: &str) -> Json {
+    Json::String(s.into())
+}
+
+fn message(msg_type: &str, content: Vec<(&str, Json)>) -> Json {
+    let content = content.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+    Json::Object(vec![("msg_type".into(), string(msg_type)), ("content".into(), Json::Object(content))])
+}
+
+// The reply to a request that can't be served, named after it so the relay knows the request
+// is answered: `foo_request` gets `foo_reply`, and anything else `error_reply`.
+fn error_reply(request: Option<&str>, ename: &str, evalue: &str) -> Json {
+    let msg_type = match request.and_then(|request| request.strip_suffix("_request")) {
+        Some(name) => format!("{}_reply", name),
+        None => "error_reply".into(),
+    };
+    message(&msg_type, vec![("status", string("error")), ("ename", string(ename)), ("evalue", string(evalue))])
+}
+
+impl Kernel {
+    pub fn new(engine: Evaluator) -> Kernel {
+        Kernel { engine, functions: HashSet::new(), execution_count: 0 }
+    }
+
+    // Runs one cell against the kernel's evaluator, which keeps its state between cells. It runs
+    // a statement at a time, so an error names its line within the cell.
+    fn run_cell(&mut self, code: &str) -> Result<Option<String>, EngineError> {
+        let statements = watch::statements_from(code, 0, &mut self.functions)?;
+        match evaluate_statements(&mut self.engine, statements, evaluate_statement)? {
+            Value::Nothing => Ok(None),
+            answer => Ok(Some(self.engine.format(&answer).unwrap_or_else(|_| describe(&answer)))),
+        }
+    }
+
+    // The messages answering one line of the protocol. A line that isn't JSON gets an error
+    // reply like any other request that can't be served, and the kernel reads on.
+    pub fn handle_line(&mut self, line: &str) -> Vec<Json> {
+        match json::parse(line) {
+            Ok(request) => self.handle(&request),
+            Err(err) => vec![error_reply(None, err.code(), &err.to_string())],
+        }
+    }
+
+    // The messages answering one request, in the order a kernel sends them.
+    pub fn handle(&mut self, request: &Json) -> Vec<Json> {
+        match request.get("msg_type").and_then(Json::as_str) {
+            Some("kernel_info_request") => vec![message(
+                "kernel_info_reply",
+                vec![
+                    ("status", string("ok")),
+                    ("protocol_version", string("5.3")),
+                    ("implementation", string("qqc")),
+                    ("implementation_version", string(env!("CARGO_PKG_VERSION"))),
+                    (
+                        "language_info",
+                        Json::Object(vec![
+                            ("name".into(), string("qqc")),
+                            ("mimetype".into(), string("text/x-qqc")),
+                            ("file_extension".into(), string(".qq")),
+                        ]),
+                    ),
+                ],
+            )],
+            Some("execute_request") => {
+                let code = request.get("content").and_then(|c| c.get("code")).and_then(Json::as_str).unwrap_or("");
+                self.execution_count += 1;
+                let count = Json::Number(self.execution_count as f64);
+                match self.run_cell(code) {
+                    Ok(answer) => {
+                        let mut replies = vec![];
+                        if let Some(answer) = answer {
+                            let data = Json::Object(vec![("text/plain".into(), string(&answer))]);
+                            replies.push(message(
+                                "execute_result",
+                                vec![("execution_count", count.clone()), ("data", data), ("metadata", Json::Object(vec![]))],
+                            ));
+                        }
+                        replies.push(message("execute_reply", vec![("status", string("ok")), ("execution_count", count)]));
+                        replies
+                    }
+                    Err(err) => {
+                        let error = vec![
+                            ("status", string("error")),
+                            ("ename", string(err.code())),
+                            ("evalue", string(&err.root().to_string())),
+                            ("traceback", Json::Array(vec![string(&err.to_string())])),
+                        ];
+                        let mut reply = error.clone();
+                        reply.push(("execution_count", count));
+                        vec![message("error", error), message("execute_reply", reply)]
+                    }
+                }
+            }
+            Some("shutdown_request") => vec![message("shutdown_reply", vec![("status", string("ok")), ("restart", Json::Bool(false))])],
+            other => vec![error_reply(other, "unknown-request", other.unwrap_or("missing msg_type"))],
+        }
+    }
+}
+
+pub fn run(engine: Evaluator) -> Result<(), EngineError> {
+    let mut kernel = Kernel::new(engine);
+    let mut stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|_| EngineError::CannotRead("stdin".into()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let replies = kernel.handle_line(&line);
+        for reply in &replies {
+            writeln!(stdout, "{}", reply).ok();
+        }
+        stdout.flush().ok();
+        if replies.iter().any(|reply| reply.get("msg_type").and_then(Json::as_str) == Some("shutdown_reply")) {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,6871 @@
+// Without the default `std` feature the library is the engine alone, parsing and evaluating
+// programs with `alloc`. The command line, files, the environment and threads need `std`.
+#![cfg_attr(not(feature = "std"), no_std)]
+// The command line is the only caller of some of the engine's helpers.
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+extern crate alloc;
+// The test harness runs on `std` either way, so engine tests can use it without the feature.
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
+mod cells;
+mod chemistry;
+#[cfg(feature = "clipboard")]
+mod clipboard;
+pub mod complete;
+mod config;
+#[cfg(feature = "test-suite")]
+pub mod conformance;
+mod constants;
+mod csv;
+#[cfg(feature = "std")]
+mod diagnostics;
+#[cfg(feature = "std")]
+mod doctor;
+#[cfg(feature = "std")]
+mod explain;
+mod fold;
+#[cfg(feature = "std")]
+mod golden;
+#[cfg(feature = "std")]
+mod grammar;
+mod integer;
+mod interrupt;
+#[cfg(feature = "std")]
+mod journal;
+mod json;
+#[cfg(feature = "jupyter")]
+mod jupyter;
+#[cfg(feature = "std")]
+mod lint;
+#[cfg(feature = "std")]
+mod literate;
+mod logging;
+mod math;
+#[cfg(feature = "net")]
+mod net;
+pub mod notation;
+#[cfg(feature = "std")]
+mod notify;
+mod operators;
+mod prelude;
+#[cfg(feature = "std")]
+mod profile;
+#[cfg(feature = "std")]
+mod render;
+#[cfg(feature = "std")]
+mod repl;
+#[cfg(feature = "std")]
+mod source;
+#[cfg(feature = "std")]
+mod stats;
+mod symbolic;
+mod symbols;
+mod table;
+mod threads;
+pub mod tokens;
+#[cfg(feature = "std")]
+mod tty;
+mod units;
+#[cfg(feature = "std")]
+mod watch;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+use core::cell::{Cell, RefCell};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::ffi::OsString;
+#[cfg(feature = "std")]
+use std::io::Write;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use prelude::*;
+
+use integer::BigUint;
+pub use notation::NumberFormatter;
+use symbolic::{solve_linear, Expr};
+#[cfg(feature = "std")]
+use diagnostics::Diagnostic;
+#[cfg(feature = "std")]
+use source::Source;
+use symbols::Symbol;
+use units::Dim;
+
+// Binaries built on the library install it as their global allocator for `--stats` to count
+// heap use; the binary `qqc` does, and so do the tests.
+#[cfg(feature = "std")]
+pub use stats::Counting;
+pub use symbols::Vars;
+
+#[cfg(all(test, feature = "std"))]
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[derive(Clone)]
+pub enum Command {
+    SetVar(String),
+    Const(String, usize), // `= name const`, with the line it is on
+    Display(DisplayMode),
+    Add(Vec<Value>),
+    Subtract(Vec<Value>),
+    Multiply(Vec<Value>),
+    Divide(Vec<Value>),
+    Power(Vec<Value>),
+    Modulo(Vec<Value>),
+    Ulp(Vec<Value>),
+    Sqrt(Vec<Value>),
+    Root(Vec<Value>), // `x n root`, the nth root of x
+    Log(Vec<Value>, Option<f64>), // The logarithm in a base, or with the base as an operand for `logn`
+    NextAfter(Vec<Value>),
+    Approx(Vec<Value>),
+    Simplify,
+    Derivative(String),
+    Solve(Vec<Value>),
+    Define(String, Rc<Function>),
+    Call(String, Vec<Value>),
+    FindRoot(String, Vec<Value>), // lo, hi, then optional tolerance and iteration limit
+    Integrate(String, Vec<Value>), // a, b, then optional tolerance
+    Series(ArithOp, Symbol, Vec<Value>, Vec<Command>), // `sigma`/`prod` fold of the body over an index range
+    IsPrime(Vec<Value>),
+    Factor(Vec<Value>),
+    Choose(Vec<Value>),
+    Permute(Vec<Value>),
+    PowMod(Vec<Value>),
+    Diff(Vec<Value>),
+    TransferTime(Vec<Value>),
+    Reciprocal(Vec<Value>, Dim, Dim), // `hz-to-period` and `period-to-hz`: from one dimension to the other
+    Parallel(Vec<Value>),
+    Min(Vec<Value>),
+    Max(Vec<Value>),
+    Aggregate(Vec<Value>, Aggregate), // Over the values and any list among them
+    Compare(Vec<Value>, Comparison),
+    Trig(Vec<Value>, Trig), // Of an angle read in the current angle mode
+    InverseTrig(Vec<Value>, InverseTrig),
+    VoltageDivider(Vec<Value>),
+    Angle(AngleMode),
+    ToPolar(Vec<Value>),
+    ToCartesian(Vec<Value>),
+    Distance(DistanceUnit),
+    Haversine(Vec<Value>), // `haversine lat1 lon1 lat2 lon2`, coordinates in degrees
+    MolarMass(String),
+    LoadCsv(String), // Makes the file's cells available as `A1` and `B2:B9`
+    #[cfg(feature = "xlsx")]
+    LoadXlsx(String, String, String), // Path, sheet and range
+    LoadJson(String, String),         // Path and the selector of a number or list of numbers
+    LoadConfig(String, String),       // The same for a TOML, YAML or JSON file
+    #[cfg(feature = "net")]
+    Fetch(String, String), // The same for a JSON document at a URL
+    Exit(Option<Value>), // Stops evaluating, optionally replacing the answer first
+    Fail(String), // `error "message"`
+    Try(Vec<Command>, Vec<Command>), // `try … recover … end`: the second block runs if the first fails
+    Expect(Value), // `expect 42`: the answer must equal the value
+    Assert(String, Vec<Command>), // `assert 10 <`: the rest of the line, as written and parsed, must be true
+    Snapshot(String), // Saves the whole evaluator state under a name
+    Restore(String), // Goes back to a saved state
+    Scenario(Rc<Scenario>),
+    Vars(String), // `vars loan.*` lists the variables a pattern matches in a table
+    Unset(String), // `unset loan.*` removes them
+    Defer(String, Rc<Deferred>), // `defer total "price qty *"`
+}
+
+// A user-defined function from a `def name params...` ... `end` block.
+pub struct Function {
+    params: Vec<Symbol>, // Interned when parsed, so binding them for a call looks nothing up
+    body: Vec<Command>,
+    memo: bool, // Declared with `memo`, so its results are cached by arguments
+}
+
+// The cached results of `memo` functions, by function name and then arguments. Each evaluator
+// has its own: a clone starts empty, while the scopes calls run in share their caller's.
+#[derive(Default)]
+struct Memos(Rc<RefCell<HashMap<String, HashMap<String, Value>>>>);
+
+impl Clone for Memos {
+    fn clone(&self) -> Memos {
+        Memos::default()
+    }
+}
+
+// A `scenario rate 0.03 0.05 … end` block, run once per parameter set with the results
+// shown side by side.
+pub struct Scenario {
+    params: Vec<String>,
+    sets: Vec<(String, Vec<Value>)>, // Each set's column header, like `rate=0.03`, and its values
+    body: Vec<Command>,
+}
+
+// An expression bound to a name by `defer`, evaluated again from the current variables
+// whenever the name is read.
+pub struct Deferred {
+    text: String, // As written, without the quotes
+    body: Vec<Command>,
+    inputs: Vec<String>, // The variables the expression reads, for `--cells`
+}
+
+impl Command {
+    // The keyword of a command that reaches outside the evaluator, refused with `--sandbox`.
+    fn privileged(&self) -> Option<&'static str> {
+        match self {
+            Command::LoadCsv(_) => Some("load-csv"),
+            Command::LoadJson(..) => Some("load-json"),
+            Command::LoadConfig(..) => Some("load-config"),
+            #[cfg(feature = "xlsx")]
+            Command::LoadXlsx(..) => Some("load-xlsx"),
+            #[cfg(feature = "net")]
+            Command::Fetch(..) => Some("fetch"),
+            _ => None,
+        }
+    }
+
+    // The values written on a command's line, like the `2 3` of `2 3 +`, for commands that take them.
+    pub fn operands(&self) -> Option<&[Value]> {
+        match self {
+            Command::SetVar(_)
+            | Command::Const(..)
+            | Command::Display(_)
+            | Command::Angle(_)
+            | Command::Distance(_)
+            | Command::MolarMass(_)
+            | Command::LoadCsv(_)
+            | Command::LoadJson(_, _)
+            | Command::LoadConfig(_, _)
+            | Command::Exit(_)
+            | Command::Fail(_)
+            | Command::Try(_, _)
+            | Command::Expect(_)
+            | Command::Assert(_, _)
+            | Command::Snapshot(_)
+            | Command::Restore(_)
+            | Command::Scenario(_)
+            | Command::Vars(_)
+            | Command::Unset(_)
+            | Command::Defer(..)
+            | Command::Simplify
+            | Command::Derivative(_)
+            | Command::Define(_, _) => None,
+            #[cfg(feature = "xlsx")]
+            Command::LoadXlsx(..) => None,
+            #[cfg(feature = "net")]
+            Command::Fetch(..) => None,
+            Command::Add(operands)
+            | Command::Subtract(operands)
+            | Command::Multiply(operands)
+            | Command::Divide(operands)
+            | Command::Power(operands)
+            | Command::Modulo(operands)
+            | Command::Ulp(operands)
+            | Command::Sqrt(operands)
+            | Command::Root(operands)
+            | Command::Log(operands, _)
+            | Command::NextAfter(operands)
+            | Command::Approx(operands)
+            | Command::Solve(operands)
+            | Command::Call(_, operands)
+            | Command::FindRoot(_, operands)
+            | Command::Integrate(_, operands)
+            | Command::Series(_, _, operands, _)
+            | Command::IsPrime(operands)
+            | Command::Factor(operands)
+            | Command::Choose(operands)
+            | Command::Permute(operands)
+            | Command::PowMod(operands)
+            | Command::Diff(operands)
+            | Command::TransferTime(operands)
+            | Command::Reciprocal(operands, _, _)
+            | Command::Parallel(operands)
+            | Command::Min(operands)
+            | Command::Aggregate(operands, _)
+            | Command::Compare(operands, _)
+            | Command::Trig(operands, _)
+            | Command::InverseTrig(operands, _)
+            | Command::Max(operands)
+            | Command::VoltageDivider(operands)
+            | Command::ToPolar(operands)
+            | Command::ToCartesian(operands)
+            | Command::Haversine(operands) => Some(operands),
+        }
+    }
+
+    fn operands_mut(&mut self) -> Option<&mut Vec<Value>> {
+        match self {
+            Command::SetVar(_)
+            | Command::Const(..)
+            | Command::Display(_)
+            | Command::Angle(_)
+            | Command::Distance(_)
+            | Command::MolarMass(_)
+            | Command::LoadCsv(_)
+            | Command::LoadJson(_, _)
+            | Command::LoadConfig(_, _)
+            | Command::Exit(_)
+            | Command::Fail(_)
+            | Command::Try(_, _)
+            | Command::Expect(_)
+            | Command::Assert(_, _)
+            | Command::Snapshot(_)
+            | Command::Restore(_)
+            | Command::Scenario(_)
+            | Command::Vars(_)
+            | Command::Unset(_)
+            | Command::Defer(..)
+            | Command::Simplify
+            | Command::Derivative(_)
+            | Command::Define(_, _) => None,
+            #[cfg(feature = "xlsx")]
+            Command::LoadXlsx(..) => None,
+            #[cfg(feature = "net")]
+            Command::Fetch(..) => None,
+            Command::Add(operands)
+            | Command::Subtract(operands)
+            | Command::Multiply(operands)
+            | Command::Divide(operands)
+            | Command::Power(operands)
+            | Command::Modulo(operands)
+            | Command::Ulp(operands)
+            | Command::Sqrt(operands)
+            | Command::Root(operands)
+            | Command::Log(operands, _)
+            | Command::NextAfter(operands)
+            | Command::Approx(operands)
+            | Command::Solve(operands)
+            | Command::Call(_, operands)
+            | Command::FindRoot(_, operands)
+            | Command::Integrate(_, operands)
+            | Command::Series(_, _, operands, _)
+            | Command::IsPrime(operands)
+            | Command::Factor(operands)
+            | Command::Choose(operands)
+            | Command::Permute(operands)
+            | Command::PowMod(operands)
+            | Command::Diff(operands)
+            | Command::TransferTime(operands)
+            | Command::Reciprocal(operands, _, _)
+            | Command::Parallel(operands)
+            | Command::Min(operands)
+            | Command::Aggregate(operands, _)
+            | Command::Compare(operands, _)
+            | Command::Trig(operands, _)
+            | Command::InverseTrig(operands, _)
+            | Command::Max(operands)
+            | Command::VoltageDivider(operands)
+            | Command::ToPolar(operands)
+            | Command::ToCartesian(operands)
+            | Command::Haversine(operands) => Some(operands),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Nothing,
+    Operand(f64),
+    Variable(Symbol),
+    Bool(bool),
+    Uncertain(f64, f64), // A measurement and its absolute uncertainty, e.g. `9.81±0.02`
+    Significant(f64, u32), // A measurement and its count of significant digits
+    Symbolic(Expr),
+    List(Vec<f64>),
+    Integer(BigUint), // Exact whole numbers too large for an f64 to hold
+    Rational(i64, i64), // An exact fraction in lowest terms with a positive denominator
+    Quantity(f64, Dim), // An amount with units, held in seconds and bytes
+    Timestamp(f64), // A point in time, as seconds since the Unix epoch
+}
+
+impl Value {
+    fn as_big(&self) -> Option<BigUint> {
+        match self {
+            Value::Integer(n) => Some(n.clone()),
+            Value::Operand(x) => to_natural(*x).ok().map(BigUint::from_u64),
+            _ => None,
+        }
+    }
+
+    fn as_ratio(&self) -> Option<(i64, i64)> {
+        match self {
+            Value::Rational(num, den) => Some((*num, *den)),
+            Value::Operand(x) if x.fract() == 0.0 && x.abs() <= MAX_EXACT_INTEGER => Some((*x as i64, 1)),
+            _ => None,
+        }
+    }
+}
+
+// The folding arithmetic operators shared by the basic commands.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArithOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Modulo,
+}
+
+impl ArithOp {
+    fn apply(self, acc: f64, x: f64) -> f64 {
+        match self {
+            ArithOp::Add => acc + x,
+            ArithOp::Subtract => acc - x,
+            ArithOp::Multiply => acc * x,
+            ArithOp::Divide => acc / x,
+            ArithOp::Power => math::pow(acc, x),
+            ArithOp::Modulo => acc % x,
+        }
+    }
+
+    // First-order propagation of independent uncertainties, added in quadrature.
+    fn propagate(self, (a, da): (f64, f64), (b, db): (f64, f64), result: f64) -> f64 {
+        match self {
+            ArithOp::Add | ArithOp::Subtract | ArithOp::Modulo => math::hypot(da, db),
+            ArithOp::Multiply | ArithOp::Divide => result.abs() * math::hypot(da / a, db / b),
+            ArithOp::Power => {
+                let from_base = if da == 0.0 { 0.0 } else { b * da / a };
+                let from_exponent = if db == 0.0 { 0.0 } else { math::ln(a) * db };
+                result.abs() * math::hypot(from_base, from_exponent)
+            }
+        }
+    }
+
+    fn combine(self, acc: Value, x: Value) -> Result<Value, EngineError> {
+        // Sums and products of big integers stay exact, anything else falls back to floats.
+        if let (Value::Integer(_), _) | (_, Value::Integer(_)) = (&acc, &x) {
+            if let (ArithOp::Add | ArithOp::Multiply, Some(a), Some(b)) = (self, acc.as_big(), x.as_big()) {
+                let result = if self == ArithOp::Add { a.add(&b) } else { a.mul(&b) };
+                return Ok(integer_value(result));
+            }
+        }
+        if let (Value::Rational(_, _), _) | (_, Value::Rational(_, _)) = (&acc, &x) {
+            if let Some(result) = acc.as_ratio().zip(x.as_ratio()).and_then(|(a, b)| self.combine_rational(a, b)) {
+                return Ok(rational_value(result));
+            }
+        }
+        let demote = |v: Value| match v {
+            Value::Integer(n) => Value::Operand(n.to_f64()),
+            Value::Rational(num, den) => Value::Operand(num as f64 / den as f64),
+            v => v,
+        };
+        let (acc, x) = (demote(acc), demote(x));
+        // Timestamps shift by durations, and their differences are durations.
+        match (self, &acc, &x) {
+            (ArithOp::Add, Value::Timestamp(t), Value::Quantity(d, units::TIME))
+            | (ArithOp::Add, Value::Quantity(d, units::TIME), Value::Timestamp(t)) => return Ok(Value::Timestamp(t + d)),
+            (ArithOp::Subtract, Value::Timestamp(t), Value::Quantity(d, units::TIME)) => return Ok(Value::Timestamp(t - d)),
+            (ArithOp::Subtract, Value::Timestamp(a), Value::Timestamp(b)) => return Ok(Value::Quantity(a - b, units::TIME)),
+            (_, Value::Timestamp(_), _) | (_, _, Value::Timestamp(_)) => return Err(EngineError::MismatchType),
+            _ => {}
+        }
+        if let (Value::Quantity(_, _), _) | (_, Value::Quantity(_, _)) = (&acc, &x) {
+            let as_quantity = |v: &Value| match v {
+                Value::Quantity(value, dim) => Some((*value, *dim)),
+                Value::Operand(value) => Some((*value, units::NONE)),
+                _ => None,
+            };
+            let (a, b) = as_quantity(&acc).zip(as_quantity(&x)).ok_or(EngineError::MismatchType)?;
+            return match units::combine(self, a, b) {
+                Some((value, units::NONE)) => Ok(Value::Operand(value)),
+                Some((value, dim)) => Ok(Value::Quantity(value, dim)),
+                None => Err(EngineError::MismatchType),
+            };
+        }
+        match (acc, x) {
+            (Value::Operand(a), Value::Operand(b)) => Ok(Value::Operand(self.apply(a, b))),
+            (Value::Operand(a), Value::Uncertain(b, db)) => Ok(self.combine_uncertain((a, 0.0), (b, db))),
+            (Value::Uncertain(a, da), Value::Operand(b)) => Ok(self.combine_uncertain((a, da), (b, 0.0))),
+            (Value::Uncertain(a, da), Value::Uncertain(b, db)) => Ok(self.combine_uncertain((a, da), (b, db))),
+            (Value::Significant(a, sa), Value::Significant(b, sb)) => Ok(self.combine_significant((a, Some(sa)), (b, Some(sb)))),
+            (Value::Significant(a, sa), Value::Operand(b)) => Ok(self.combine_significant((a, Some(sa)), (b, None))),
+            (Value::Operand(a), Value::Significant(b, sb)) => Ok(self.combine_significant((a, None), (b, Some(sb)))),
+            (Value::Symbolic(a), Value::Symbolic(b)) => Ok(Value::Symbolic(Expr::binary(self, a, b))),
+            (Value::Symbolic(a), Value::Operand(b)) => Ok(Value::Symbolic(Expr::binary(self, a, Expr::Num(b)))),
+            (Value::Operand(a), Value::Symbolic(b)) => Ok(Value::Symbolic(Expr::binary(self, Expr::Num(a), b))),
+            _ => Err(EngineError::MismatchType),
+        }
+    }
+
+    // Exact fraction arithmetic, or `None` when the result would overflow or stop being rational.
+    fn combine_rational(self, (an, ad): (i64, i64), (bn, bd): (i64, i64)) -> Option<(i64, i64)> {
+        let (an, ad, bn, bd) = (an as i128, ad as i128, bn as i128, bd as i128);
+        // Cross products of i64s fit in i128, but their sums and the remainder's triple
+        // product may not, so every step is checked and `None` falls back to floats.
+        let (cross, across) = (an.checked_mul(bd)?, bn.checked_mul(ad)?);
+        match self {
+            ArithOp::Add => integer::reduce_ratio(cross.checked_add(across)?, ad.checked_mul(bd)?),
+            ArithOp::Subtract => integer::reduce_ratio(cross.checked_sub(across)?, ad.checked_mul(bd)?),
+            ArithOp::Multiply => integer::reduce_ratio(an.checked_mul(bn)?, ad.checked_mul(bd)?),
+            ArithOp::Divide => integer::reduce_ratio(cross, across),
+            // Truncated remainder, matching `%` on floats.
+            ArithOp::Modulo => {
+                let quotient = integer::reduce_ratio(cross, across)?;
+                let whole = (quotient.0 as i128).checked_div(quotient.1 as i128)?;
+                integer::reduce_ratio(cross.checked_sub(whole.checked_mul(across)?)?, ad.checked_mul(bd)?)
+            }
+            ArithOp::Power if bd == 1 && bn.abs() <= 64 => {
+                let (mut num, mut den) = (1i128, 1i128);
+                for _ in 0..bn.abs() {
+                    num = num.checked_mul(an)?;
+                    den = den.checked_mul(ad)?;
+                }
+                if bn < 0 {
+                    (num, den) = (den, num);
+                }
+                integer::reduce_ratio(num, den)
+            }
+            ArithOp::Power => None,
+        }
+    }
+
+    // Applies the usual sig-fig rules: sums keep the coarsest decimal place,
+    // products keep the fewest significant digits. `None` marks an exact number.
+    fn combine_significant(self, (a, sa): (f64, Option<u32>), (b, sb): (f64, Option<u32>)) -> Value {
+        let result = self.apply(a, b);
+        let digits = match self {
+            ArithOp::Add | ArithOp::Subtract | ArithOp::Modulo => {
+                let place = |v: f64, digits: Option<u32>| digits.map(|d| magnitude(v) + 1 - d as i32);
+                match (place(a, sa), place(b, sb)) {
+                    (Some(pa), Some(pb)) => Some(pa.max(pb)),
+                    (p, None) | (None, p) => p,
+                }
+                .map(|place| (magnitude(result) + 1 - place).max(1) as u32)
+            }
+            ArithOp::Multiply | ArithOp::Divide => match (sa, sb) {
+                (Some(sa), Some(sb)) => Some(sa.min(sb)),
+                (d, None) | (None, d) => d,
+            },
+            ArithOp::Power => sa,
+        };
+        match digits {
+            Some(digits) => Value::Significant(result, digits),
+            None => Value::Operand(result),
+        }
+    }
+
+    fn combine_uncertain(self, a: (f64, f64), b: (f64, f64)) -> Value {
+        let result = self.apply(a.0, b.0);
+        Value::Uncertain(result, self.propagate(a, b, result))
+    }
+}
+
+// How the final answer is printed, set by display directives like `i8`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DisplayMode {
+    Default,
+    I8,
+    I16,
+    I32,
+    U32,
+    Bits,
+    Base(u32), // Integers in any radix from 2 to 36, set by `to-base N`
+    Mixed(Option<u32>), // Mixed numbers like `3 1/4`, optionally rounded to the nearest 1/N
+    Clock,
+    Unit(&'static str), // Quantities converted to a unit, set by `to-<unit>` like `to-gib`
+}
+
+// Whole-list statistics, run on `--threads` workers for long lists.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Aggregate {
+    Sum,
+    Mean,
+    StdDev,
+    Sort,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Trig {
+    Sin,
+    Cos,
+    Tan,
+}
+
+// The inverses give an angle in the current angle mode.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InverseTrig {
+    Asin,
+    Acos,
+    Atan,
+}
+
+// How angles are read and shown, set by a `deg` or `rad` line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+}
+
+impl AngleMode {
+    fn radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_radians(),
+        }
+    }
+
+    fn angle(self, radians: f64) -> f64 {
+        match self {
+            AngleMode::Radians => radians,
+            AngleMode::Degrees => radians.to_degrees(),
+        }
+    }
+}
+
+// The unit great-circle distances are given in, set by a `km` or `mi` line.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DistanceUnit {
+    Kilometres,
+    Miles,
+}
+
+impl DistanceUnit {
+    fn convert(self, km: f64) -> f64 {
+        match self {
+            DistanceUnit::Kilometres => km,
+            DistanceUnit::Miles => km / 1.609344,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EngineError {
+    TooManyVariableNames,
+    MissingVariableName,
+    MissingOperands,
+    MismatchType,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingVariable(String),
+    EvaluatorAnswerShouldNotBeValueVariable,
+    NoValuesInQueue,
+    UnexpectedOperands,
+    TooManyOperands,
+    NotAnInteger,
+    OutOfRange,
+    UnsupportedExpression,
+    NotSolvable,
+    UnknownFunction(String),
+    UnterminatedBlock,
+    NoRootInInterval,
+    NoConvergence,
+    DivisionByZero,
+    UnknownFormula(String),
+    ReadOnlyVariable(String),
+    ConstantReassigned(String, usize), // The name and the line its `const` is on
+    MissingArgument(usize),
+    InvalidDefine(String),
+    User(String), // Raised by the script itself with `error "message"`
+    Strict(String), // A warning turned into an error by strict mode
+    InvalidCell(String),
+    AtLine(usize, Box<EngineError>), // An error raised while parsing the given source line
+    CannotRead(String),
+    CannotWrite(String),
+    InvalidJson(String),
+    InvalidConfig(String),
+    #[cfg(feature = "net")]
+    FetchFailed(String),
+    #[cfg(feature = "net")]
+    FetchTimeout(String),
+    #[cfg(feature = "xlsx")]
+    InvalidXlsx(String),
+    IncludeCycle(String),
+    DeferCycle(Vec<String>), // The names from the first one read back to it
+    OperandCount(String, operators::Operands, usize), // The operator as written, what it takes and what it got
+    NameTaken(String, Vec<String>), // A function name and the built-ins it collides with
+    NotPermitted(String), // A command refused with `--sandbox`
+    Interrupted, // Ctrl-C during an evaluation in the REPL or `--watch`
+    RecursionLimit(String), // The function that called itself too deeply
+    NotABoolean, // `--test` needs the answer to be true or false
+    NumberOutOfRange(String), // A literal too large or too small for an f64
+    InvalidNumber(String), // A token starting with a digit that isn't a number
+    UnknownSnapshot(String),
+}
+
+impl EngineError {
+    // Places an error on a line, unless it already has one.
+    fn at_line(self, line: usize) -> EngineError {
+        match self {
+            EngineError::AtLine(..) => self,
+            err => EngineError::AtLine(line, Box::new(err)),
+        }
+    }
+
+    // Errors from a block's body are numbered from the line opening the block.
+    fn in_block(self, start: usize) -> EngineError {
+        match self {
+            EngineError::AtLine(line, err) => EngineError::AtLine(start + line, err),
+            err => EngineError::AtLine(start, Box::new(err)),
+        }
+    }
+
+    fn unlocated(self) -> EngineError {
+        match self {
+            EngineError::AtLine(_, err) => *err,
+            err => err,
+        }
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            EngineError::AtLine(line, _) => Some(*line),
+            _ => None,
+        }
+    }
+
+    // The error itself, without the line it was placed on.
+    pub fn root(&self) -> &EngineError {
+        match self {
+            EngineError::AtLine(_, err) => err.root(),
+            err => err,
+        }
+    }
+
+    // A stable name for each kind of error, for tools reading `--diagnostics json`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EngineError::TooManyVariableNames => "too-many-variable-names",
+            EngineError::MissingVariableName => "missing-variable-name",
+            EngineError::MissingOperands => "missing-operands",
+            EngineError::MismatchType => "mismatched-type",
+            EngineError::UnknownCommand(_) => "unknown-command",
+            EngineError::UnknownOption(_) => "unknown-option",
+            EngineError::MissingVariable(_) => "missing-variable",
+            EngineError::EvaluatorAnswerShouldNotBeValueVariable => "answer-is-variable",
+            EngineError::NoValuesInQueue => "no-values",
+            EngineError::UnexpectedOperands => "unexpected-operands",
+            EngineError::TooManyOperands => "too-many-operands",
+            EngineError::NotAnInteger => "not-an-integer",
+            EngineError::OutOfRange => "out-of-range",
+            EngineError::UnsupportedExpression => "unsupported-expression",
+            EngineError::NotSolvable => "not-solvable",
+            EngineError::UnknownFunction(_) => "unknown-function",
+            EngineError::UnterminatedBlock => "unterminated-block",
+            EngineError::NoRootInInterval => "no-root-in-interval",
+            EngineError::NoConvergence => "no-convergence",
+            EngineError::DivisionByZero => "division-by-zero",
+            EngineError::UnknownFormula(_) => "unknown-formula",
+            EngineError::ReadOnlyVariable(_) => "read-only-variable",
+            EngineError::ConstantReassigned(..) => "constant-reassigned",
+            EngineError::MissingArgument(_) => "missing-argument",
+            EngineError::InvalidDefine(_) => "invalid-define",
+            EngineError::User(_) => "user-error",
+            EngineError::Strict(_) => "strict",
+            EngineError::InvalidCell(_) => "invalid-cell",
+            EngineError::AtLine(_, err) => err.code(),
+            EngineError::CannotRead(_) => "cannot-read",
+            EngineError::CannotWrite(_) => "cannot-write",
+            EngineError::InvalidJson(_) => "invalid-json",
+            EngineError::InvalidConfig(_) => "invalid-config",
+            #[cfg(feature = "net")]
+            EngineError::FetchFailed(_) => "fetch-failed",
+            #[cfg(feature = "net")]
+            EngineError::FetchTimeout(_) => "fetch-timeout",
+            #[cfg(feature = "xlsx")]
+            EngineError::InvalidXlsx(_) => "invalid-xlsx",
+            EngineError::IncludeCycle(_) => "include-cycle",
+            EngineError::DeferCycle(_) => "defer-cycle",
+            EngineError::OperandCount(..) => "operand-count",
+            EngineError::NameTaken(..) => "name-taken",
+            EngineError::NotPermitted(_) => "not-permitted",
+            EngineError::Interrupted => "interrupted",
+            EngineError::RecursionLimit(_) => "recursion-limit",
+            EngineError::NotABoolean => "not-a-boolean",
+            EngineError::NumberOutOfRange(_) => "number-out-of-range",
+            EngineError::InvalidNumber(_) => "invalid-number",
+            EngineError::UnknownSnapshot(_) => "unknown-snapshot",
+        }
+    }
+
+    fn suggestion(&self) -> Option<String> {
+        match self {
+            EngineError::MissingVariable(name) => Some(format!("assign it first with '= {}' or pass -D {}=<value>", name, name)),
+            EngineError::MissingArgument(index) => Some(format!("pass at least {} arguments after the script name", index)),
+            EngineError::UnterminatedBlock => Some("close the block with a line containing only 'end'".into()),
+            EngineError::ReadOnlyVariable(_) => Some("constants cannot be reassigned; pick another name".into()),
+            EngineError::ConstantReassigned(..) => Some("drop `const` from the first binding or pick another name".into()),
+            EngineError::NameTaken(..) => Some("pick another name for the function".into()),
+            EngineError::RecursionLimit(_) => Some("check that the function stops calling itself for some input".into()),
+            EngineError::NotPermitted(_) => Some("the program runs with --sandbox, which keeps it from reading files, the network and the environment".into()),
+            EngineError::UnknownCommand(_) => Some("the last token on a line must be an operator or a defined function".into()),
+            EngineError::AtLine(_, err) => err.suggestion(),
+            _ => None,
+        }
+    }
+
+    // Script-raised errors get their own code so callers can tell them from engine failures.
+    fn exit_code(&self) -> i32 {
+        match self {
+            EngineError::User(_) => 2,
+            EngineError::Interrupted => 130,
+            EngineError::AtLine(_, err) => err.exit_code(),
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::TooManyVariableNames => write!(f, "too many variable names"),
+            EngineError::MissingVariableName => write!(f, "missing variable name"),
+            EngineError::MissingOperands => write!(f, "missing operands"),
+            EngineError::MismatchType => write!(f, "mismatched value type"),
+            EngineError::UnknownCommand(name) => write!(f, "unknown command '{}'", name),
+            EngineError::UnknownOption(name) => write!(f, "unknown option '{}'", name),
+            EngineError::MissingVariable(name) => write!(f, "missing variable '{}'", name),
+            EngineError::EvaluatorAnswerShouldNotBeValueVariable => write!(f, "answer should not be a variable"),
+            EngineError::NoValuesInQueue => write!(f, "no values in queue"),
+            EngineError::UnexpectedOperands => write!(f, "unexpected operands"),
+            EngineError::TooManyOperands => write!(f, "too many operands"),
+            EngineError::NotAnInteger => write!(f, "value is not an integer"),
+            EngineError::OutOfRange => write!(f, "value is out of range"),
+            EngineError::UnsupportedExpression => write!(f, "expression is not supported"),
+            EngineError::NotSolvable => write!(f, "equation is not linear in a single unknown"),
+            EngineError::UnknownFunction(name) => write!(f, "unknown function '{}'", name),
+            EngineError::UnterminatedBlock => write!(f, "block is missing its 'end'"),
+            EngineError::NoRootInInterval => write!(f, "function does not change sign over the interval"),
+            EngineError::NoConvergence => write!(f, "iteration did not converge"),
+            EngineError::DivisionByZero => write!(f, "division by zero"),
+            EngineError::UnknownFormula(formula) => write!(f, "cannot read chemical formula '{}'", formula),
+            EngineError::ReadOnlyVariable(name) => write!(f, "cannot assign to read-only '{}'", name),
+            EngineError::ConstantReassigned(name, line) => write!(f, "cannot reassign '{}', declared const on line {}", name, line),
+            EngineError::MissingArgument(index) => write!(f, "missing script argument ${}", index),
+            EngineError::InvalidDefine(arg) => write!(f, "expected name=value for -D, got '{}'", arg),
+            EngineError::User(message) => write!(f, "{}", message),
+            EngineError::Strict(warning) => write!(f, "{} (strict mode)", warning),
+            EngineError::InvalidCell(cell) => write!(f, "cell {} is not a number", cell),
+            EngineError::AtLine(line, err) => write!(f, "line {}: {}", line, err),
+            EngineError::CannotRead(path) => write!(f, "cannot read '{}'", path),
+            EngineError::CannotWrite(path) => write!(f, "cannot write '{}'", path),
+            EngineError::InvalidJson(what) => write!(f, "invalid JSON: {}", what),
+            EngineError::InvalidConfig(what) => write!(f, "invalid config: {}", what),
+            #[cfg(feature = "net")]
+            EngineError::FetchFailed(what) => write!(f, "fetch failed: {}", what),
+            #[cfg(feature = "net")]
+            EngineError::FetchTimeout(url) => write!(f, "fetch timed out after {}s: {}", net::TIMEOUT_SECONDS, url),
+            #[cfg(feature = "xlsx")]
+            EngineError::InvalidXlsx(what) => write!(f, "invalid workbook: {}", what),
+            EngineError::IncludeCycle(path) => write!(f, "'{}' includes itself", path),
+            EngineError::DeferCycle(names) => write!(f, "'{}' depends on itself: {}", names[0], names.join(" -> ")),
+            EngineError::OperandCount(op, operands, found) if *found < operands.min => write!(f, "'{}' needs {}", op, operands),
+            EngineError::OperandCount(op, operands, found) => write!(f, "'{}' takes {}, got {}", op, operands, found),
+            EngineError::NotPermitted(keyword) => write!(f, "'{}' is not permitted in the sandbox", keyword),
+            EngineError::NameTaken(name, conflicts) => write!(f, "cannot define '{}', it is already {}", name, conflicts.join(" and ")),
+            EngineError::Interrupted => write!(f, "interrupted"),
+            EngineError::RecursionLimit(name) => write!(f, "'{}' recursed more than {} calls deep", name, MAX_CALL_DEPTH),
+            EngineError::NotABoolean => write!(f, "the answer is not true or false"),
+            EngineError::NumberOutOfRange(literal) => write!(f, "'{}' is out of the range of a number", literal),
+            EngineError::InvalidNumber(literal) => write!(f, "'{}' is not a valid number", literal),
+            EngineError::UnknownSnapshot(name) => write!(f, "no snapshot named '{}'", name),
+        }
+    }
+}
+
+impl core::error::Error for EngineError {}
+
+const ENV_PREFIX: &str = "QQC_VAR_";
+
+// How deeply user functions may nest before a call fails, well short of overflowing the stack.
+const MAX_CALL_DEPTH: usize = 32;
+
+#[derive(Clone)]
+pub struct Evaluator {
+    vars: Vars,
+    consts: HashMap<String, usize>, // Variables declared `const`, with the line of the declaration
+    deferred: HashMap<String, Rc<Deferred>>, // Names bound by `defer`
+    deferring: Vec<String>, // The deferred names being recomputed, innermost last
+    cells: Option<cells::Cells>, // Deferred values kept until their inputs change, with `--cells`
+    answers: Vec<Value>, // Saving answers to display at the end, not used in evaluation.
+    answer: Value, // The main accumulator
+    display: DisplayMode,
+    angle: AngleMode,
+    distance: DistanceUnit,
+    memos: Memos,
+    symbolic: bool, // Let missing variables through as unknowns instead of erroring
+    functions: HashMap<String, Rc<Function>>,
+    halted: bool, // Set by `exit`, ending the run even from inside a `try` block
+    warnings: Vec<String>, // Reported on stderr after the run, never mixed into the answer
+    precision: Option<u32>, // Significant digits plain numbers are shown with
+    notation: Rc<dyn NumberFormatter>, // How plain numbers are written
+    strict: bool, // Fail on anything that would otherwise be a warning
+    history: bool, // Keep every answer in `answers`
+    prompt: Option<fn(&str) -> Option<Value>>, // Asks for a missing variable instead of failing
+    sheet: Option<Rc<csv::Sheet>>, // The last CSV file loaded with `load-csv`
+    interruptible: bool, // Stop with `Interrupted` after Ctrl-C; only the REPL and `--watch` install a handler
+    sandbox: bool, // Refuse commands that read files or the network, for untrusted programs
+    depth: usize, // User function calls in progress, checked against `MAX_CALL_DEPTH`
+    executed: usize, // Commands run so far, for `--stats`
+    line: Option<usize>, // The first line of the statement running, for the logging events
+    program: Rc<Program>, // What `step` runs, set by `load`
+    position: usize, // The next command `step` runs
+    checks: Vec<Check>, // Every `expect` and `assert` run so far, for `qqc test`
+    snapshots: HashMap<String, Rc<Evaluator>>, // Saved by `snapshot name`
+    tables: Vec<table::Table>, // Printed before the answer, one per `scenario` block
+}
+
+// The outcome of an `expect` or `assert` directive.
+#[derive(Clone, PartialEq, Debug)]
+struct Check {
+    directive: String, // As written, like `expect 42`
+    expected: String,
+    actual: String,
+    passed: bool,
+}
+
+// What one `step` did: the index of the command it ran and how that went. A failed command
+// is left in place, so stepping again retries it.
+#[derive(Debug)]
+pub struct StepResult {
+    pub index: usize,
+    pub result: Result<Value, EngineError>,
+}
+
+// What `parse` makes of a program: its commands, with the source line each starts on so an
+// error raised running one can name it. It reads as the slice of its commands.
+#[derive(Clone, Default)]
+pub struct Program {
+    pub commands: Vec<Command>,
+    pub lines: Vec<usize>,
+}
+
+impl Program {
+    // Places an error raised by the command at `index` on the line it starts on.
+    fn locate(&self, index: usize, err: EngineError) -> EngineError {
+        match self.lines.get(index) {
+            Some(line) => err.at_line(*line),
+            None => err,
+        }
+    }
+}
+
+impl core::ops::Deref for Program {
+    type Target = [Command];
+
+    fn deref(&self) -> &[Command] {
+        &self.commands
+    }
+}
+
+// Everything an embedder wants from a run: the answer, every variable it left bound in name
+// order, the answers it recorded along the way and the warnings it raised.
+#[derive(Debug, PartialEq)]
+pub struct EvalOutcome {
+    pub value: Value,
+    pub bindings: Vec<(String, Value)>,
+    pub history: Vec<Value>,
+    pub warnings: Vec<String>,
+}
+
+// Configures an evaluator for embedding, e.g.
+// `Evaluator::builder().angle_mode(AngleMode::Degrees).precision(12).strict(true).build()`.
+pub struct EvaluatorBuilder {
+    engine: Evaluator,
+}
+
+impl EvaluatorBuilder {
+    pub fn angle_mode(mut self, angle: AngleMode) -> Self {
+        self.engine.angle = angle;
+        self
+    }
+
+    pub fn distance_unit(mut self, distance: DistanceUnit) -> Self {
+        self.engine.distance = distance;
+        self
+    }
+
+    pub fn precision(mut self, digits: u32) -> Self {
+        self.engine.precision = Some(digits);
+        self
+    }
+
+    pub fn notation(mut self, notation: impl NumberFormatter + 'static) -> Self {
+        self.engine.notation = Rc::new(notation);
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.engine.strict = strict;
+        self
+    }
+
+    pub fn cells(mut self, cells: bool) -> Self {
+        self.engine.cells = cells.then(cells::Cells::default);
+        self
+    }
+
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.engine.sandbox = sandbox;
+        self
+    }
+
+    pub fn history(mut self, history: bool) -> Self {
+        self.engine.history = history;
+        self
+    }
+
+    pub fn prompt(mut self, prompt: fn(&str) -> Option<Value>) -> Self {
+        self.engine.prompt = Some(prompt);
+        self
+    }
+
+    pub fn symbolic(mut self, symbolic: bool) -> Self {
+        self.engine.symbolic = symbolic;
+        self
+    }
+
+    pub fn build(self) -> Evaluator {
+        self.engine
+    }
+}
+
+// Step-wise evaluation for embedders like a debugger or an editor, which run a program one
+// command at a time and look at the state in between.
+impl Evaluator {
+    pub fn load(&mut self, program: Program) {
+        self.program = Rc::new(program);
+        self.position = 0;
+    }
+
+    // Runs the next command of the loaded program, or returns None once it is done or halted.
+    pub fn step(&mut self) -> Option<StepResult> {
+        if self.halted || self.position >= self.program.len() {
+            return None;
+        }
+        let (program, index) = (self.program.clone(), self.position);
+        let mut next = index;
+        let result = self.evaluate_from(&program[..=index], &mut next).map_err(|err| program.locate(index, err));
+        self.position = next;
+        Some(StepResult { index, result })
+    }
+
+    // Runs a program and gathers the outcome, so embedders don't read the state field by field.
+    // An error is placed on the line of the command that raised it, and deferred names are
+    // included when their value can be worked out.
+    pub fn evaluate(&mut self, program: &Program) -> Result<EvalOutcome, EngineError> {
+        let (answers, warnings) = (self.answers.len(), self.warnings.len());
+        let mut next = 0;
+        let value = self.evaluate_prompting(program, &mut next).map_err(|err| program.locate(next, err))?;
+        let mut names: Vec<_> = self.vars.keys().chain(self.deferred.keys().map(String::as_str)).map(String::from).collect();
+        names.sort();
+        let bindings = names.into_iter().filter_map(|name| Some((name.clone(), self.binding(&name)?.ok()?))).collect();
+        Ok(EvalOutcome { value, bindings, history: self.answers[answers..].to_vec(), warnings: self.warnings[warnings..].to_vec() })
+    }
+
+    // Evaluates each line on its own copy of the current state, as for spreadsheet cells that
+    // share one set of variables: no line sees what another bound, and the state is unchanged.
+    pub fn eval_many<'a, I: IntoIterator<Item = &'a str>>(&mut self, lines: I) -> Vec<Result<Value, EngineError>> {
+        let functions: HashSet<_> = self.functions.keys().cloned().collect();
+        lines.into_iter().map(|line| self.clone().evaluate_commands(&parse_program(line, &mut functions.clone())?)).collect()
+    }
+
+    pub fn answer(&self) -> &Value {
+        &self.answer
+    }
+
+    pub fn vars(&self) -> &Vars {
+        &self.vars
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl Default for Evaluator {
+    fn default() -> Evaluator {
+        Evaluator::new()
+    }
+}
+
+impl Evaluator {
+    pub fn builder() -> EvaluatorBuilder {
+        EvaluatorBuilder { engine: Evaluator::new() }
+    }
+
+    pub fn new() -> Evaluator {
+        Self {
+            vars: Vars::default(),
+            answers: vec![],
+            answer: Value::Nothing,
+            display: DisplayMode::Default,
+            angle: AngleMode::Radians,
+            distance: DistanceUnit::Kilometres,
+            memos: Memos::default(),
+            symbolic: false,
+            functions: HashMap::new(),
+            halted: false,
+            warnings: vec![],
+            precision: None,
+            notation: Rc::new(notation::Plain),
+            strict: false,
+            history: true,
+            prompt: None,
+            sheet: None,
+            interruptible: false,
+            sandbox: false,
+            depth: 0,
+            executed: 0,
+            line: None,
+            program: Rc::default(),
+            position: 0,
+            checks: vec![],
+            snapshots: HashMap::new(),
+            tables: vec![],
+            consts: HashMap::new(),
+            deferred: HashMap::new(),
+            deferring: vec![],
+            cells: None,
+        }
+    }
+
+    // Logs an event, led by the line it came from when a whole statement is running.
+    fn event(&self, level: logging::Level, event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+        match self.line.filter(|_| logging::enabled(level)) {
+            Some(line) => logging::log(level, event, &[&[("line", &line as &dyn fmt::Display)], fields].concat()),
+            None => logging::log(level, event, fields),
+        }
+    }
+
+    fn record(&mut self) {
+        if self.history {
+            self.answers.push(self.answer.clone());
+        }
+    }
+
+    // Runs the body on a copy of the state for each parameter set, and keeps a table with a
+    // row for every variable the body binds and a column per set. The answer is the list of
+    // the sets' answers, while the variables stay as they were.
+    fn scenario(&mut self, scenario: &Scenario) -> Result<Value, EngineError> {
+        let mut names = vec![];
+        bindings(&scenario.body, &mut names);
+        let mut rows: Vec<Vec<String>> = names.iter().chain([&"answer".to_string()]).map(|name| vec![name.clone()]).collect();
+        let mut answers = vec![];
+        for (_, values) in &scenario.sets {
+            let mut branch = self.clone();
+            branch.warnings.clear();
+            branch.checks.clear();
+            branch.tables.clear();
+            // Bound like `= name`, so a `const` can't be rebound, with the body still folding in the answer.
+            for (param, value) in scenario.params.iter().zip(values) {
+                branch.answer = self.substitute(value.clone())?;
+                branch.bind(param)?;
+            }
+            branch.answer = self.answer.clone();
+            let answer = branch.evaluate_commands(&scenario.body)?;
+            for (row, name) in rows.iter_mut().zip(&names) {
+                row.push(branch.binding(name).map_or(Ok(String::new()), |value| branch.format(&value?))?);
+            }
+            let last = rows.last_mut().unwrap();
+            last.push(if answer == Value::Nothing { String::new() } else { branch.format(&answer)? });
+            answers.push(answer);
+            self.warnings.append(&mut branch.warnings);
+            self.checks.append(&mut branch.checks);
+            self.executed = branch.executed;
+        }
+        // A body ending in `= name` has no answer of its own to show.
+        if answers.iter().all(|answer| *answer == Value::Nothing) {
+            rows.pop();
+        }
+        let headers = core::iter::once(String::new()).chain(scenario.sets.iter().map(|(header, _)| header.clone())).collect();
+        self.tables.push(table::Table { headers, rows, labels: 1 });
+        Ok(match self.resolve_numbers(answers)? {
+            answers if answers.is_empty() => Value::Nothing,
+            answers => Value::List(answers),
+        })
+    }
+
+    // Takes on everything a worksheet can change from a snapshot. What only accumulates, like
+    // warnings, checks and other snapshots, and the program being run carry on.
+    // `= name`: moves the answer into a variable, unless a `const` already holds the name.
+    fn bind(&mut self, name: &str) -> Result<(), EngineError> {
+        if let Some(line) = self.consts.get(name) {
+            return Err(EngineError::ConstantReassigned(name.into(), *line));
+        }
+        if self.cells.as_ref().is_some_and(|cells| cells.pinned.contains(name)) && self.answer != Value::Nothing {
+            self.event(logging::Level::Info, "pinned", &[("name", &name)]);
+            self.answer = Value::Nothing;
+            return Ok(());
+        }
+        match &self.answer {
+            Value::Nothing => return Err(EngineError::NoValuesInQueue),
+            Value::Variable(_) => return Err(EngineError::EvaluatorAnswerShouldNotBeValueVariable),
+            value => {
+                self.event(logging::Level::Info, "set", &[("name", &name), ("value", &describe(value))]);
+                self.vars.insert(name, value.clone());
+                self.deferred.remove(name);
+            }
+        }
+        self.changed(name);
+        self.answer = Value::Nothing;
+        Ok(())
+    }
+
+    fn restore(&mut self, saved: &Evaluator) {
+        self.vars = saved.vars.clone();
+        self.consts = saved.consts.clone();
+        self.deferred = saved.deferred.clone();
+        self.cells = saved.cells.clone();
+        self.answers = saved.answers.clone();
+        self.answer = saved.answer.clone();
+        self.display = saved.display;
+        self.angle = saved.angle;
+        self.distance = saved.distance;
+        self.functions = saved.functions.clone();
+        self.sheet = saved.sheet.clone();
+    }
+
+    // Keeps the outcome for `qqc test`; outside it a failed check is a warning.
+    fn check(&mut self, check: Check) -> Result<(), EngineError> {
+        let failure = (!check.passed).then(|| format!("{} failed: expected {}, got {}", check.directive, check.expected, check.actual));
+        self.checks.push(check);
+        match failure {
+            Some(message) => self.warn(message),
+            None => Ok(()),
+        }
+    }
+
+    // Keeps a warning for after the run, or fails with it in strict mode.
+    fn warn(&mut self, message: String) -> Result<(), EngineError> {
+        if self.strict {
+            return Err(EngineError::Strict(message));
+        }
+        self.warnings.push(message);
+        Ok(())
+    }
+
+    // The answer as text in the current display mode and notation, rounded to the configured
+    // precision.
+    pub fn format(&self, answer: &Value) -> Result<String, EngineError> {
+        match (answer, self.display, self.precision) {
+            (Value::Operand(x), DisplayMode::Default, Some(digits)) => Ok(self.notation.format(round_significant(*x, digits))),
+            _ => format_value(answer, self.display, &*self.notation),
+        }
+    }
+
+    // Binds command line arguments after the script name as `$1`, `$2`, …
+    fn bind_arguments(&mut self, args: &[String]) -> Result<(), EngineError> {
+        for (i, arg) in args.iter().enumerate() {
+            self.vars.insert(format!("${}", i + 1), parse_float(arg)?);
+        }
+        Ok(())
+    }
+
+    // Presets variables from `-D name=value`, read the same way as literals.
+    fn define_variables(&mut self, defines: &[String]) -> Result<(), EngineError> {
+        for define in defines {
+            let (name, value) = define.split_once('=').ok_or_else(|| EngineError::InvalidDefine(define.clone()))?;
+            if name.is_empty() || value.is_empty() {
+                return Err(EngineError::InvalidDefine(define.clone()));
+            }
+            let name = parse_var_name(name)?;
+            if let Some(cells) = &mut self.cells {
+                cells.pinned.insert(name.clone());
+            }
+            self.vars.insert(name, parse_number(value)?);
+        }
+        Ok(())
+    }
+
+    // Binds `QQC_VAR_RATE=0.07` as `rate`, before any `-D` so the flag wins. Variables that
+    // aren't UTF-8 can't be ours, and are skipped.
+    #[cfg(feature = "std")]
+    fn bind_environment(&mut self, env: impl Iterator<Item = (OsString, OsString)>) -> Result<(), EngineError> {
+        for (key, value) in env {
+            let (Some(key), Some(value)) = (key.to_str(), value.to_str()) else { continue };
+            if let Some(name) = key.strip_prefix(ENV_PREFIX).filter(|name| !name.is_empty()) {
+                self.vars.insert(parse_var_name(&name.to_lowercase())?, parse_number(value)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn operate(&self, mut operands: Vec<Value>, operator: ArithOp) -> Result<Value, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut values = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                v => values.push(v),
+            }
+        }
+        // Long generated lines of plain numbers take the vectorized fold.
+        if values.len() >= fold::THRESHOLD && matches!(operator, ArithOp::Add | ArithOp::Multiply) {
+            let numbers: Option<Vec<f64>> = values.iter().map(|v| if let Value::Operand(x) = v { Some(*x) } else { None }).collect();
+            if let Some(numbers) = numbers {
+                return Ok(Value::Operand(if operator == ArithOp::Add { fold::sum(&numbers) } else { fold::product(&numbers) }));
+            }
+        }
+        let mut result = None;
+        for v in values {
+            result = Some(match result {
+                None => v,
+                Some(acc) => operator.combine(acc, v)?,
+            });
+        }
+        result.ok_or(EngineError::MissingOperands)
+    }
+
+    // Replaces a variable with the value bound to it.
+    fn substitute(&self, value: Value) -> Result<Value, EngineError> {
+        match value {
+            Value::Variable(var_name) if var_name.starts_with(constants::PREFIX) => match constants::lookup(&var_name) {
+                Some(value) => Ok(Value::Operand(value)),
+                None => Err(EngineError::MissingVariable(var_name.to_string())),
+            },
+            Value::Variable(var_name) => match self.vars.get_symbol(&var_name) {
+                Some(var_val) => Ok(var_val.clone()),
+                None if self.deferred.contains_key(&*var_name) => self.recompute(&var_name),
+                None => match argument_index(&var_name) {
+                    None if self.sheet.is_some() && csv::is_reference(&var_name) => self.sheet.as_ref().unwrap().lookup(&var_name),
+                    Some(index) => Err(EngineError::MissingArgument(index)),
+                    // Only an identifier can be an unknown, so `*` or `a=b` among the operands stays an
+                    // error. `x` is one even though it is also an alias of `*`, which only counts last.
+                    None if self.symbolic && tokens::is_identifier(&var_name) => Ok(Value::Symbolic(Expr::Var(var_name.to_string()))),
+                    None => Err(EngineError::MissingVariable(var_name.to_string())),
+                },
+            },
+            v => Ok(v),
+        }
+    }
+
+    // The value of a `defer` expression from the variables as they are now, worked out on a
+    // copy of the state so reading it changes nothing.
+    fn recompute(&self, name: &str) -> Result<Value, EngineError> {
+        if let Some(start) = self.deferring.iter().position(|deferring| deferring == name) {
+            return Err(EngineError::DeferCycle(self.deferring[start..].iter().chain([&name.to_string()]).cloned().collect()));
+        }
+        if let Some(value) = self.cells.as_ref().and_then(|cells| cells.values.borrow().get(name).cloned()) {
+            return Ok(value);
+        }
+        let mut engine = self.clone();
+        engine.answer = Value::Nothing;
+        engine.answers.clear();
+        engine.deferring.push(name.into());
+        let value = match engine.evaluate_commands(&self.deferred[name].body)? {
+            Value::Nothing => return Err(EngineError::NoValuesInQueue),
+            value => value,
+        };
+        // Keeps the cells worked out along the way too.
+        if let (Some(cells), Some(inner)) = (&self.cells, engine.cells) {
+            *cells.values.borrow_mut() = inner.values.into_inner();
+            cells.values.borrow_mut().insert(name.into(), value.clone());
+        }
+        Ok(value)
+    }
+
+    // With `--cells`, forgets the cells downstream of a name that was just bound.
+    fn changed(&mut self, name: &str) {
+        if let Some(cells) = &mut self.cells {
+            cells.values.get_mut().remove(name);
+            cells.invalidate(name, &self.deferred);
+        }
+    }
+
+    // What a name reads as: its variable, or its `defer` expression worked out now.
+    fn binding(&self, name: &str) -> Option<Result<Value, EngineError>> {
+        match self.vars.get(name) {
+            Some(value) => Some(Ok(value.clone())),
+            None => self.deferred.contains_key(name).then(|| self.recompute(name)),
+        }
+    }
+
+    // Resolves the accumulator followed by the operands to plain numbers.
+    fn resolve(&self, mut operands: Vec<Value>) -> Result<Vec<f64>, EngineError> {
+        operands.insert(0, self.answer.clone());
+        self.resolve_numbers(operands)
+    }
+
+    fn resolve_numbers(&self, values: Vec<Value>) -> Result<Vec<f64>, EngineError> {
+        let mut nums = vec![];
+        for v in values {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                Value::Operand(num) | Value::Significant(num, _) => nums.push(num),
+                Value::Integer(n) => nums.push(n.to_f64()),
+                Value::Rational(num, den) => nums.push(num as f64 / den as f64),
+                _ => return Err(EngineError::MismatchType),
+            }
+        }
+        Ok(nums)
+    }
+
+    fn operate_unary(&self, operands: Vec<Value>, operator: fn(f64) -> f64) -> Result<Value, EngineError> {
+        match self.resolve(operands)?[..] {
+            [] => Err(EngineError::MissingOperands),
+            [x] => Ok(Value::Operand(operator(x))),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    fn operate_binary(&self, operands: Vec<Value>, operator: fn(f64, f64) -> f64) -> Result<Value, EngineError> {
+        match self.resolve(operands)?[..] {
+            [] | [_] => Err(EngineError::MissingOperands),
+            [x, y] => Ok(Value::Operand(operator(x, y))),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // The single value for an integer command, as a whole number f64 holds exactly.
+    fn natural(&self, operands: Vec<Value>) -> Result<u64, EngineError> {
+        match self.resolve(operands)?[..] {
+            [] => Err(EngineError::MissingOperands),
+            [x] => to_natural(x),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    fn naturals(&self, operands: Vec<Value>) -> Result<(u64, u64), EngineError> {
+        match self.resolve(operands)?[..] {
+            [] | [_] => Err(EngineError::MissingOperands),
+            [n, k] => Ok((to_natural(n)?, to_natural(k)?)),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // `base exp modulus powmod`, computed exactly over big integers.
+    fn pow_mod(&self, mut operands: Vec<Value>) -> Result<Value, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut args = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                Value::Operand(x) => args.push(BigUint::from_u64(to_natural(x)?)),
+                Value::Integer(n) => args.push(n),
+                _ => return Err(EngineError::MismatchType),
+            }
+        }
+        match &args[..] {
+            [] | [_] | [_, _] => Err(EngineError::MissingOperands),
+            [_, _, modulus] if modulus.is_zero() => Err(EngineError::DivisionByZero),
+            [base, exp, modulus] => Ok(integer_value(base.pow_mod(exp, modulus))),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // `start end diff` is the duration from start to end.
+    fn diff(&self, mut operands: Vec<Value>) -> Result<Value, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut values = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                v => values.push(v),
+            }
+        }
+        match <[Value; 2]>::try_from(values) {
+            Ok([start, end]) => ArithOp::Subtract.combine(end, start),
+            Err(values) if values.len() < 2 => Err(EngineError::MissingOperands),
+            Err(_) => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // `size rate transfer-time` is how long moving `size` takes at `rate`.
+    fn transfer_time(&self, mut operands: Vec<Value>) -> Result<Value, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut values = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                Value::Quantity(amount, dim) => values.push((amount, dim)),
+                _ => return Err(EngineError::MismatchType),
+            }
+        }
+        match values[..] {
+            [] | [_] => Err(EngineError::MissingOperands),
+            [(size, units::DATA), (rate, units::RATE)] => Ok(Value::Quantity(size / rate, units::TIME)),
+            [_, _] => Err(EngineError::MismatchType),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // Inverts a quantity of dimension `from` into `to`; plain numbers count as `from` in base units.
+    fn reciprocal(&self, mut operands: Vec<Value>, from: Dim, to: Dim) -> Result<Value, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut values = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                Value::Operand(x) => values.push(x),
+                Value::Quantity(x, dim) if dim == from => values.push(x),
+                _ => return Err(EngineError::MismatchType),
+            }
+        }
+        match values[..] {
+            [] => Err(EngineError::MissingOperands),
+            [x] => Ok(Value::Quantity(1.0 / x, to)),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // Resistors in parallel: the reciprocal of the summed reciprocals.
+    fn parallel(&self, operands: Vec<Value>) -> Result<Value, EngineError> {
+        let values = self.resolve(operands)?;
+        if values.is_empty() {
+            return Err(EngineError::MissingOperands);
+        }
+        Ok(Value::Operand(1.0 / values.iter().map(|r| 1.0 / r).sum::<f64>()))
+    }
+
+    // The accumulator and operands as one run of numbers, with lists spliced in.
+    fn list_values(&self, mut operands: Vec<Value>) -> Result<Vec<f64>, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut values = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::List(items) => values.extend(items),
+                v => values.extend(self.resolve_numbers(vec![v])?),
+            }
+        }
+        Ok(values)
+    }
+
+    fn aggregate(&self, operands: Vec<Value>, aggregate: Aggregate) -> Result<Value, EngineError> {
+        let values = self.list_values(operands)?;
+        let least = if aggregate == Aggregate::StdDev { 2 } else { 1 };
+        if values.len() < least {
+            return Err(EngineError::MissingOperands);
+        }
+        Ok(match aggregate {
+            Aggregate::Sum => Value::Operand(threads::sum(&values)),
+            Aggregate::Mean => Value::Operand(threads::mean(&values)),
+            Aggregate::StdDev => Value::Operand(threads::stddev(&values)),
+            Aggregate::Sort => Value::List(threads::sort(values)),
+        })
+    }
+
+    fn extremum(&self, operands: Vec<Value>, fold: fn(&[f64]) -> f64) -> Result<Value, EngineError> {
+        let values = self.resolve(operands)?;
+        if values.is_empty() {
+            return Err(EngineError::MissingOperands);
+        }
+        Ok(Value::Operand(fold(&values)))
+    }
+
+    // `vin r1 r2 vdiv` is the output of a divider with r1 on top and r2 to ground.
+    fn voltage_divider(&self, operands: Vec<Value>) -> Result<Value, EngineError> {
+        match self.resolve(operands)?[..] {
+            [] | [_] | [_, _] => Err(EngineError::MissingOperands),
+            [vin, r1, r2] => Ok(Value::Operand(vin * r2 / (r1 + r2))),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // A coordinate pair, either as two operands or as a 2-element list left in the accumulator.
+    fn coordinates(&self, operands: Vec<Value>) -> Result<(f64, f64), EngineError> {
+        let values = match (&self.answer, operands.is_empty()) {
+            (Value::List(items), true) => items.clone(),
+            _ => self.resolve(operands)?,
+        };
+        match values[..] {
+            [] | [_] => Err(EngineError::MissingOperands),
+            [a, b] => Ok((a, b)),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    fn to_polar(&self, operands: Vec<Value>) -> Result<Value, EngineError> {
+        let (x, y) = self.coordinates(operands)?;
+        Ok(Value::List(vec![math::hypot(x, y), self.angle.angle(math::atan2(y, x))]))
+    }
+
+    // The single value a function of one variable applies to.
+    fn argument(&self, operands: Vec<Value>) -> Result<f64, EngineError> {
+        match self.resolve(operands)?[..] {
+            [] => Err(EngineError::MissingOperands),
+            [x] => Ok(x),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    // `sin`, `cos` and `tan` of an angle in the current angle mode.
+    fn trig(&self, operands: Vec<Value>, function: Trig) -> Result<Value, EngineError> {
+        let angle = self.argument(operands)?;
+        // Whole quarter turns in degrees are exact, so `90 cos` is 0 rather than 6e-17.
+        if self.angle == AngleMode::Degrees && angle % 90.0 == 0.0 {
+            let (sin, cos) = [(0.0, 1.0), (1.0, 0.0), (0.0, -1.0), (-1.0, 0.0)][(angle / 90.0).rem_euclid(4.0) as usize];
+            return match function {
+                Trig::Sin => Ok(Value::Operand(sin)),
+                Trig::Cos => Ok(Value::Operand(cos)),
+                Trig::Tan if cos == 0.0 => Err(EngineError::OutOfRange),
+                Trig::Tan => Ok(Value::Operand(0.0)),
+            };
+        }
+        let x = self.angle.radians(angle);
+        Ok(Value::Operand(match function {
+            Trig::Sin => math::sin(x),
+            Trig::Cos => math::cos(x),
+            Trig::Tan => math::tan(x),
+        }))
+    }
+
+    fn inverse_trig(&self, operands: Vec<Value>, function: InverseTrig) -> Result<Value, EngineError> {
+        let x = self.argument(operands)?;
+        let radians = match function {
+            InverseTrig::Asin | InverseTrig::Acos if x.abs() > 1.0 => return Err(EngineError::OutOfRange),
+            InverseTrig::Asin => math::asin(x),
+            InverseTrig::Acos => math::acos(x),
+            InverseTrig::Atan => math::atan(x),
+        };
+        Ok(Value::Operand(self.angle.angle(radians)))
+    }
+
+    fn to_cartesian(&self, operands: Vec<Value>) -> Result<Value, EngineError> {
+        let (r, theta) = self.coordinates(operands)?;
+        let theta = self.angle.radians(theta);
+        Ok(Value::List(vec![r * math::cos(theta), r * math::sin(theta)]))
+    }
+
+    // Great-circle distance on a spherical Earth with the mean radius of 6371 km.
+    fn haversine(&self, operands: Vec<Value>) -> Result<Value, EngineError> {
+        let (lat1, lon1, lat2, lon2) = match self.resolve_numbers(operands)?[..] {
+            [lat1, lon1, lat2, lon2] => (lat1.to_radians(), lon1.to_radians(), lat2.to_radians(), lon2.to_radians()),
+            ref coordinates if coordinates.len() < 4 => return Err(EngineError::MissingOperands),
+            _ => return Err(EngineError::TooManyOperands),
+        };
+        let a = math::sin((lat2 - lat1) / 2.0).powi(2) + math::cos(lat1) * math::cos(lat2) * math::sin((lon2 - lon1) / 2.0).powi(2);
+        Ok(Value::Operand(self.distance.convert(2.0 * 6371.0 * math::asin(a.sqrt()))))
+    }
+
+    // `x n root` is the real nth root, so odd roots of negative values are negative, and
+    // `sqrt` is the same with n = 2. Exact powers give exact roots: `27 3 root` is 3.
+    fn root(&self, operands: Vec<Value>, degree: Option<f64>) -> Result<Value, EngineError> {
+        let (x, n) = match (&self.resolve(operands)?[..], degree) {
+            ([x], Some(n)) => (*x, n),
+            ([x, n], None) => (*x, *n),
+            ([], _) | ([_], None) => return Err(EngineError::MissingOperands),
+            _ => return Err(EngineError::TooManyOperands),
+        };
+        let odd = n.fract() == 0.0 && n % 2.0 != 0.0;
+        if n == 0.0 || x < 0.0 && !odd {
+            return Err(EngineError::OutOfRange);
+        }
+        let root = match n {
+            2.0 => x.abs().sqrt(),
+            _ => math::pow(x.abs(), 1.0 / n),
+        };
+        let nearest = root.round();
+        let root = if math::pow(nearest, n) == x.abs() { nearest } else { root };
+        Ok(Value::Operand(root.copysign(x)))
+    }
+
+    fn log(&self, operands: Vec<Value>, base: Option<f64>) -> Result<Value, EngineError> {
+        let (x, b) = match (&self.resolve(operands)?[..], base) {
+            ([x], Some(b)) => (*x, b),
+            ([x, b], None) => (*x, *b),
+            ([], _) | ([_], None) => return Err(EngineError::MissingOperands),
+            _ => return Err(EngineError::TooManyOperands),
+        };
+        if x <= 0.0 || b <= 0.0 || b == 1.0 {
+            return Err(EngineError::OutOfRange);
+        }
+        if b == core::f64::consts::E {
+            return Ok(Value::Operand(math::ln(x)));
+        }
+        let log = match b {
+            10.0 => math::log10(x),
+            2.0 => math::log2(x),
+            _ => math::ln(x) / math::ln(b),
+        };
+        // Exact powers of the base come out exact, so `1000 log` is 3 rather than 2.9999999999999996.
+        let nearest = log.round();
+        let log = if math::pow(b, nearest) == x { nearest } else { log };
+        Ok(Value::Operand(log))
+    }
+
+    // Checks whether a value lies within a tolerance of an expected value.
+    fn approx(&self, operands: Vec<Value>) -> Result<Value, EngineError> {
+        match self.resolve(operands)?[..] {
+            [] | [_] | [_, _] => Err(EngineError::MissingOperands),
+            [actual, expected, tolerance] => Ok(Value::Bool((actual - expected).abs() <= tolerance.abs())),
+            _ => Err(EngineError::TooManyOperands),
+        }
+    }
+
+    fn compare(&self, operands: Vec<Value>, comparison: Comparison) -> Result<Value, EngineError> {
+        let (a, b) = match self.resolve(operands)?[..] {
+            [a, b] => (a, b),
+            [] | [_] => return Err(EngineError::MissingOperands),
+            _ => return Err(EngineError::TooManyOperands),
+        };
+        Ok(Value::Bool(match comparison {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+        }))
+    }
+
+    fn simplify(&self) -> Result<Value, EngineError> {
+        match &self.answer {
+            Value::Symbolic(expr) => Ok(symbolic_value(expr.simplify())),
+            Value::Operand(num) => Ok(Value::Operand(*num)),
+            Value::Nothing => Err(EngineError::NoValuesInQueue),
+            _ => Err(EngineError::MismatchType),
+        }
+    }
+
+    fn derivative(&self, var: &str) -> Result<Value, EngineError> {
+        match &self.answer {
+            Value::Symbolic(expr) => Ok(symbolic_value(expr.derivative(var)?)),
+            Value::Operand(_) => Ok(Value::Operand(0.0)),
+            Value::Nothing => Err(EngineError::NoValuesInQueue),
+            _ => Err(EngineError::MismatchType),
+        }
+    }
+
+    // Solves `accumulator = target` (target defaults to 0) for its one unknown.
+    fn solve(&self, mut operands: Vec<Value>) -> Result<Value, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut sides = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                Value::Operand(num) => sides.push(Expr::Num(num)),
+                Value::Symbolic(expr) => sides.push(expr),
+                _ => return Err(EngineError::MismatchType),
+            }
+        }
+        let (_, solution) = match &sides[..] {
+            [] => return Err(EngineError::MissingOperands),
+            [lhs] => solve_linear(lhs, &Expr::Num(0.0))?,
+            [lhs, rhs] => solve_linear(lhs, rhs)?,
+            _ => return Err(EngineError::TooManyOperands),
+        };
+        Ok(Value::Operand(solution))
+    }
+
+    // A fresh accumulator that sees copies of the current variables and functions.
+    fn scope(&self) -> Evaluator {
+        let mut scope = Evaluator::new();
+        scope.vars = self.vars.clone();
+        scope.angle = self.angle;
+        scope.distance = self.distance;
+        scope.symbolic = self.symbolic;
+        scope.strict = self.strict;
+        scope.sheet = self.sheet.clone();
+        scope.interruptible = self.interruptible;
+        scope.sandbox = self.sandbox;
+        scope.depth = self.depth;
+        scope.functions = self.functions.clone();
+        scope.memos = Memos(self.memos.0.clone());
+        scope
+    }
+
+    // Runs a function body against a copy of the current variables with its parameters bound.
+    fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, EngineError> {
+        let function = self.functions.get(name).ok_or_else(|| EngineError::UnknownFunction(name.into()))?;
+        if args.len() < function.params.len() {
+            return Err(EngineError::MissingOperands);
+        }
+        if args.len() > function.params.len() {
+            return Err(EngineError::TooManyOperands);
+        }
+
+        // A memoized function only sees its parameters and the modes, so its result depends on nothing else.
+        let key = format!("{:?} {:?} {:?}", self.angle, self.distance, args);
+        if let Some(value) = self.memos.0.borrow().get(name).and_then(|cache| cache.get(&key)).filter(|_| function.memo) {
+            return Ok(value.clone());
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(EngineError::RecursionLimit(name.into()));
+        }
+        let mut scope = self.scope();
+        scope.depth += 1;
+        if function.memo {
+            scope.vars = Vars::default();
+        }
+        for (param, arg) in function.params.iter().zip(args) {
+            scope.vars.insert_symbol(param.clone(), arg);
+        }
+        let value = scope.evaluate_commands(&function.body)?;
+        if function.memo {
+            self.memos.0.borrow_mut().entry(name.into()).or_default().insert(key, value.clone());
+        }
+        Ok(value)
+    }
+
+    fn call_with_operands(&self, name: &str, mut operands: Vec<Value>) -> Result<Value, EngineError> {
+        operands.insert(0, self.answer.clone());
+        let mut args = vec![];
+        for v in operands {
+            match self.substitute(v)? {
+                Value::Nothing => {}
+                v => args.push(v),
+            }
+        }
+        self.call(name, args)
+    }
+
+    fn call_numeric(&self, name: &str, x: f64) -> Result<f64, EngineError> {
+        match self.call(name, vec![Value::Operand(x)])? {
+            Value::Operand(y) | Value::Significant(y, _) => Ok(y),
+            _ => Err(EngineError::MismatchType),
+        }
+    }
+
+    // Newton steps from a numerical derivative, falling back to bisection
+    // whenever a step would leave the bracketing interval.
+    fn find_root(&self, name: &str, bounds: Vec<Value>) -> Result<Value, EngineError> {
+        let bounds = self.resolve_numbers(bounds)?;
+        let (mut lo, mut hi, tolerance, max_iterations) = match bounds[..] {
+            [] | [_] => return Err(EngineError::MissingOperands),
+            [lo, hi] => (lo, hi, 1e-12, 100.0),
+            [lo, hi, tolerance] => (lo, hi, tolerance, 100.0),
+            [lo, hi, tolerance, max_iterations] => (lo, hi, tolerance, max_iterations),
+            _ => return Err(EngineError::TooManyOperands),
+        };
+
+        let mut f_lo = self.call_numeric(name, lo)?;
+        let f_hi = self.call_numeric(name, hi)?;
+        if f_lo == 0.0 {
+            return Ok(Value::Operand(lo));
+        }
+        if f_hi == 0.0 {
+            return Ok(Value::Operand(hi));
+        }
+        if f_lo.signum() == f_hi.signum() {
+            return Err(EngineError::NoRootInInterval);
+        }
+
+        let mut x = (lo + hi) / 2.0;
+        for _ in 0..max_iterations as usize {
+            let fx = self.call_numeric(name, x)?;
+            if fx == 0.0 || (hi - lo).abs() < tolerance {
+                return Ok(Value::Operand(x));
+            }
+            if fx.signum() == f_lo.signum() {
+                lo = x;
+                f_lo = fx;
+            } else {
+                hi = x;
+            }
+
+            let h = tolerance.max(x.abs() * 1e-8);
+            let slope = (self.call_numeric(name, x + h)? - fx) / h;
+            let newton = x - fx / slope;
+            let next = if newton.is_finite() && newton > lo.min(hi) && newton < lo.max(hi) {
+                newton
+            } else {
+                (lo + hi) / 2.0
+            };
+            if (next - x).abs() < tolerance {
+                return Ok(Value::Operand(next));
+            }
+            x = next;
+        }
+        Err(EngineError::NoConvergence)
+    }
+
+    // Adaptive Simpson quadrature of a one-parameter function over [a, b].
+    fn integrate(&self, name: &str, bounds: Vec<Value>) -> Result<Value, EngineError> {
+        let (a, b, tolerance) = match self.resolve_numbers(bounds)?[..] {
+            [] | [_] => return Err(EngineError::MissingOperands),
+            [a, b] => (a, b, 1e-10),
+            [a, b, tolerance] => (a, b, tolerance),
+            _ => return Err(EngineError::TooManyOperands),
+        };
+
+        let (fa, fb) = (self.call_numeric(name, a)?, self.call_numeric(name, b)?);
+        let m = (a + b) / 2.0;
+        let fm = self.call_numeric(name, m)?;
+        let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+        Ok(Value::Operand(self.simpson(name, [(a, fa), (m, fm), (b, fb)], whole, tolerance, 50)?))
+    }
+
+    // `points` are (x, f(x)) at the start, middle and end of the interval.
+    fn simpson(&self, name: &str, points: [(f64, f64); 3], whole: f64, tolerance: f64, depth: u32) -> Result<f64, EngineError> {
+        let [(a, fa), (m, fm), (b, fb)] = points;
+        let (lm, rm) = ((a + m) / 2.0, (m + b) / 2.0);
+        let (flm, frm) = (self.call_numeric(name, lm)?, self.call_numeric(name, rm)?);
+        let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+        let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+        let delta = left + right - whole;
+        if depth == 0 || delta.abs() <= 15.0 * tolerance {
+            return Ok(left + right + delta / 15.0);
+        }
+        Ok(self.simpson(name, [(a, fa), (lm, flm), (m, fm)], left, tolerance / 2.0, depth - 1)?
+            + self.simpson(name, [(m, fm), (rm, frm), (b, fb)], right, tolerance / 2.0, depth - 1)?)
+    }
+
+    // Evaluates `body` once per integer index in the inclusive range and folds the results.
+    fn series(&self, operator: ArithOp, index: &Symbol, bounds: Vec<Value>, body: &[Command]) -> Result<Value, EngineError> {
+        let (first, last) = match self.resolve_numbers(bounds)?[..] {
+            [] | [_] => return Err(EngineError::MissingOperands),
+            [first, last] => (first, last),
+            _ => return Err(EngineError::TooManyOperands),
+        };
+        if first.fract() != 0.0 || last.fract() != 0.0 {
+            return Err(EngineError::NotAnInteger);
+        }
+
+        let mut result = Value::Operand(if operator == ArithOp::Multiply { 1.0 } else { 0.0 });
+        for i in first as i64..=last as i64 {
+            let mut scope = self.scope();
+            scope.vars.insert_symbol(index.clone(), Value::Operand(i as f64));
+            result = operator.combine(result, scope.evaluate_commands(body)?)?;
+        }
+        Ok(result)
+    }
+
+    // Runs commands for their answer, leaving where an error came from to the caller.
+    fn evaluate_commands(&mut self, commands: &[Command]) -> Result<Value, EngineError> {
+        self.evaluate_prompting(commands, &mut 0)
+    }
+
+    // `next` is left at the command that failed.
+    fn evaluate_prompting(&mut self, commands: &[Command], next: &mut usize) -> Result<Value, EngineError> {
+        let mut asked = HashSet::new();
+        loop {
+            match (self.evaluate_from(commands, next), self.prompt) {
+                // Bound at the prompt, the variable is there when the failed command runs again.
+                (Err(EngineError::MissingVariable(name)), Some(prompt)) if asked.insert(name.clone()) => {
+                    let value = prompt(&name).ok_or(EngineError::MissingVariable(name.clone()))?;
+                    self.vars.insert(name, value);
+                }
+                (result, _) => return result,
+            }
+        }
+    }
+
+    // Runs `commands` from index `next`, which is left at the command that failed, if any.
+    fn evaluate_from(&mut self, commands: &[Command], next: &mut usize) -> Result<Value, EngineError> {
+            
+        fn ulp(x: f64) -> f64 {
+            let x = x.abs();
+            if x == f64::MAX {
+                x - x.next_down()
+            } else {
+                x.next_up() - x
+            }
+        }
+        fn next_after(x: f64, toward: f64) -> f64 {
+            if x < toward {
+                x.next_up()
+            } else if x > toward {
+                x.next_down()
+            } else {
+                toward
+            }
+        }
+
+        for command in &commands[*next..] {
+            if self.halted {
+                break;
+            }
+            if self.interruptible && interrupt::take() {
+                return Err(EngineError::Interrupted);
+            }
+            if let Some(keyword) = command.privileged().filter(|_| self.sandbox) {
+                return Err(EngineError::NotPermitted(keyword.into()));
+            }
+            self.executed += 1;
+            match command {
+                Command::SetVar(name) => {
+                    self.bind(name)?;
+                    self.record();
+                }
+                Command::Const(name, line) => {
+                    self.bind(name)?;
+                    self.consts.insert(name.clone(), *line);
+                    self.record();
+                }
+                Command::Display(mode) => {
+                    self.display = *mode;
+                    self.record();
+                }
+                Command::Add(operands) => {
+                    self.answer = self.operate(operands.to_vec(), ArithOp::Add)?;
+                    self.record();
+                }
+                Command::Subtract(operands) => {
+                    self.answer = self.operate(operands.to_vec(), ArithOp::Subtract)?;
+                    self.record();
+                }
+                Command::Multiply(operands) => {
+                    self.answer = self.operate(operands.to_vec(), ArithOp::Multiply)?;
+                    self.record();
+                }
+                Command::Divide(operands) => {
+                    self.answer = self.operate(operands.to_vec(), ArithOp::Divide)?;
+                    self.record();
+                }
+                Command::Power(operands) => {
+                    self.answer = self.operate(operands.to_vec(), ArithOp::Power)?;
+                    self.record();
+                }
+                Command::Modulo(operands) => {
+                    self.answer = self.operate(operands.to_vec(), ArithOp::Modulo)?;
+                    self.record();
+                }
+                Command::Ulp(operands) => {
+                    self.answer = self.operate_unary(operands.to_vec(), ulp)?;
+                    self.record();
+                }
+                Command::Sqrt(operands) => {
+                    self.answer = self.root(operands.to_vec(), Some(2.0))?;
+                    self.record();
+                }
+                Command::Root(operands) => {
+                    self.answer = self.root(operands.to_vec(), None)?;
+                    self.record();
+                }
+                Command::Log(operands, base) => {
+                    self.answer = self.log(operands.to_vec(), *base)?;
+                    self.record();
+                }
+                Command::NextAfter(operands) => {
+                    self.answer = self.operate_binary(operands.to_vec(), next_after)?;
+                    self.record();
+                }
+                Command::Approx(operands) => {
+                    self.answer = self.approx(operands.to_vec())?;
+                    self.record();
+                }
+                Command::Compare(operands, comparison) => {
+                    self.answer = self.compare(operands.to_vec(), *comparison)?;
+                    self.record();
+                }
+                Command::Trig(operands, function) => {
+                    self.answer = self.trig(operands.to_vec(), *function)?;
+                    self.record();
+                }
+                Command::InverseTrig(operands, function) => {
+                    self.answer = self.inverse_trig(operands.to_vec(), *function)?;
+                    self.record();
+                }
+                Command::Simplify => {
+                    self.answer = self.simplify()?;
+                    self.record();
+                }
+                Command::Derivative(var) => {
+                    self.answer = self.derivative(var)?;
+                    self.record();
+                }
+                Command::Solve(operands) => {
+                    self.answer = self.solve(operands.to_vec())?;
+                    self.record();
+                }
+                Command::Define(name, function) => {
+                    self.event(logging::Level::Info, "define", &[("name", name), ("params", &function.params.iter().map(Symbol::name).collect::<Vec<_>>().join(" "))]);
+                    self.memos.0.borrow_mut().remove(name);
+                    if self.functions.insert(name.clone(), function.clone()).is_some() {
+                        self.warn(format!("function '{}' is redefined", name))?;
+                    }
+                    self.record();
+                }
+                Command::Call(name, operands) => {
+                    self.answer = self.call_with_operands(name, operands.to_vec())?;
+                    self.record();
+                }
+                Command::FindRoot(name, bounds) => {
+                    self.answer = self.find_root(name, bounds.to_vec())?;
+                    self.record();
+                }
+                Command::Integrate(name, bounds) => {
+                    self.answer = self.integrate(name, bounds.to_vec())?;
+                    self.record();
+                }
+                Command::Series(operator, index, bounds, body) => {
+                    self.answer = self.series(*operator, index, bounds.to_vec(), body)?;
+                    self.record();
+                }
+                Command::IsPrime(operands) => {
+                    self.answer = Value::Bool(integer::is_prime(self.natural(operands.to_vec())?));
+                    self.record();
+                }
+                Command::Factor(operands) => {
+                    let factors = integer::factor(self.natural(operands.to_vec())?);
+                    self.answer = Value::List(factors.into_iter().map(|p| p as f64).collect());
+                    self.record();
+                }
+                Command::Choose(operands) => {
+                    let (n, k) = self.naturals(operands.to_vec())?;
+                    self.answer = integer_value(integer::choose(n, k));
+                    self.record();
+                }
+                Command::Permute(operands) => {
+                    let (n, k) = self.naturals(operands.to_vec())?;
+                    self.answer = integer_value(integer::permute(n, k));
+                    self.record();
+                }
+                Command::PowMod(operands) => {
+                    self.answer = self.pow_mod(operands.to_vec())?;
+                    self.record();
+                }
+                Command::Diff(operands) => {
+                    self.answer = self.diff(operands.to_vec())?;
+                    self.record();
+                }
+                Command::TransferTime(operands) => {
+                    self.answer = self.transfer_time(operands.to_vec())?;
+                    self.record();
+                }
+                Command::Reciprocal(operands, from, to) => {
+                    self.answer = self.reciprocal(operands.to_vec(), *from, *to)?;
+                    self.record();
+                }
+                Command::Parallel(operands) => {
+                    self.answer = self.parallel(operands.to_vec())?;
+                    self.record();
+                }
+                Command::Aggregate(operands, aggregate) => {
+                    self.answer = self.aggregate(operands.to_vec(), *aggregate)?;
+                    self.record();
+                }
+                Command::Min(operands) => {
+                    self.answer = self.extremum(operands.to_vec(), fold::min)?;
+                    self.record();
+                }
+                Command::Max(operands) => {
+                    self.answer = self.extremum(operands.to_vec(), fold::max)?;
+                    self.record();
+                }
+                Command::VoltageDivider(operands) => {
+                    self.answer = self.voltage_divider(operands.to_vec())?;
+                    self.record();
+                }
+                Command::Angle(mode) => {
+                    self.angle = *mode;
+                    // Cells with trigonometry read the angle mode too.
+                    if let Some(cells) = &mut self.cells {
+                        cells.values.get_mut().clear();
+                    }
+                }
+                Command::ToPolar(operands) => {
+                    self.answer = self.to_polar(operands.to_vec())?;
+                    self.record();
+                }
+                Command::ToCartesian(operands) => {
+                    self.answer = self.to_cartesian(operands.to_vec())?;
+                    self.record();
+                }
+                Command::Distance(unit) => {
+                    self.distance = *unit;
+                }
+                Command::Haversine(operands) => {
+                    self.answer = self.haversine(operands.to_vec())?;
+                    self.record();
+                }
+                Command::LoadCsv(path) => {
+                    let text = read_file(path)?;
+                    self.sheet = Some(Rc::new(csv::parse(&text)));
+                    self.record();
+                }
+                Command::LoadJson(path, selector) => {
+                    let text = read_file(path)?;
+                    self.answer = select_numbers(&json::parse(&text)?, selector, EngineError::InvalidJson)?;
+                    self.record();
+                }
+                Command::LoadConfig(path, selector) => {
+                    let text = read_file(path)?;
+                    self.answer = select_numbers(&config::parse(path, &text)?, selector, EngineError::InvalidConfig)?;
+                    self.record();
+                }
+                #[cfg(feature = "net")]
+                Command::Fetch(url, selector) => {
+                    self.answer = net::fetch(url, selector, |text| select_numbers(&json::parse(text)?, selector, EngineError::InvalidJson))?;
+                    self.record();
+                }
+                #[cfg(feature = "xlsx")]
+                Command::LoadXlsx(path, sheet, range) => {
+                    let archive = std::fs::read(path).map_err(|_| EngineError::CannotRead(path.clone()))?;
+                    self.answer = xlsx::load(&archive, sheet, range)?;
+                    self.record();
+                }
+                Command::MolarMass(formula) => {
+                    let mass = chemistry::molar_mass(formula).ok_or_else(|| EngineError::UnknownFormula(formula.clone()))?;
+                    self.answer = Value::Operand(mass);
+                    self.record();
+                }
+                Command::Exit(value) => {
+                    if let Some(value) = value {
+                        self.answer = self.substitute(value.clone())?;
+                    }
+                    self.record();
+                    self.halted = true;
+                }
+                Command::Fail(message) => return Err(EngineError::User(message.clone())),
+                Command::Expect(expected) => {
+                    let expected = self.substitute(expected.clone())?;
+                    let passed = self.answer == expected || matches!(self.compare(vec![expected.clone()], Comparison::Equal), Ok(Value::Bool(true)));
+                    let (expected, actual) = (describe(&expected), describe(&self.answer));
+                    self.check(Check { directive: format!("expect {}", expected), expected, actual, passed })?;
+                }
+                Command::Assert(text, body) => {
+                    // The condition leaves the answer it read untouched.
+                    let (answer, answers) = (self.answer.clone(), self.answers.len());
+                    let result = self.evaluate_commands(body)?;
+                    self.answer = answer;
+                    self.answers.truncate(answers);
+                    let actual = format!("{} for the answer {}", describe(&result), describe(&self.answer));
+                    self.check(Check { directive: format!("assert {}", text), expected: "true".into(), actual, passed: result == Value::Bool(true) })?;
+                }
+                Command::Defer(name, deferred) => {
+                    if let Some(line) = self.consts.get(name) {
+                        return Err(EngineError::ConstantReassigned(name.clone(), *line));
+                    }
+                    self.event(logging::Level::Info, "defer", &[("name", name), ("expr", &deferred.text)]);
+                    self.vars.remove(name);
+                    self.deferred.insert(name.clone(), deferred.clone());
+                    self.changed(name);
+                    self.record();
+                }
+                Command::Snapshot(name) => {
+                    self.snapshots.insert(name.clone(), Rc::new(self.clone()));
+                }
+                Command::Restore(name) => {
+                    let saved = self.snapshots.get(name).ok_or_else(|| EngineError::UnknownSnapshot(name.clone()))?;
+                    self.restore(&saved.clone());
+                }
+                Command::Vars(pattern) => {
+                    let mut names: Vec<_> = self.vars.keys().chain(self.deferred.keys().map(String::as_str)).filter(|name| matches_pattern(name, pattern)).map(String::from).collect();
+                    names.sort();
+                    // A deferred value that cannot be worked out yet shows why instead.
+                    let rows = names
+                        .into_iter()
+                        .map(|name| match self.binding(&name).expect("listed names are bound") {
+                            Ok(value) => Ok(vec![name, self.format(&value)?]),
+                            Err(err) => Ok(vec![name, format!("({})", err)]),
+                        })
+                        .collect::<Result<_, EngineError>>()?;
+                    self.tables.push(table::Table { headers: vec!["name".into(), "value".into()], rows, labels: 1 });
+                }
+                Command::Unset(pattern) => {
+                    // Constants stay, like they do against `=`.
+                    let removed: Vec<String> = self.vars.keys().filter(|name| !self.consts.contains_key(*name))
+                        .chain(self.deferred.keys().map(String::as_str))
+                        .filter(|name| matches_pattern(name, pattern))
+                        .map(String::from)
+                        .collect();
+                    self.vars.retain(|name, _| !matches_pattern(name, pattern) || self.consts.contains_key(name));
+                    self.deferred.retain(|name, _| !matches_pattern(name, pattern));
+                    // Cells reading a removed name can't keep the value they worked out from it.
+                    for name in &removed {
+                        self.changed(name);
+                    }
+                    if removed.is_empty() {
+                        self.warn(format!("unset {} matched no variables", pattern))?;
+                    }
+                }
+                Command::Scenario(scenario) => {
+                    self.answer = self.scenario(scenario)?;
+                    self.record();
+                }
+                Command::Try(body, recover) => {
+                    // A failed block leaves no trace, so the fallback starts from the same state.
+                    let (vars, answer, answers) = (self.vars.clone(), self.answer.clone(), self.answers.len());
+                    if let Err(err) = self.evaluate_commands(body) {
+                        self.event(logging::Level::Warn, "recover", &[("error", &err)]);
+                        self.vars = vars;
+                        self.answer = answer;
+                        self.answers.truncate(answers);
+                        self.evaluate_commands(recover)?;
+                    }
+                }
+            }
+            if logging::enabled(logging::Level::Debug) {
+                self.event(logging::Level::Debug, "execute", &[("answer", &describe(&self.answer))]);
+            }
+            *next += 1;
+        }
+        Ok(self.answer.clone())
+    }
+}
+
+// Largest integer below which every whole f64 is exact.
+const MAX_EXACT_INTEGER: f64 = 9007199254740992.0;
+
+fn to_natural(x: f64) -> Result<u64, EngineError> {
+    if x.fract() != 0.0 {
+        return Err(EngineError::NotAnInteger);
+    }
+    if !(0.0..=MAX_EXACT_INTEGER).contains(&x) {
+        return Err(EngineError::OutOfRange);
+    }
+    Ok(x as u64)
+}
+
+// Exact integers become plain operands whenever an f64 can hold them.
+fn integer_value(n: BigUint) -> Value {
+    match n.to_u64() {
+        Some(small) if small as f64 <= MAX_EXACT_INTEGER => Value::Operand(small as f64),
+        _ => Value::Integer(n),
+    }
+}
+
+// Fractions with a denominator of one become plain operands.
+fn rational_value((num, den): (i64, i64)) -> Value {
+    if den == 1 {
+        Value::Operand(num as f64)
+    } else {
+        Value::Rational(num, den)
+    }
+}
+
+// Symbolic results that reduce to a plain number become operands again.
+fn symbolic_value(expr: Expr) -> Value {
+    match expr {
+        Expr::Num(num) => Value::Operand(num),
+        expr => Value::Symbolic(expr),
+    }
+}
+
+// Shows an integer as its two's-complement bit pattern in hex, `bits` wide.
+fn format_twos_complement(ans: f64, bits: u32, signed: bool) -> Result<String, EngineError> {
+    if ans.fract() != 0.0 {
+        return Err(EngineError::NotAnInteger);
+    }
+    let (min, max) = if signed {
+        (-(2f64.powi(bits as i32 - 1)), 2f64.powi(bits as i32 - 1) - 1.0)
+    } else {
+        (0.0, 2f64.powi(bits as i32) - 1.0)
+    };
+    if ans < min || ans > max {
+        return Err(EngineError::OutOfRange);
+    }
+    let pattern = (ans as i64 as u64) & (u64::MAX >> (64 - bits));
+    Ok(format!("0x{:0width$X}", pattern, width = (bits / 4) as usize))
+}
+
+// Breaks an f64 into its IEEE-754 sign, exponent and mantissa fields.
+fn format_bits(ans: f64) -> String {
+    let raw = ans.to_bits();
+    let sign = raw >> 63;
+    let exponent = (raw >> 52) & 0x7FF;
+    let mantissa = raw & 0xF_FFFF_FFFF_FFFF;
+    format!(
+        "value    {:?}\nsign     {}\nexponent {:011b} ({})\nmantissa {:052b}\nhex      0x{:016X}",
+        ans,
+        sign,
+        exponent,
+        exponent as i64 - 1023,
+        mantissa,
+        raw
+    )
+}
+
+// The power of ten of the leading digit, treating zero as magnitude 0.
+fn magnitude(v: f64) -> i32 {
+    if v == 0.0 {
+        0
+    } else {
+        v.abs().log10().floor() as i32
+    }
+}
+
+fn round_significant(x: f64, digits: u32) -> f64 {
+    let scale = 10f64.powi(digits as i32 - 1 - magnitude(x));
+    if x.is_finite() && scale.is_finite() { (x * scale).round() / scale } else { x }
+}
+
+// Rounds to `digits` significant figures and keeps significant trailing zeros.
+fn format_significant(ans: f64, digits: u32) -> String {
+    format_decimals(ans, digits as i32 - 1 - magnitude(ans))
+}
+
+// Rounds to a decimal place, counting back from the point when `decimals` is negative.
+fn format_decimals(x: f64, decimals: i32) -> String {
+    if decimals >= 0 {
+        format!("{:.*}", decimals as usize, x)
+    } else {
+        let scale = 10f64.powi(-decimals);
+        format_number((x / scale).round() * scale)
+    }
+}
+
+// Writes the uncertainty with one significant digit, or two when it starts with a 1, and the
+// value to the same decimal place, the way a lab report would: `19.62 ± 0.04`.
+fn format_uncertain(ans: f64, err: f64, numbers: &dyn NumberFormatter) -> String {
+    if err == 0.0 || !err.is_finite() || !ans.is_finite() {
+        return format!("{} ± {}", numbers.format(ans), numbers.format(err));
+    }
+    let err = err.abs();
+    let digits = if err / 10f64.powi(magnitude(err)) < 2.0 { 2 } else { 1 };
+    let decimals = digits - 1 - magnitude(err);
+    // A value that rounds away entirely is 0, not -0.
+    let ans = if (ans * 10f64.powi(decimals)).abs() < 0.5 { 0.0 } else { ans };
+    format!("{} ± {}", format_decimals(ans, decimals), format_decimals(err, decimals))
+}
+
+fn format_mixed((num, den): (i64, i64)) -> String {
+    let sign = if num < 0 { "-" } else { "" };
+    let (whole, rem) = (num.unsigned_abs() / den as u64, num.unsigned_abs() % den as u64);
+    match (whole, rem) {
+        (whole, 0) => format!("{}{}", sign, whole),
+        (0, rem) => format!("{}{}/{}", sign, rem, den),
+        (whole, rem) => format!("{}{} {}/{}", sign, whole, rem, den),
+    }
+}
+
+// Rationals print exactly; other values only when rounding to a chosen fraction.
+fn format_as_mixed(answer: &Value, nearest: Option<u32>) -> Result<String, EngineError> {
+    let ratio = match (answer, nearest) {
+        (Value::Rational(num, den), None) => (*num, *den),
+        (value, Some(nearest)) => {
+            let x = match value {
+                Value::Rational(num, den) => *num as f64 / *den as f64,
+                Value::Operand(x) | Value::Significant(x, _) => *x,
+                _ => return Err(EngineError::MismatchType),
+            };
+            let scaled = (x * nearest as f64).round();
+            if scaled.abs() > MAX_EXACT_INTEGER {
+                return Err(EngineError::OutOfRange);
+            }
+            integer::reduce_ratio(scaled as i128, nearest as i128).ok_or(EngineError::OutOfRange)?
+        }
+        (value, None) => return format_answer(value, DisplayMode::Default),
+    };
+    Ok(format_mixed(ratio))
+}
+
+fn format_number(ans: f64) -> String {
+    if ans.fract() == 0.0 {
+        format!("{:?}", ans as i64)
+    } else {
+        format!("{:?}", ans)
+    }
+}
+
+// A value for logs and messages, falling back to its debug form when it has no plain display.
+fn describe(value: &Value) -> String {
+    format_answer(value, DisplayMode::Default).unwrap_or_else(|_| format!("{:?}", value))
+}
+
+fn format_answer(answer: &Value, display: DisplayMode) -> Result<String, EngineError> {
+    format_value(answer, display, &notation::Plain)
+}
+
+fn format_value(answer: &Value, display: DisplayMode, numbers: &dyn NumberFormatter) -> Result<String, EngineError> {
+    if let DisplayMode::Mixed(nearest) = display {
+        return format_as_mixed(answer, nearest);
+    }
+    match answer {
+        Value::Nothing => Err(EngineError::NoValuesInQueue),
+        Value::Operand(ans) => match display {
+            DisplayMode::Default => Ok(numbers.format(*ans)),
+            DisplayMode::I8 => format_twos_complement(*ans, 8, true),
+            DisplayMode::I16 => format_twos_complement(*ans, 16, true),
+            DisplayMode::I32 => format_twos_complement(*ans, 32, true),
+            DisplayMode::U32 => format_twos_complement(*ans, 32, false),
+            DisplayMode::Bits => Ok(format_bits(*ans)),
+            DisplayMode::Mixed(_) => unreachable!("mixed numbers are formatted above"),
+            DisplayMode::Clock | DisplayMode::Unit(_) => Err(EngineError::MismatchType),
+            DisplayMode::Base(radix) => {
+                if ans.fract() != 0.0 {
+                    return Err(EngineError::NotAnInteger);
+                }
+                let digits = BigUint::from_u64(to_natural(ans.abs())?).to_radix(radix);
+                Ok(if *ans < 0.0 { format!("-{}", digits) } else { digits })
+            }
+        },
+        Value::Variable(_) => Err(EngineError::EvaluatorAnswerShouldNotBeValueVariable),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Uncertain(ans, err) => match display {
+            DisplayMode::Default => Ok(format_uncertain(*ans, *err, numbers)),
+            _ => Err(EngineError::MismatchType),
+        },
+        Value::Significant(ans, digits) => match display {
+            DisplayMode::Default => Ok(format_significant(*ans, *digits)),
+            _ => format_value(&Value::Operand(*ans), display, numbers),
+        },
+        Value::Symbolic(expr) => match display {
+            DisplayMode::Default => Ok(expr.to_string()),
+            _ => Err(EngineError::MismatchType),
+        },
+        Value::List(items) => {
+            let items = items
+                .iter()
+                .map(|item| format_value(&Value::Operand(*item), display, numbers))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("[{}]", items.join(", ")))
+        }
+        Value::Integer(n) => match display {
+            DisplayMode::Default => Ok(numbers.format_integer(&n.to_string())),
+            DisplayMode::Base(radix) => Ok(n.to_radix(radix)),
+            _ => format_value(&Value::Operand(n.to_f64()), display, numbers),
+        },
+        Value::Rational(num, den) => match display {
+            DisplayMode::Default => Ok(format!("{}/{}", num, den)),
+            _ => format_value(&Value::Operand(*num as f64 / *den as f64), display, numbers),
+        },
+        Value::Quantity(value, dim) => match display {
+            DisplayMode::Default => Ok(units::format_quantity(*value, *dim)),
+            DisplayMode::Clock if *dim == units::TIME => Ok(units::format_clock(*value)),
+            DisplayMode::Unit(unit) => units::format_in_unit(*value, *dim, unit).ok_or(EngineError::MismatchType),
+            _ => Err(EngineError::MismatchType),
+        },
+        Value::Timestamp(seconds) => match display {
+            DisplayMode::Default => Ok(units::format_timestamp(*seconds)),
+            _ => Err(EngineError::MismatchType),
+        },
+    }
+}
+
+// A value given outside a program, like `-D rate=0.07`, where a name would only fail later as
+// a mismatched type. It isn't interned, since there is no program to read it.
+fn parse_number(input: &str) -> Result<Value, EngineError> {
+    parse_literal(input)?.ok_or_else(|| EngineError::InvalidNumber(input.into()))
+}
+
+fn parse_float(input: &str) -> Result<Value, EngineError> {
+    Ok(parse_literal(input)?.unwrap_or_else(|| Value::Variable(Symbol::intern(input))))
+}
+
+// The value a token spells out, or `None` for a name.
+fn parse_literal(input: &str) -> Result<Option<Value>, EngineError> {
+    if let Some((value, error)) = input.split_once('±').or_else(|| input.split_once("+-")) {
+        if let (Ok(value), Ok(error)) = (value.parse::<f64>(), error.parse::<f64>()) {
+            return Ok(Some(Value::Uncertain(value, error.abs())));
+        }
+    }
+
+    if let Some(value) = parse_radix(input) {
+        return Ok(Some(value));
+    }
+
+    if let Some(value) = parse_fraction(input) {
+        return Ok(Some(value));
+    }
+
+    if let Some(seconds) = units::parse_clock(input) {
+        return Ok(Some(Value::Quantity(seconds, units::TIME)));
+    }
+
+    if let Some(seconds) = units::parse_timestamp(input) {
+        return Ok(Some(Value::Timestamp(seconds)));
+    }
+
+    if let Some((amount, dim)) = units::parse_quantity(input) {
+        return Ok(Some(Value::Quantity(amount, dim)));
+    }
+
+    // Whole numbers with more digits than an f64 holds exactly stay exact, as radix literals do.
+    let digits = input.strip_prefix('-').unwrap_or(input);
+    if digits.len() > 15 && digits.bytes().all(|b| b.is_ascii_digit()) {
+        let n = BigUint::from_radix(digits, 10).ok_or_else(|| EngineError::InvalidNumber(input.into()))?;
+        let value = match integer_value(n) {
+            Value::Integer(n) if digits.len() != input.len() => match -n.to_f64() {
+                x if x.is_finite() => Value::Operand(x),
+                _ => return Err(EngineError::NumberOutOfRange(input.into())),
+            },
+            Value::Operand(x) if digits.len() != input.len() => Value::Operand(-x),
+            value => value,
+        };
+        return Ok(Some(value));
+    }
+
+    let result = input.parse::<f64>();
+
+    match result {
+        // Only `inf` itself may be infinite; `1e999999` overflowed, and `1e-999999` underflowed.
+        Ok(x) if x.is_infinite() && digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') => Err(EngineError::NumberOutOfRange(input.into())),
+        Ok(x) if x == 0.0 && digits.split(['e', 'E']).next().is_some_and(|mantissa| mantissa.bytes().any(|b| (b'1'..=b'9').contains(&b))) => {
+            Err(EngineError::NumberOutOfRange(input.into()))
+        }
+        Ok(x) => Ok(Some(Value::Operand(x))),
+        // Names like `4ac` may start with a digit, but `1_000`, `1.2.3` or `8r9` is a mistyped number.
+        _ if digits.starts_with(|c: char| c.is_ascii_digit()) && is_malformed_number(digits) => Err(EngineError::InvalidNumber(input.into())),
+        _ => Ok(None),
+    }
+}
+
+// Only number characters, or a radix literal with digits its radix doesn't have.
+fn is_malformed_number(token: &str) -> bool {
+    let radix = token.split_once('r').is_some_and(|(radix, digits)| {
+        !radix.is_empty() && radix.bytes().all(|b| b.is_ascii_digit()) && !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_alphanumeric())
+    });
+    radix || token.bytes().all(|b| b.is_ascii_digit() || b"_.eE+-".contains(&b))
+}
+
+// Fractions like `1/2` and mixed numbers like `3_1/4` or `-3_1/4`.
+fn parse_fraction(input: &str) -> Option<Value> {
+    let (whole, fraction) = match input.split_once('_') {
+        Some((whole, fraction)) => (Some(whole.parse::<i64>().ok()?), fraction),
+        None => (None, input),
+    };
+    let (num, den) = fraction.split_once('/')?;
+    let (num, den) = (num.parse::<u64>().ok()? as i128, den.parse::<u64>().ok()? as i128);
+    let ratio = match whole {
+        Some(whole) if whole < 0 || input.starts_with('-') => integer::reduce_ratio(whole as i128 * den - num, den)?,
+        Some(whole) => integer::reduce_ratio(whole as i128 * den + num, den)?,
+        None => integer::reduce_ratio(num, den)?,
+    };
+    Some(rational_value(ratio))
+}
+
+// Literals like `36rZZ` or `2r1011`: a decimal radix from 2 to 36, `r`, then the digits.
+fn parse_radix(input: &str) -> Option<Value> {
+    let (negative, input) = match input.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+    let (radix, digits) = input.split_once('r')?;
+    let radix = radix.parse::<u32>().ok().filter(|r| (2..=36).contains(r))?;
+    let n = BigUint::from_radix(digits, radix)?;
+    Some(match integer_value(n) {
+        Value::Operand(x) if negative => Value::Operand(-x),
+        Value::Integer(n) if negative => Value::Operand(-n.to_f64()),
+        value => value,
+    })
+}
+
+// Counts the significant digits written in a numeric literal like `0.0450` or `1.2e3`.
+fn count_significant(literal: &str) -> u32 {
+    let mantissa = literal.split(['e', 'E']).next().unwrap_or(literal);
+    let digits: String = mantissa.chars().filter(|c| c.is_ascii_digit() || *c == '.').collect();
+    let significant = if digits.contains('.') {
+        digits.replace('.', "").trim_start_matches('0').len()
+    } else {
+        digits.trim_start_matches('0').trim_end_matches('0').len()
+    };
+    significant.max(1) as u32
+}
+
+fn track_significant(command: &mut Command, tokens: &[&str]) {
+    // Prefix commands like `findroot f 0 1` keep their operands after the name.
+    let tokens = match command {
+        Command::FindRoot(_, _) | Command::Integrate(_, _) | Command::Series(_, _, _, _) => &tokens[2..],
+        _ => tokens,
+    };
+    if let Some(operands) = command.operands_mut() {
+        for (operand, token) in operands.iter_mut().zip(tokens) {
+            if let Value::Operand(num) = operand {
+                *operand = Value::Significant(*num, count_significant(token));
+            }
+        }
+    }
+}
+
+// Whether a variable name is picked by a `vars` or `unset` pattern: `*` for every name,
+// `loan.*` for the names under the `loan.` prefix, or one exact name.
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+// The index of a positional argument name like `$2`.
+fn argument_index(name: &str) -> Option<usize> {
+    name.strip_prefix('$')?.parse().ok().filter(|index| *index > 0)
+}
+
+fn parse_operands(operand_strings: &[&str]) -> Result<Vec<Value>, EngineError> {
+    operand_strings.iter().map(|s| parse_float(s)).collect()
+}
+
+fn parse_var_name(var_name: &str) -> Result<String, EngineError> {
+    if var_name.starts_with(constants::PREFIX) {
+        return Err(EngineError::ReadOnlyVariable(var_name.into()));
+    }
+    Ok(var_name.into())
+}
+
+// `= name`, or `= name const` and `const name` for a binding that cannot be reassigned.
+// `line` is the line the binding is on.
+fn parse_set_var(input: &[&str], line: usize) -> Result<Command, EngineError> {
+    let (name, constant) = match input {
+        ["=" | "const"] => return Err(EngineError::MissingVariableName),
+        ["=", name] => (name, false),
+        ["=", name, "const"] | ["const", name] => (name, true),
+        _ => return Err(EngineError::TooManyVariableNames),
+    };
+
+    let var_name = parse_var_name(name)?;
+
+    Ok(if constant { Command::Const(var_name, line) } else { Command::SetVar(var_name) })
+}
+
+fn parse_simplify(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() >= 2 {
+        return Err(EngineError::UnexpectedOperands);
+    }
+
+    Ok(Command::Simplify)
+}
+
+fn parse_solve(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Solve(operands))
+}
+
+fn parse_is_prime(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::IsPrime(operands))
+}
+
+fn parse_factor(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Factor(operands))
+}
+
+fn parse_choose(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Choose(operands))
+}
+
+fn parse_permute(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Permute(operands))
+}
+
+fn parse_pow_mod(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::PowMod(operands))
+}
+
+fn parse_diff(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Diff(operands))
+}
+
+fn parse_transfer_time(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::TransferTime(operands))
+}
+
+fn parse_reciprocal(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    match input.last() {
+        Some(&"hz-to-period") => Ok(Command::Reciprocal(operands, units::FREQUENCY, units::TIME)),
+        _ => Ok(Command::Reciprocal(operands, units::TIME, units::FREQUENCY)),
+    }
+}
+
+fn parse_parallel(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Parallel(operands))
+}
+
+// Like the folds, but a bare `sum` on its own line works on a list left in the accumulator.
+fn parse_aggregate(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().ok_or(EngineError::MissingOperands)?.1)?;
+
+    let aggregate = match input.last() {
+        Some(&"sum") => Aggregate::Sum,
+        Some(&"mean") => Aggregate::Mean,
+        Some(&"stddev") => Aggregate::StdDev,
+        _ => Aggregate::Sort,
+    };
+    Ok(Command::Aggregate(operands, aggregate))
+}
+
+fn parse_extremum(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    match input.last() {
+        Some(&"min") => Ok(Command::Min(operands)),
+        _ => Ok(Command::Max(operands)),
+    }
+}
+
+fn parse_voltage_divider(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::VoltageDivider(operands))
+}
+
+fn parse_angle(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() >= 2 {
+        return Err(EngineError::UnexpectedOperands);
+    }
+
+    match input[0] {
+        "deg" | "degrees" => Ok(Command::Angle(AngleMode::Degrees)),
+        _ => Ok(Command::Angle(AngleMode::Radians)),
+    }
+}
+
+fn parse_coordinates(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    match input.last() {
+        Some(&"to-polar") => Ok(Command::ToPolar(operands)),
+        _ => Ok(Command::ToCartesian(operands)),
+    }
+}
+
+fn parse_distance(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() >= 2 {
+        return Err(EngineError::UnexpectedOperands);
+    }
+
+    match input[0] {
+        "mi" => Ok(Command::Distance(DistanceUnit::Miles)),
+        _ => Ok(Command::Distance(DistanceUnit::Kilometres)),
+    }
+}
+
+fn parse_haversine(input: &[&str]) -> Result<Command, EngineError> {
+    let coordinates = parse_operands(&input[1..])?;
+
+    Ok(Command::Haversine(coordinates))
+}
+
+fn parse_molar_mass(input: &[&str]) -> Result<Command, EngineError> {
+    match input[1..] {
+        [] => Err(EngineError::MissingOperands),
+        [formula] => Ok(Command::MolarMass(formula.to_string())),
+        _ => Err(EngineError::TooManyOperands),
+    }
+}
+
+// `load-csv path`, where the path may be quoted to hold spaces.
+fn parse_load(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    Ok(Command::LoadCsv(input[1..].join(" ").trim_matches('"').to_string()))
+}
+
+// The number, or the list of numbers, a selector picks out of a loaded document.
+fn select_numbers(document: &json::Json, selector: &str, error: fn(String) -> EngineError) -> Result<Value, EngineError> {
+    let invalid = |what: &str| error(format!("{} {}", selector, what));
+    match document.select(selector).ok_or_else(|| invalid("is not in the document"))? {
+        json::Json::Number(n) => Ok(Value::Operand(*n)),
+        json::Json::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                json::Json::Number(n) => Ok(*n),
+                _ => Err(invalid("is not a list of numbers")),
+            })
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        _ => Err(invalid("is not a number")),
+    }
+}
+
+// The text of a file a program loads with `load-csv`, `load-json` or `load-config`.
+#[cfg(feature = "std")]
+fn read_file(path: &str) -> Result<String, EngineError> {
+    std::fs::read_to_string(path).map_err(|_| EngineError::CannotRead(path.into()))
+}
+
+// Without `std` there are no files, so every load fails as unreadable.
+#[cfg(not(feature = "std"))]
+fn read_file(path: &str) -> Result<String, EngineError> {
+    Err(EngineError::CannotRead(path.into()))
+}
+
+// The words after a prefix keyword, where a double-quoted field may hold spaces.
+fn quoted_fields<const N: usize>(input: &[&str]) -> Result<[String; N], EngineError> {
+    let line = input[1..].join(" ");
+    let mut fields = vec![];
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let (field, after) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').ok_or(EngineError::MissingOperands)?,
+            None => rest.split_once(' ').unwrap_or((rest, "")),
+        };
+        fields.push(field.to_string());
+        rest = after.trim_start();
+    }
+    match fields.len().cmp(&N) {
+        core::cmp::Ordering::Less => Err(EngineError::MissingOperands),
+        core::cmp::Ordering::Greater => Err(EngineError::TooManyOperands),
+        core::cmp::Ordering::Equal => Ok(fields.try_into().unwrap()),
+    }
+}
+
+// `load-xlsx path sheet range`
+#[cfg(feature = "xlsx")]
+fn parse_load_xlsx(input: &[&str]) -> Result<Command, EngineError> {
+    let [path, sheet, range] = quoted_fields(input)?;
+    Ok(Command::LoadXlsx(path, sheet, range))
+}
+
+// `load-json path .items[3].price`, `load-config path package.metadata.budget` and
+// `fetch url .rates.EUR`
+fn parse_load_document(input: &[&str]) -> Result<Command, EngineError> {
+    let [path, selector] = quoted_fields(input)?;
+    match input[0] {
+        "load-json" => Ok(Command::LoadJson(path, selector)),
+        #[cfg(feature = "net")]
+        "fetch" => Ok(Command::Fetch(path, selector)),
+        _ => Ok(Command::LoadConfig(path, selector)),
+    }
+}
+
+fn parse_exit(input: &[&str]) -> Result<Command, EngineError> {
+    match input[1..] {
+        [] => Ok(Command::Exit(None)),
+        [value] => Ok(Command::Exit(Some(parse_float(value)?))),
+        _ => Err(EngineError::TooManyOperands),
+    }
+}
+
+fn parse_vars(input: &[&str]) -> Result<Command, EngineError> {
+    let pattern = match (input[0], &input[1..]) {
+        ("vars", []) => "*".to_string(),
+        (_, []) => return Err(EngineError::MissingVariableName),
+        (_, [pattern]) => pattern.to_string(),
+        _ => return Err(EngineError::TooManyVariableNames),
+    };
+    Ok(if input[0] == "vars" { Command::Vars(pattern) } else { Command::Unset(pattern) })
+}
+
+fn parse_snapshot(input: &[&str]) -> Result<Command, EngineError> {
+    let name = match input[1..] {
+        [] => return Err(EngineError::MissingVariableName),
+        [name] => name.to_string(),
+        _ => return Err(EngineError::TooManyVariableNames),
+    };
+    Ok(if input[0] == "snapshot" { Command::Snapshot(name) } else { Command::Restore(name) })
+}
+
+fn parse_expect(input: &[&str]) -> Result<Command, EngineError> {
+    match input[1..] {
+        [] => Err(EngineError::MissingOperands),
+        [value] => Ok(Command::Expect(parse_float(value)?)),
+        _ => Err(EngineError::TooManyOperands),
+    }
+}
+
+// The condition is an ordinary line, so the answer comes first: `assert 10 <` means answer < 10.
+fn parse_assert(input: &[&str], functions: &mut HashSet<String>) -> Result<Command, EngineError> {
+    let text = input[1..].join(" ");
+    let body = parse_lines(core::iter::once(text.as_str()), &Cell::new(0), functions)?.commands;
+    if body.is_empty() {
+        return Err(EngineError::MissingOperands);
+    }
+    Ok(Command::Assert(text, body))
+}
+
+// `defer total "price qty *"`; the quotes are optional.
+fn parse_defer(input: &[&str], functions: &mut HashSet<String>) -> Result<Command, EngineError> {
+    let [_, name, expr @ ..] = input else { return Err(EngineError::MissingVariableName) };
+    let name = parse_var_name(name)?;
+    let text = expr.join(" ");
+    let text = text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(&text).trim().to_string();
+    let mut body = parse_lines(core::iter::once(text.as_str()), &Cell::new(0), functions)?.commands;
+    if body.is_empty() {
+        return Err(EngineError::MissingOperands);
+    }
+    let mut inputs = vec![];
+    for operand in body.iter_mut().filter_map(Command::operands_mut).flatten() {
+        if let Value::Variable(input) = operand {
+            if !inputs.iter().any(|name| name == &**input) {
+                inputs.push(input.to_string());
+            }
+        }
+    }
+    Ok(Command::Defer(name, Rc::new(Deferred { text, body, inputs })))
+}
+
+fn parse_error(line: &str) -> Result<Command, EngineError> {
+    let message = line.trim_start().strip_prefix("error").unwrap().trim();
+    let message = message.strip_prefix('"').and_then(|m| m.strip_suffix('"')).unwrap_or(message);
+
+    Ok(Command::Fail(message.to_string()))
+}
+
+fn parse_derivative(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingVariableName);
+    }
+    if input.len() >= 3 {
+        return Err(EngineError::TooManyVariableNames);
+    }
+
+    let var_name = parse_var_name(input[1])?;
+
+    Ok(Command::Derivative(var_name))
+}
+
+// Gathers the lines up to the `end` closing a block, allowing nested blocks.
+fn collect_block<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Result<String, EngineError> {
+    let mut body = vec![];
+    let mut depth = 0;
+    for line in lines {
+        match line.split_whitespace().next() {
+            Some("end") if depth == 0 => return Ok(body.join("\n")),
+            Some("end") => depth -= 1,
+            Some("def" | "memo" | "try" | "scenario") => depth += 1,
+            _ => {}
+        }
+        body.push(line);
+    }
+    Err(EngineError::UnterminatedBlock)
+}
+
+fn parse_try<'a>(
+    input: &[&str],
+    lines: &mut impl Iterator<Item = &'a str>,
+    functions: &mut HashSet<String>,
+) -> Result<Command, EngineError> {
+    if input.len() >= 2 {
+        return Err(EngineError::UnexpectedOperands);
+    }
+
+    let block = collect_block(lines)?;
+    let (mut body, mut recover) = (vec![], vec![]);
+    let mut recovering = false;
+    let mut depth = 0;
+    for line in block.lines() {
+        match line.split_whitespace().next() {
+            Some("recover") if depth == 0 => {
+                recovering = true;
+                body.push("");
+                recover.push("");
+                continue;
+            }
+            Some("end") => depth -= 1,
+            Some("def" | "memo" | "try" | "scenario") => depth += 1,
+            _ => {}
+        }
+        // Both sections keep a line for every line of the block, so errors point at the right one.
+        if recovering {
+            body.push("");
+            recover.push(line);
+        } else {
+            body.push(line);
+            recover.push("");
+        }
+    }
+
+    Ok(Command::Try(parse_program(&body.join("\n"), functions)?.commands, parse_program(&recover.join("\n"), functions)?.commands))
+}
+
+// `scenario rate 0.03 0.05 0.07` runs the block once per value of `rate`; with several
+// parameters, each set is comma-separated: `scenario rate,years 0.03,10 0.05,20`.
+fn parse_scenario<'a>(
+    input: &[&str],
+    lines: &mut impl Iterator<Item = &'a str>,
+    functions: &mut HashSet<String>,
+) -> Result<Command, EngineError> {
+    let [_, names, sets @ ..] = input else { return Err(EngineError::MissingVariableName) };
+    let params = names.split(',').map(parse_var_name).collect::<Result<Vec<_>, _>>()?;
+    if sets.is_empty() {
+        return Err(EngineError::MissingOperands);
+    }
+    let sets = sets
+        .iter()
+        .map(|set| {
+            let values = set.split(',').map(parse_float).collect::<Result<Vec<_>, _>>()?;
+            match values.len().cmp(&params.len()) {
+                core::cmp::Ordering::Less => Err(EngineError::MissingOperands),
+                core::cmp::Ordering::Greater => Err(EngineError::TooManyOperands),
+                core::cmp::Ordering::Equal => {
+                    let header = params.iter().zip(set.split(',')).map(|(param, value)| format!("{}={}", param, value)).collect::<Vec<_>>().join(" ");
+                    Ok((header, values))
+                }
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    let body = parse_program(&collect_block(lines)?, functions)?.commands;
+    Ok(Command::Scenario(Rc::new(Scenario { params, sets, body })))
+}
+
+fn parse_define<'a>(
+    input: &[&str],
+    lines: &mut impl Iterator<Item = &'a str>,
+    functions: &mut HashSet<String>,
+) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingVariableName);
+    }
+
+    let name = parse_var_name(input[1])?;
+    let conflicts = operators::conflicts(&name);
+    if !conflicts.is_empty() {
+        return Err(EngineError::NameTaken(name, conflicts));
+    }
+    let params = input[2..].iter().map(|p| parse_var_name(p).map(|name| Symbol::intern(&name))).collect::<Result<_, _>>()?;
+
+    // Registered before the body is parsed so the function can call itself.
+    functions.insert(name.clone());
+    let body = parse_program(&collect_block(lines)?, &mut functions.clone())?.commands;
+    let memo = input[0] == "memo";
+
+    Ok(Command::Define(name, Rc::new(Function { params, body, memo })))
+}
+
+fn parse_call(input: &[&str]) -> Result<Command, EngineError> {
+    let (name, operands) = input.split_last().unwrap();
+    let operands = parse_operands(operands)?;
+
+    Ok(Command::Call(name.to_string(), operands))
+}
+
+fn parse_find_root(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingVariableName);
+    }
+
+    let name = parse_var_name(input[1])?;
+    let bounds = parse_operands(&input[2..])?;
+
+    Ok(Command::FindRoot(name, bounds))
+}
+
+fn parse_integrate(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingVariableName);
+    }
+
+    let name = parse_var_name(input[1])?;
+    let bounds = parse_operands(&input[2..])?;
+
+    Ok(Command::Integrate(name, bounds))
+}
+
+// `sigma i 1 100 'i 2 ^` binds `i` over 1..=100 and sums the quoted line after it.
+fn parse_series(input: &[&str], functions: &HashSet<String>) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingVariableName);
+    }
+
+    let index = Symbol::intern(&parse_var_name(input[1])?);
+    let quote = input.iter().position(|t| t.starts_with('\'')).ok_or(EngineError::MissingOperands)?;
+    let bounds = parse_operands(&input[2..quote])?;
+
+    let mut expr = vec![input[quote].trim_start_matches('\'')];
+    expr.extend(&input[quote + 1..]);
+    let body = parse_program(&expr.join(" "), &mut functions.clone()).map_err(EngineError::unlocated)?.commands;
+
+    let operator = if input[0] == "prod" { ArithOp::Multiply } else { ArithOp::Add };
+
+    Ok(Command::Series(operator, index, bounds, body))
+}
+
+fn parse_to_base(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+    if input.len() >= 3 {
+        return Err(EngineError::TooManyOperands);
+    }
+
+    match input[1].parse::<u32>() {
+        Ok(radix) if (2..=36).contains(&radix) => Ok(Command::Display(DisplayMode::Base(radix))),
+        _ => Err(EngineError::OutOfRange),
+    }
+}
+
+fn parse_mixed(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() >= 3 {
+        return Err(EngineError::TooManyOperands);
+    }
+
+    let nearest = match input.get(1) {
+        None => None,
+        Some(n) => match n.parse::<u32>() {
+            Ok(n) if n >= 1 => Some(n),
+            _ => return Err(EngineError::OutOfRange),
+        },
+    };
+
+    Ok(Command::Display(DisplayMode::Mixed(nearest)))
+}
+
+fn parse_to_unit(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() >= 2 {
+        return Err(EngineError::UnexpectedOperands);
+    }
+
+    let name = input[0].trim_start_matches("to-");
+    let unit = units::find_unit(name).ok_or_else(|| EngineError::UnknownCommand(input[0].into()))?;
+
+    Ok(Command::Display(DisplayMode::Unit(unit)))
+}
+
+fn parse_display(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() >= 2 {
+        return Err(EngineError::UnexpectedOperands);
+    }
+
+    let mode = match input[0] {
+        "i8" => DisplayMode::I8,
+        "i16" => DisplayMode::I16,
+        "i32" => DisplayMode::I32,
+        "u32" => DisplayMode::U32,
+        "to-clock" => DisplayMode::Clock,
+        _ => DisplayMode::Bits,
+    };
+
+    Ok(Command::Display(mode))
+}
+
+fn parse_add(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Add(operands))
+}
+
+fn parse_subtract(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Subtract(operands))
+}
+
+fn parse_multiply(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Multiply(operands))
+}
+
+fn parse_divide(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Divide(operands))
+}
+
+fn parse_power(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Power(operands))
+}
+
+fn parse_modulo(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Modulo(operands))
+}
+
+fn parse_ulp(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Ulp(operands))
+}
+
+fn parse_sqrt(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Sqrt(operands))
+}
+
+fn parse_root(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Root(operands))
+}
+
+fn parse_log(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    let base = match input.last() {
+        Some(&"ln") => Some(core::f64::consts::E),
+        Some(&"log") => Some(10.0),
+        Some(&"log2") => Some(2.0),
+        _ => None,
+    };
+    Ok(Command::Log(operands, base))
+}
+
+fn parse_next_after(input: &[&str]) -> Result<Command, EngineError> {
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::NextAfter(operands))
+}
+
+fn parse_compare(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    let comparison = match input.last() {
+        Some(&"<") => Comparison::Less,
+        Some(&"<=") => Comparison::LessOrEqual,
+        Some(&">") => Comparison::Greater,
+        Some(&">=") => Comparison::GreaterOrEqual,
+        Some(&"==") => Comparison::Equal,
+        _ => Comparison::NotEqual,
+    };
+    Ok(Command::Compare(operands, comparison))
+}
+
+// Only the names in the operator table reach these, so any other is a table entry without a function.
+fn parse_trig(input: &[&str]) -> Result<Command, EngineError> {
+    let (name, operands) = input.split_last().unwrap();
+    let function = match *name {
+        "sin" => Trig::Sin,
+        "cos" => Trig::Cos,
+        "tan" => Trig::Tan,
+        name => return Err(EngineError::UnknownCommand(name.into())),
+    };
+    Ok(Command::Trig(parse_operands(operands)?, function))
+}
+
+fn parse_inverse_trig(input: &[&str]) -> Result<Command, EngineError> {
+    let (name, operands) = input.split_last().unwrap();
+    let function = match *name {
+        "asin" => InverseTrig::Asin,
+        "acos" => InverseTrig::Acos,
+        "atan" => InverseTrig::Atan,
+        name => return Err(EngineError::UnknownCommand(name.into())),
+    };
+    Ok(Command::InverseTrig(parse_operands(operands)?, function))
+}
+
+fn parse_approx(input: &[&str]) -> Result<Command, EngineError> {
+    if input.len() <= 1 {
+        return Err(EngineError::MissingOperands);
+    }
+
+    let operands = parse_operands(input.split_last().unwrap().1)?;
+
+    Ok(Command::Approx(operands))
+}
+
+pub fn parse(input: &str) -> Result<Program, EngineError> {
+    parse_program(input, &mut HashSet::new())
+}
+
+// `functions` holds the names of functions defined so far, which parse as commands.
+fn parse_program(input: &str, functions: &mut HashSet<String>) -> Result<Program, EngineError> {
+    parse_program_from(input, 0, functions)
+}
+
+// The same for text that starts after line `offset` of a larger source, so lines in errors
+// and `const` declarations count from the top of that source.
+fn parse_program_from(input: &str, offset: usize, functions: &mut HashSet<String>) -> Result<Program, EngineError> {
+    let number = Cell::new(offset);
+    let lines = input.lines().inspect(|_| number.set(number.get() + 1));
+    parse_lines(lines, &number, functions).map_err(|err| err.at_line(number.get()))
+}
+
+// The parser for a token naming a postfix operator in the table.
+fn operator(token: &str) -> Option<(&'static operators::Operator, operators::Parser)> {
+    operators::find(token).and_then(|op| Some((op, op.parse?)))
+}
+
+// `number` follows the line last taken from `lines`, including those taken by blocks.
+fn parse_lines<'a>(
+    mut lines: impl Iterator<Item = &'a str>,
+    number: &Cell<usize>,
+    functions: &mut HashSet<String>,
+) -> Result<Program, EngineError> {
+    let mut output = vec![];
+    let mut sigfigs = false;
+    let mut consts = HashMap::new();
+    // The line each command starts on, filled in for a line's commands once it is parsed.
+    let (mut starts, mut first) = (vec![], number.get());
+
+    while let Some(line) = lines.next() {
+        starts.resize(output.len(), first);
+        first = number.get();
+        // Anything from a token starting with # is a comment, like the `# = 42` left by `render`.
+        let command: Vec<_> = line.split_whitespace().take_while(|token| !token.starts_with('#')).collect();
+        if !command.is_empty() {
+            logging::debug("parse", &[("line", &number.get()), ("text", &line.trim())]);
+        }
+        let parsed = output.len();
+
+        match command.first() { // If the line starts with # this is a comment line, skip the parsing and ignore.
+            Some(x) if (x.starts_with("#")) => continue,
+            Some(x) if (*x == "sigfigs" && command.len() == 1) => {
+                sigfigs = true;
+                continue;
+            }
+            Some(x) if (*x == "=" || *x == "const") => {
+                // Caught here too, so a whole program fails before running with both lines named.
+                let command = parse_set_var(&command, number.get())?;
+                if let Command::SetVar(name) | Command::Const(name, _) = &command {
+                    if let Some(line) = consts.get(name) {
+                        return Err(EngineError::ConstantReassigned(name.clone(), *line));
+                    }
+                }
+                if let Command::Const(name, line) = &command {
+                    consts.insert(name.clone(), *line);
+                }
+                output.push(command);
+                continue;
+            }
+            Some(x) if (*x == "deriv") => {
+                output.push(parse_derivative(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "def" || *x == "memo") => {
+                let start = number.get();
+                output.push(parse_define(&command, &mut lines, functions).map_err(|err| err.in_block(start))?);
+                continue;
+            }
+            Some(x) if (*x == "findroot") => {
+                output.push(parse_find_root(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "integrate") => {
+                output.push(parse_integrate(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "to-base") => {
+                output.push(parse_to_base(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "mixed") => {
+                output.push(parse_mixed(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "scenario") => {
+                let start = number.get();
+                output.push(parse_scenario(&command, &mut lines, functions).map_err(|err| err.in_block(start))?);
+                continue;
+            }
+            Some(x) if (*x == "try") => {
+                let start = number.get();
+                output.push(parse_try(&command, &mut lines, functions).map_err(|err| err.in_block(start))?);
+                continue;
+            }
+            Some(x) if (*x == "error") => {
+                output.push(parse_error(line)?);
+                continue;
+            }
+            Some(x) if (*x == "exit") => {
+                output.push(parse_exit(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "expect") => {
+                output.push(parse_expect(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "vars" || *x == "unset") => {
+                output.push(parse_vars(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "snapshot" || *x == "restore") => {
+                output.push(parse_snapshot(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "defer") => {
+                output.push(parse_defer(&command, functions)?);
+                continue;
+            }
+            Some(x) if (*x == "assert") => {
+                output.push(parse_assert(&command, functions)?);
+                continue;
+            }
+            Some(x) if (*x == "load-csv") => {
+                output.push(parse_load(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "load-json" || *x == "load-config" || cfg!(feature = "net") && *x == "fetch") => {
+                output.push(parse_load_document(&command)?);
+                continue;
+            }
+            #[cfg(feature = "xlsx")]
+            Some(x) if (*x == "load-xlsx") => {
+                output.push(parse_load_xlsx(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "molar-mass") => {
+                output.push(parse_molar_mass(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "haversine") => {
+                output.push(parse_haversine(&command)?);
+                continue;
+            }
+            Some(x) if (*x == "sigma" || *x == "prod") => {
+                output.push(parse_series(&command, functions)?);
+                continue;
+            }
+            Some(_) => {},
+            None => {},
+        }
+
+        match command.last().map(|x| (*x, operator(x))) {
+            Some((x, Some((op, parse)))) => {
+                op.operands.check(x, command.len() - 1)?;
+                output.push(parse(&command)?);
+            }
+            Some((x, None)) if (x.starts_with("to-") && units::find_unit(&x[3..]).is_some()) => {
+                output.push(parse_to_unit(&command)?);
+            }
+            Some((x, None)) if functions.contains(x) => {
+                output.push(parse_call(&command)?);
+            }
+            Some((name, None)) => return Err(EngineError::UnknownCommand(name.to_string())),
+            None => {}
+        }
+
+        if sigfigs && output.len() > parsed {
+            track_significant(output.last_mut().unwrap(), &command);
+        }
+    }
+    starts.resize(output.len(), first);
+    Ok(Program { commands: output, lines: starts })
+}
+
+#[test]
+fn test_eval_add() -> Result<(), EngineError> {
+    let commands = vec![
+        Command::Add(vec![Value::Operand(1.0), Value::Operand(2.0)]),
+        Command::Add(vec![Value::Operand(3.0), Value::Operand(4.0), Value::Operand(5.0)]),
+    ];
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(15.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_variables() -> Result<(), EngineError> {
+    let commands = vec![
+        Command::Add(vec![Value::Operand(5.0), Value::Operand(5.0)]),
+        Command::SetVar(String::from("derp")),
+        Command::Add(vec![Value::Operand(2.0), Value::Operand(2.0)]),
+        Command::SetVar(String::from("blorp")),
+        Command::Add(vec![Value::Operand(5.0), Value::Variable(Symbol::intern("derp"))]),
+    ];
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(15.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_add() -> Result<(), EngineError> {
+    let input = "1 2 3 +\n4 5 +";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(15.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_add_plus() -> Result<(), EngineError> {
+    let input = "1 2 3 +\n4 5 plus";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(15.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_add_plus_add() -> Result<(), EngineError> {
+    let input = "1 2 3 +\n4 5 plus\n 6 add";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(21.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_subtract() -> Result<(), EngineError> {
+    let input = "20 2 -\n3 5 minus\n1 subtract";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(9.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_add_subtract() -> Result<(), EngineError> {
+    let input = "20 5 +\n3 4 -";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(18.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_multiply() -> Result<(), EngineError> {
+    let input = "2 5 x\n3 4 *\n5 times\n6 multiply";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(3600.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_divide() -> Result<(), EngineError> {
+    let input = "100 2 /\n5 divide\n2 div";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(5.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_power() -> Result<(), EngineError> {
+    let input = "2 1 **\n3 ^\n2 2 power";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(4096.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_comment() -> Result<(), EngineError> {
+    let input = "#2 2\n# 2 1 +\n3 2 +\n4 5 plus";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(14.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_modulus() -> Result<(), EngineError> {
+    let input = "29 17 %\n7 mod\n3 modulus\n3 modulo";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(2.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_variables() -> Result<(), EngineError> {
+    let input = "5 5 +\n= derp\n2 2 +\n= blorp\n5 derp add";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(15.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_negatives() -> Result<(), EngineError> {
+    let input = "5 -5 +";
+    
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(0.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_display_twos_complement() -> Result<(), EngineError> {
+    let input = "0 5 -\ni8";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "0xFB");
+    assert_eq!(format_answer(&result, DisplayMode::I16)?, "0xFFFB");
+    assert_eq!(format_answer(&result, DisplayMode::I32)?, "0xFFFFFFFB");
+    assert!(format_answer(&result, DisplayMode::U32).is_err());
+    assert!(format_answer(&Value::Operand(128.0), DisplayMode::I8).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_display_bits() -> Result<(), EngineError> {
+    let input = "-2 0.5 *\nbits";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    let output = format_answer(&result, evaluator.display)?;
+    assert!(output.contains("sign     1"));
+    assert!(output.contains("exponent 01111111111 (0)"));
+    assert!(output.contains("hex      0xBFF0000000000000"));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_ulp() -> Result<(), EngineError> {
+    let input = "1 0.5 +\nulp";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(f64::EPSILON));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_nextafter() -> Result<(), EngineError> {
+    let input = "1 0 nextafter";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(1.0 - f64::EPSILON / 2.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_approx() -> Result<(), EngineError> {
+    let input = "22 7 /\n= result\nresult 3.14159 1e-2 approx";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Bool(true));
+
+    let commands = parse("22 7 /\n3.14159 1e-4 approx")?;
+
+    let result = Evaluator::new().evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Bool(false));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_uncertainty() -> Result<(), EngineError> {
+    let input = "9.81±0.02 +\n= g\n3+-0.09 4±0.16 *\n= area\n1±0.3 2+-0.4 +";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Uncertain(3.0, 0.5));
+    assert_eq!(evaluator.vars.get("g"), Some(&Value::Uncertain(9.81, 0.02)));
+    match evaluator.vars.get("area") {
+        Some(Value::Uncertain(area, err)) => {
+            assert_eq!(*area, 12.0);
+            assert!((err - 0.6).abs() < 1e-12);
+        }
+        other => panic!("unexpected area {:?}", other),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_format_uncertainty() -> Result<(), EngineError> {
+    let format = |ans, err| format_answer(&Value::Uncertain(ans, err), DisplayMode::Default);
+    assert_eq!(format(19.6234, 0.0412)?, "19.62 ± 0.04");
+    assert_eq!(format(9.779627427665478, 0.23408184138552876)?, "9.8 ± 0.2");
+    // An uncertainty starting with a 1 keeps a second digit.
+    assert_eq!(format(1.23456, 0.0137)?, "1.235 ± 0.014");
+    assert_eq!(format(12345.6, 230.0)?, "12300 ± 200");
+    assert_eq!(format(-0.001, 0.04)?, "0.00 ± 0.04");
+    assert_eq!(format(9.81, 0.0)?, "9.81 ± 0");
+    assert_eq!(format_answer(&Evaluator::new().evaluate_commands(&parse("9.81±0.02 2 *")?)?, DisplayMode::Default)?, "19.62 ± 0.04");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_sigfigs() -> Result<(), EngineError> {
+    let input = "sigfigs\n12.0 3.10 *\n0.0450 +";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "37.2");
+    assert_eq!(format_significant(1234.0, 2), "1200");
+    assert_eq!(count_significant("1.20e3"), 3);
+    assert_eq!(count_significant("4500"), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_symbolic_unknowns() -> Result<(), EngineError> {
+    let input = "x 2 *\n3 +\n= f\nf y /";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+    evaluator.symbolic = true;
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "(2*x + 3)/y");
+    assert!(Evaluator::new().evaluate_commands(&commands).is_err());
+
+    // Operators written among the operands of one line are not unknowns.
+    let eval = |program| Evaluator::builder().symbolic(true).build().evaluate_commands(&parse(program)?);
+    assert_eq!(format_answer(&eval("x 2 y +")?, DisplayMode::Default)?, "x + 2 + y");
+    assert!(matches!(eval("x 2 * 3 +"), Err(EngineError::MissingVariable(name)) if name == "*"));
+    assert!(matches!(eval("x a=b +"), Err(EngineError::MissingVariable(name)) if name == "a=b"));
+
+    Ok(())
+}
+
+#[test]
+fn test_symbolic_simplify_deriv() -> Result<(), EngineError> {
+    let input = "t t 3 *\n= a\nt 6 *\na +\n5 -\nsimplify";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+    evaluator.symbolic = true;
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "3*t^2 + 6*t - 5");
+
+    let commands = parse("deriv t")?;
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "6*t + 6");
+
+    let commands = parse("2 t ^\n= g\ng y *\nderiv t")?;
+    let mut evaluator = Evaluator::new();
+    evaluator.symbolic = true;
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "2^t*ln(2)*y");
+
+    Ok(())
+}
+
+#[test]
+fn test_symbolic_solve() -> Result<(), EngineError> {
+    let input = "# what rate hits 1150 after a year on 1000?\n1 rate +\n1000 *\n1150 solve";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+    evaluator.symbolic = true;
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(0.15));
+
+    let commands = parse("t t *\nsolve")?;
+
+    assert!(matches!(evaluator.evaluate_commands(&commands), Err(EngineError::NotSolvable)));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_functions() -> Result<(), EngineError> {
+    let input = "def hyp a b\na a *\n= aa\nb b *\naa +\n0.5 ^\nend\n3 4 hyp\n2 *";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(10.0));
+    assert!(!evaluator.vars.contains_key("aa"));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_findroot() -> Result<(), EngineError> {
+    let input = "def f x\nx x *\n2 -\nend\nfindroot f 0 2";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    match result {
+        Value::Operand(root) => assert!((root - 2f64.sqrt()).abs() < 1e-10),
+        other => panic!("unexpected root {:?}", other),
+    }
+
+    let commands = parse("findroot f 2 3")?;
+
+    assert!(matches!(evaluator.evaluate_commands(&commands), Err(EngineError::NoRootInInterval)));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_integrate() -> Result<(), EngineError> {
+    let input = "def f t\nt 2 ^\nend\nintegrate f 0 3";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    match result {
+        Value::Operand(area) => assert!((area - 9.0).abs() < 1e-9),
+        other => panic!("unexpected area {:?}", other),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_series() -> Result<(), EngineError> {
+    let input = "sigma i 1 100 'i +";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(5050.0));
+
+    let commands = parse("def sq n\nn n *\nend\nsigma k 1 3 'k sq\n= squares\nprod k 1 5 'k +")?;
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(120.0));
+    assert_eq!(evaluator.vars.get("squares"), Some(&Value::Operand(14.0)));
+
+    Ok(())
+}
+
+#[test]
+fn test_recursion_limit() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    let err = evaluator.evaluate_commands(&parse("def forever n\nn forever\nend\n1 forever")?).unwrap_err();
+    assert!(matches!(err.root(), EngineError::RecursionLimit(name) if name == "forever"));
+    assert_eq!(err.code(), "recursion-limit");
+    let err = evaluator.evaluate_commands(&parse("def around n\nsigma k 1 1 'n around\nend\n1 around")?).unwrap_err();
+    assert!(matches!(err.root(), EngineError::RecursionLimit(name) if name == "around"));
+
+    // The limit counts calls in progress, not calls made.
+    let result = evaluator.evaluate_commands(&parse("def twice n\nn 2 *\nend\nsigma k 1 500 'k twice")?)?;
+    assert_eq!(result, Value::Operand(250500.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_primes() -> Result<(), EngineError> {
+    let input = "97 isprime";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Bool(true));
+
+    let commands = parse("360 factor")?;
+    let result = Evaluator::new().evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "[2, 2, 2, 3, 3, 5]");
+    assert!(matches!(Evaluator::new().evaluate_commands(&parse("2.5 isprime")?), Err(EngineError::NotAnInteger)));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_combinatorics() -> Result<(), EngineError> {
+    let input = "52 5 choose";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(2598960.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("10 3 permute")?)?;
+    assert_eq!(result, Value::Operand(720.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("100 50 choose\n2 *")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "201782689091128386669624994512");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_powmod() -> Result<(), EngineError> {
+    let input = "4 13 497 powmod";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(445.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("2 1000 1000000007 powmod")?)?;
+    assert_eq!(result, Value::Operand(688423210.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("60 30 choose\n= big\n7 big +\n= m\nbig 65537 m powmod")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "111057953098258173");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_radix() -> Result<(), EngineError> {
+    let input = "36rZZ 2r1010 +\nto-base 7";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(1305.0));
+    assert_eq!(format_answer(&result, evaluator.display)?, "3543");
+    assert_eq!(format_answer(&Value::Operand(-255.0), DisplayMode::Base(16))?, "-FF");
+    assert!(matches!(parse_float("8r9"), Err(EngineError::InvalidNumber(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_pathological_literals() -> Result<(), EngineError> {
+    assert!(matches!(parse_float("1e999999"), Err(EngineError::NumberOutOfRange(_))));
+    assert!(matches!(parse_float("-1e999999"), Err(EngineError::NumberOutOfRange(_))));
+    assert!(matches!(parse_float("1e-999999"), Err(EngineError::NumberOutOfRange(_))));
+    assert_eq!(parse_float("0e-999999")?, Value::Operand(0.0));
+    assert_eq!(parse_float("inf")?, Value::Operand(f64::INFINITY));
+
+    let digits = "9".repeat(2000);
+    assert!(matches!(parse_float(&digits)?, Value::Integer(n) if n.to_string() == digits));
+    assert_eq!(parse_float("-12345678901234567")?, Value::Operand(-12345678901234567.0));
+    assert!(matches!(parse_float(&format!("-{}", digits)), Err(EngineError::NumberOutOfRange(_))));
+    assert!(matches!(parse_float("1_000"), Err(EngineError::InvalidNumber(_))));
+    assert!(matches!(parse("1.2.3 1 +"), Err(EngineError::AtLine(1, err)) if matches!(*err, EngineError::InvalidNumber(_))));
+    assert_eq!(parse_float("4ac")?, Value::Variable("4ac".into()));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_fractions() -> Result<(), EngineError> {
+    let input = "3_1/4 1/2 +\n2 *";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Rational(15, 2));
+    assert_eq!(format_answer(&result, evaluator.display)?, "15/2");
+    assert_eq!(parse_float("-1_1/2")?, Value::Rational(-3, 2));
+    assert_eq!(parse_float("4/2")?, Value::Operand(2.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("1/3 0.5 +")?)?;
+    assert_eq!(result, Value::Operand(1.0 / 3.0 + 0.5));
+
+    // Fractions too big for exact arithmetic fall back to floats instead of overflowing.
+    let (a, b) = ((i64::MAX, i64::MAX - 1), (i64::MAX - 1, i64::MAX - 2));
+    for op in [ArithOp::Add, ArithOp::Subtract, ArithOp::Divide, ArithOp::Modulo] {
+        assert_eq!(op.combine_rational(a, b), None);
+    }
+    assert_eq!(ArithOp::Multiply.combine_rational(a, b), Some((i64::MAX, i64::MAX - 2)));
+    let result = Evaluator::new().evaluate_commands(&parse("9223372036854775807/9223372036854775806 9223372036854775806/9223372036854775805 +")?)?;
+    assert!(matches!(result, Value::Operand(x) if (x - 2.0).abs() < 1e-9));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_mixed() -> Result<(), EngineError> {
+    let input = "2_3/8 7/8 +\nmixed";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "3 1/4");
+    assert_eq!(format_answer(&Value::Operand(-2.3), DisplayMode::Mixed(Some(16)))?, "-2 5/16");
+    assert_eq!(format_answer(&Value::Operand(0.3), DisplayMode::Mixed(None))?, "0.3");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_clock_times() -> Result<(), EngineError> {
+    let input = "14:35 3:50 +\n0:15 -\nto-clock";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "18:10");
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "18h 10m");
+
+    let result = Evaluator::new().evaluate_commands(&parse("1:30 3 *\n2:00 /")?)?;
+    assert_eq!(result, Value::Operand(2.25));
+    assert!(Evaluator::new().evaluate_commands(&parse("1:30 5 +")?).is_err());
+    assert_eq!(units::parse_clock("18446744073709551:00"), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_timestamps() -> Result<(), EngineError> {
+    let input = "2024-02-28T23:15:00+02:00 2024-03-01T01:45:30Z diff";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "1d 4h 30m 30s");
+
+    let result = Evaluator::new().evaluate_commands(&parse("2024-12-31T22:00:00Z 3:00 +")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "2025-01-01T01:00:00Z");
+    assert_eq!(units::parse_timestamp("2024-02-29"), Some(1709164800.0));
+    assert_eq!(units::parse_timestamp("2023-02-29"), None);
+    assert_eq!(units::parse_timestamp("2024-02-31"), None);
+    assert_eq!(units::parse_timestamp("2024-04-31T10:00:00Z"), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_byte_sizes() -> Result<(), EngineError> {
+    let input = "1.5GiB 300MB +\n4096KiB -";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Quantity(1906418432.0, units::DATA));
+    assert_eq!(format_answer(&result, DisplayMode::Unit("B"))?, "1906418432 B");
+
+    let commands = parse("2TB 1.5GiB /\n= disks\n3GiB 2 *\nto-gib")?;
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "6 GiB");
+    assert_eq!(format_answer(&Value::Quantity(1536.0, units::DATA), DisplayMode::Default)?, "1.5 KiB");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_transfer_time() -> Result<(), EngineError> {
+    let input = "10GiB 250Mbps transfer-time";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "5m 43.59738368s");
+
+    let result = Evaluator::new().evaluate_commands(&parse("600MB 0:01 /")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "10 MB/s");
+    assert!(Evaluator::new().evaluate_commands(&parse("10GiB 5 transfer-time")?).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_frequency() -> Result<(), EngineError> {
+    let input = "16MHz 8 /\nhz-to-period";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(format_answer(&result, evaluator.display)?, "500 ns");
+
+    let result = Evaluator::new().evaluate_commands(&parse("104.17us period-to-hz")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Unit("Hz"))?, "9599.69280983009 Hz");
+
+    let result = Evaluator::new().evaluate_commands(&parse("72MHz 250ns *")?)?;
+    match result {
+        Value::Operand(cycles) => assert!((cycles - 18.0).abs() < 1e-9),
+        other => panic!("unexpected cycle count {:?}", other),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_electronics() -> Result<(), EngineError> {
+    let input = "100 100 50 parallel";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(25.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("5 10000 3300 vdiv")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "1.2406015037593985");
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_polar() -> Result<(), EngineError> {
+    let input = "deg
+3 4 to-polar";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::List(vec![5.0, 4f64.atan2(3.0).to_degrees()]));
+
+    let result = Evaluator::new().evaluate_commands(&parse("deg\n2 90 to-cartesian")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "[1.2246467991473532e-16, 2]");
+
+    // The accumulator list round-trips back to where it started.
+    let result = Evaluator::new().evaluate_commands(&parse("3 4 to-polar\nto-cartesian")?)?;
+    let Value::List(items) = result else { panic!("expected a list") };
+    assert!((items[0] - 3.0).abs() < 1e-12 && (items[1] - 4.0).abs() < 1e-12);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_haversine() -> Result<(), EngineError> {
+    // London to Paris.
+    let input = "haversine 51.5074 -0.1278 48.8566 2.3522";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    let Value::Operand(km) = result else { panic!("expected a number") };
+    assert!((km - 343.56).abs() < 0.01);
+
+    let result = Evaluator::new().evaluate_commands(&parse("mi\nhaversine 51.5074 -0.1278 48.8566 2.3522")?)?;
+    let Value::Operand(miles) = result else { panic!("expected a number") };
+    assert!((miles - km / 1.609344).abs() < 1e-9);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_molar_mass() -> Result<(), EngineError> {
+    // Grams of water in 2.5 mol.
+    let input = "molar-mass H2O
+2.5 *";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(evaluator.answers[0], Value::Operand(18.015));
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "45.0375");
+
+    let result = Evaluator::new().evaluate_commands(&parse("molar-mass Ca(OH)2")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "74.092");
+
+    let result = Evaluator::new().evaluate_commands(&parse("molar-mass CuSO4·5H2O")?)?;
+    assert_eq!(format_answer(&result, DisplayMode::Default)?, "249.677");
+
+    assert!(matches!(Evaluator::new().evaluate_commands(&parse("molar-mass Xy2")?), Err(EngineError::UnknownFormula(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_constants() -> Result<(), EngineError> {
+    // Photon energy at 500nm.
+    let input = "const.h const.c *
+500e-9 /";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(6.62607015e-34 * 299792458.0 / 500e-9));
+
+    assert!(matches!(parse("= const.c").map_err(EngineError::unlocated), Err(EngineError::ReadOnlyVariable(_))));
+    assert!(matches!(Evaluator::new().evaluate_commands(&parse("const.nope 1 +")?), Err(EngineError::MissingVariable(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_arguments() -> Result<(), EngineError> {
+    let input = "$1 $2 *";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+    evaluator.bind_arguments(&["1500".to_string(), "0.5".to_string()])?;
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(750.0));
+
+    let mut evaluator = Evaluator::new();
+    evaluator.bind_arguments(&["1500".to_string()])?;
+    assert!(matches!(evaluator.evaluate_commands(&commands), Err(EngineError::MissingArgument(2))));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_define() -> Result<(), EngineError> {
+    let input = "rate 100 *";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+    evaluator.define_variables(&["rate=3/4".to_string()])?;
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(75.0));
+
+    assert!(matches!(Evaluator::new().define_variables(&["rate".to_string()]), Err(EngineError::InvalidDefine(_))));
+    assert!(matches!(Evaluator::new().define_variables(&["rate=abc".to_string()]), Err(EngineError::InvalidNumber(value)) if value == "abc"));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_parse_environment() -> Result<(), EngineError> {
+    let input = "rate 100 *";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+    let env = [("QQC_VAR_RATE", "0.5"), ("HOME", "/root"), ("QQC_VAR_", "1")];
+    evaluator.bind_environment(env.iter().map(|(k, v)| (k.into(), v.into())))?;
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(50.0));
+    assert_eq!(evaluator.vars.len(), 1);
+
+    let env = [("QQC_VAR_RATE", "zz")].map(|(k, v)| (k.into(), v.into()));
+    assert!(matches!(Evaluator::new().bind_environment(env.into_iter()), Err(EngineError::InvalidNumber(value)) if value == "zz"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        let env = [(OsString::from("QQC_VAR_TAX"), OsString::from("0.2")), (OsString::from_vec(b"QQC_VAR_\xff".to_vec()), OsString::from("1"))];
+        let mut evaluator = Evaluator::new();
+        evaluator.bind_environment(env.into_iter())?;
+        assert_eq!(evaluator.vars.len(), 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_exit() -> Result<(), EngineError> {
+    let input = "2 3 +
+exit
+missing 1 +";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(5.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("2 3 +\n= total\nexit total\n1 +")?)?;
+    assert_eq!(result, Value::Operand(5.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_error() -> Result<(), EngineError> {
+    let input = "budget 0 -
+error \"budget must be positive\"
+1 +";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+    evaluator.vars.insert("budget", Value::Operand(-5.0));
+
+    let err = evaluator.evaluate_commands(&commands).unwrap_err();
+
+    assert_eq!(err.to_string(), "budget must be positive");
+    assert_eq!(err.exit_code(), 2);
+    assert_eq!(evaluator.answer, Value::Operand(-5.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_try() -> Result<(), EngineError> {
+    let input = "5 5 +
+try
+rate 2 *
+recover
+0.5 *
+end
+1 +";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(6.0));
+
+    let mut evaluator = Evaluator::new();
+    evaluator.vars.insert("rate", Value::Operand(3.0));
+    assert_eq!(evaluator.evaluate_commands(&commands)?, Value::Operand(61.0));
+
+    // Exiting inside a block ends the whole run.
+    let result = Evaluator::new().evaluate_commands(&parse("try\nexit 7\nend\n1 +")?)?;
+    assert_eq!(result, Value::Operand(7.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_redefine_warning() -> Result<(), EngineError> {
+    let input = "def f a
+a 1 +
+end
+def f a
+a 2 +
+end
+1 f";
+
+    let commands = parse(input)?;
+
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&commands)?;
+
+    assert_eq!(result, Value::Operand(3.0));
+    assert_eq!(evaluator.warnings, vec!["function 'f' is redefined".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_log_json_string() -> Result<(), EngineError> {
+    assert_eq!(logging::json_string("say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_diagnostic_json() -> Result<(), EngineError> {
+    let source = "1 2 +
+def f a
+a 1 pluss
+end";
+
+    let Err(err) = parse(source) else { panic!("expected a parse error") };
+    let mut program = Source::default();
+    program.text = source.into();
+    let diagnostic = error_diagnostic(&err, Some("sheet.qq"), &program);
+
+    assert_eq!(
+        diagnostic.to_json(),
+        "{\"file\":\"sheet.qq\",\"line\":3,\"column\":5,\"severity\":\"error\",\"code\":\"unknown-command\",\
+         \"message\":\"unknown command 'pluss'\",\"suggestion\":\"the last token on a line must be an operator or a defined function\",\"included_from\":[]}"
+    );
+    assert_eq!(diagnostic.to_text(), "error: line 3: unknown command 'pluss'");
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_include_chain() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir().join(format!("qqc-include-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("main.qq"), "1 2 +\ninclude lib/rates.qq\n").unwrap();
+    std::fs::write(dir.join("lib/rates.qq"), "# rates\n3 *\n\n1 pluss\n").unwrap();
+    let main = dir.join("main.qq").to_string_lossy().into_owned();
+
+    let mut source = Source::default();
+    source.load(&main)?;
+    let Err(err) = parse(&source.text) else { panic!("expected a parse error") };
+    let diagnostic = error_diagnostic(&err, Some(&main), &source);
+
+    let lib = dir.join("lib/rates.qq").to_string_lossy().into_owned();
+    assert_eq!((diagnostic.file.as_deref(), diagnostic.line), (Some(lib.as_str()), Some(4)));
+    assert_eq!(diagnostic.to_text(), format!("error at {}:4, included from {}:2: unknown command 'pluss'", lib, main));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_repl_help() -> Result<(), EngineError> {
+    assert_eq!(repl::meta_command(":help plus", &Evaluator::new()).unwrap(), "+ (plus, add)  [postfix, at least one operand]  adds the values");
+    assert_eq!(repl::meta_command(":help nope", &Evaluator::new()).unwrap(), "no operator named 'nope'");
+    assert!(repl::meta_command(":help", &Evaluator::new()).unwrap().lines().count() == operators::OPERATORS.len());
+    assert!(repl::meta_command(":quit", &Evaluator::new()).is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_render() -> Result<(), EngineError> {
+    let input = "# prices
+2 3 +
+= total
+def double a
+a 2 *
+end
+total double   # = 7
+";
+
+    let rendered = render::render(input, Path::new(""), &mut Evaluator::new())?;
+
+    assert_eq!(
+        rendered,
+        "# prices
+2 3 +         # = 5
+= total
+def double a
+a 2 *
+end
+total double  # = 10
+"
+    );
+    assert_eq!(render::render(&rendered, Path::new(""), &mut Evaluator::new())?, rendered);
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_literate() -> Result<(), EngineError> {
+    let input = "# Notes
+
+```qqc
+2 3 +
+= width
+```
+
+Some prose.
+
+```qqc
+width 4 *
+```
+";
+
+    let answers = literate::evaluate(input, &mut Evaluator::new())?;
+
+    assert_eq!(answers, vec![None, Some("20".to_string())]);
+
+    let written = literate::write_back(input, &answers)?;
+    assert!(written.ends_with("width 4 *\n```\n\n```qqc-result\n20\n```\n"));
+    assert_eq!(literate::write_back(&written, &answers)?, written);
+
+    Ok(())
+}
+
+#[test]
+fn test_evaluator_builder() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::builder().angle_mode(AngleMode::Degrees).precision(12).history(false).build();
+    let result = evaluator.evaluate_commands(&parse("0.1 0.2 +")?)?;
+    assert_eq!(evaluator.format(&result)?, "0.3");
+    assert_eq!(evaluator.angle, AngleMode::Degrees);
+    assert!(evaluator.answers.is_empty());
+
+    let mut evaluator = Evaluator::builder().strict(true).build();
+    let result = evaluator.evaluate_commands(&parse("def f a\na 1 +\nend\ndef f a\na 2 +\nend")?);
+    assert!(matches!(result, Err(EngineError::Strict(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_portable_math() -> Result<(), EngineError> {
+    use math::portable;
+    use std::f64::consts::{E, PI};
+
+    let close = |a: f64, b: f64| (a - b).abs() <= 2.0 * f64::EPSILON * b.abs().max(1.0);
+    assert!(close(portable::exp(1.0), E));
+    assert!(close(portable::ln(E), 1.0));
+    assert!(close(portable::ln(1e-310), 1e-310f64.ln()));
+    assert_eq!(portable::pow(2.0, 10.0), 1024.0);
+    assert_eq!(portable::pow(2.0, 0.5), 2f64.sqrt());
+    assert!(close(portable::pow(10.0, 2.5), 10f64.powf(2.5)));
+    assert!(close(portable::pow(1.5, -7.25), 1.5f64.powf(-7.25)));
+    assert_eq!(portable::pow(0.0, -2.5), f64::INFINITY);
+    for x in [0.5, 1.0, 2.0, 3.0, -4.0, 100.0, 12345.678] {
+        assert!(close(portable::sin(x), x.sin()), "sin {}", x);
+        assert!(close(portable::cos(x), x.cos()), "cos {}", x);
+    }
+    assert!(close(portable::atan2(1.0, -1.0), 3.0 * PI / 4.0));
+    assert!(close(portable::asin(0.5), PI / 6.0));
+    assert!(close(portable::acos(0.5), PI / 3.0));
+    assert_eq!(portable::hypot(3.0, 4.0), 5.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_no_std_float() -> Result<(), EngineError> {
+    use math::Float;
+
+    // What a `no_std` build uses for the methods of `std`, which must agree to the bit.
+    let same = |a: f64, b: f64| a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan());
+    let mut values = vec![0.0, -0.0, 0.5, -0.5, 2.5, 0.49999999999999994, 4503599627370495.5, 1e300, 5e-324, f64::MIN_POSITIVE, f64::MAX];
+    values.extend([f64::INFINITY, f64::NEG_INFINITY, f64::NAN]);
+    let mut bits = 0x9e37_79b9_7f4a_7c15u64;
+    for _ in 0..10_000 {
+        bits ^= bits << 13;
+        bits ^= bits >> 7;
+        bits ^= bits << 17;
+        values.extend([f64::from_bits(bits), (bits % 20_000) as f64 / 8.0 - 1250.0]);
+    }
+    for x in values {
+        assert!(same(Float::trunc(x), x.trunc()), "trunc {:e}", x);
+        assert!(same(Float::floor(x), x.floor()), "floor {:e}", x);
+        assert!(same(Float::ceil(x), x.ceil()), "ceil {:e}", x);
+        assert!(same(Float::round(x), x.round()), "round {:e}", x);
+        assert!(same(Float::fract(x), x.fract()), "fract {:e}", x);
+        assert!(same(Float::rem_euclid(x, 3.7), x.rem_euclid(3.7)), "rem_euclid {:e}", x);
+        assert!(same(Float::sqrt(x), x.sqrt()), "sqrt {:e}", x);
+    }
+    assert_eq!(Float::powi(2.0, 10), 1024.0);
+    assert_eq!(Float::powi(2.0, -2), 0.25);
+    assert_eq!(Float::powi(1.5, 0), 1.0);
+    let close = |a: f64, b: f64| (a - b).abs() <= 4.0 * f64::EPSILON * b.abs().max(1.0);
+    for x in [0.25, 0.5, 0.9, 1.0, 2.0, 10.0] {
+        assert!(close(Float::powf(x, 1.7), x.powf(1.7)) && close(Float::exp(x), x.exp()), "powf, exp {}", x);
+        assert!(close(Float::ln(x), x.ln()) && close(Float::log10(x), x.log10()) && close(Float::log2(x), x.log2()), "logs {}", x);
+        assert!(close(Float::sin(x), x.sin()) && close(Float::cos(x), x.cos()) && close(Float::tan(x), x.tan()), "trig {}", x);
+        assert!(close(Float::atan(x), x.atan()) && close(Float::atan2(x, -1.5), x.atan2(-1.5)) && close(Float::hypot(x, 3.0), x.hypot(3.0)), "atan, hypot {}", x);
+        assert!(close(Float::asin(x.min(1.0)), x.min(1.0).asin()) && close(Float::acos(x.min(1.0)), x.min(1.0).acos()), "asin, acos {}", x);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_long_folds() -> Result<(), EngineError> {
+    let line: Vec<_> = (1..=1000).map(|i| i.to_string()).collect();
+    let mut evaluator = Evaluator::new();
+
+    let result = evaluator.evaluate_commands(&parse(&format!("{} +", line.join(" ")))?)?;
+    assert_eq!(result, Value::Operand(500500.0));
+
+    let result = evaluator.evaluate_commands(&parse(&format!("{} min", line.join(" ")))?)?;
+    assert_eq!(result, Value::Operand(1.0));
+
+    let result = evaluator.evaluate_commands(&parse("3 -2 max")?)?;
+    assert_eq!(result, Value::Operand(3.0));
+
+    let halves = ["0.5"; 100].join(" ");
+    let result = Evaluator::new().evaluate_commands(&parse(&format!("{} *", halves))?)?;
+    assert_eq!(result, Value::Operand(0.5f64.powi(100)));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_list_aggregates() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+
+    evaluator.evaluate_commands(&parse("360 factor")?)?;
+    let result = evaluator.evaluate_commands(&parse("sum")?)?;
+    assert_eq!(result, Value::Operand(17.0));
+
+    let result = Evaluator::new().evaluate_commands(&parse("2 4 4 4 5 5 7 9 mean")?)?;
+    assert_eq!(result, Value::Operand(5.0));
+    let result = Evaluator::new().evaluate_commands(&parse("2 4 4 4 5 5 7 9 stddev")?)?;
+    assert_eq!(result, Value::Operand((32f64 / 7.0).sqrt()));
+    let result = Evaluator::new().evaluate_commands(&parse("3 -1 2 sort")?)?;
+    assert_eq!(result, Value::List(vec![-1.0, 2.0, 3.0]));
+
+    // Chunked partial sums make the answer independent of the thread count.
+    let values: Vec<f64> = (0..300_000).map(|i| (i % 1000) as f64 * 0.1).rev().collect();
+    let (one, four) = (threads::Workers(1), threads::Workers(4));
+    let sequential = (one.sum(&values), one.stddev(&values), one.sort(values.clone()));
+    let threaded = (four.sum(&values), four.stddev(&values), four.sort(values.clone()));
+    assert_eq!(sequential, threaded);
+    assert!(matches!(parse_args(["--threads".to_string()].into_iter()), Err(EngineError::UnknownOption(option)) if option == "--threads"));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_watch_incremental() -> Result<(), EngineError> {
+    let mut worksheet = watch::Worksheet::new(Evaluator::new());
+
+    assert_eq!(worksheet.update("2 3 +\n= a\ndef f x\nx a *\nend\n4 f")?, 4);
+    assert_eq!(worksheet.engine().answer, Value::Operand(20.0));
+
+    // Only the edited last line runs again, from the snapshot before it.
+    assert_eq!(worksheet.update("2 3 +\n= a\ndef f x\nx a *\nend\n6 f")?, 1);
+    assert_eq!(worksheet.engine().answer, Value::Operand(30.0));
+
+    assert_eq!(worksheet.update("1 3 +\n= a\ndef f x\nx a *\nend\n6 f")?, 4);
+    assert_eq!(worksheet.engine().answer, Value::Operand(24.0));
+
+    let Err(err) = worksheet.update("1 3 +\n= a\n2 b +") else { panic!("expected an error") };
+    assert_eq!(err.line(), Some(3));
+    assert_eq!(worksheet.update("1 3 +\n= a\n2 a +")?, 1);
+    assert_eq!(worksheet.engine().answer, Value::Operand(6.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_memo() -> Result<(), EngineError> {
+    let input = "memo growth years
+1.05 years ^
+end
+sigma i 1 3 'i growth
+= total
+10 growth";
+
+    let mut evaluator = Evaluator::new();
+    let result = evaluator.evaluate_commands(&parse(input)?)?;
+    assert_eq!(result, Value::Operand(1.05f64.powf(10.0)));
+    assert_eq!(evaluator.memos.0.borrow()["growth"].len(), 4);
+    assert!(evaluator.clone().memos.0.borrow().is_empty());
+
+    // The angle mode is part of what a result depends on.
+    let input = "memo wave x\nx sin\nend\n90 wave\n= before\ndeg\n90 wave";
+    assert_eq!(Evaluator::new().evaluate_commands(&parse(input)?)?, Value::Operand(1.0));
+
+    // Outer variables are out of reach, so cached results can't go stale.
+    let input = "5 5 +
+= rate
+memo scaled x
+x rate *
+end
+2 scaled";
+    let result = Evaluator::new().evaluate_commands(&parse(input)?);
+    assert!(matches!(result, Err(EngineError::MissingVariable(_))));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_quick_mode() -> Result<(), EngineError> {
+    let args = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+
+    let mut options = args(&["2", "-3", "+"])?;
+    assert_eq!(quick_expression(&mut options).as_deref(), Some("2 -3 +"));
+    assert!(options.file.is_none() && options.args.is_empty());
+
+    let mut options = args(&["--quiet", "Cargo.toml", "7"])?;
+    assert_eq!(quick_expression(&mut options), None);
+    assert_eq!(options.args, ["7"]);
+
+    for file in ["missing.qq", "sheets/q3"] {
+        let mut options = args(&[file])?;
+        assert_eq!(quick_expression(&mut options), None);
+    }
+    assert_eq!(quick_expression(&mut args(&["6 3 /"])?).as_deref(), Some("6 3 /"));
+
+    let mut options = args(&["-", "2"])?;
+    assert_eq!(quick_expression(&mut options), None);
+    assert_eq!(options.file.as_deref(), Some("-"));
+
+    let mut options = args(&["-e", "3 4 +", "--expr", "$1 *", "2"])?;
+    assert_eq!(quick_expression(&mut options).as_deref(), Some("3 4 +\n$1 *"));
+    assert_eq!(options.args, ["2"]);
+
+    let mut options = args(&["--", "2", "3"])?;
+    assert_eq!(quick_expression(&mut options), None);
+    assert_eq!(options.file.as_deref(), Some("2"));
+
+    let mut options = args(&["-q", "--test", "2", "3", "<"])?;
+    assert!(options.quiet);
+    assert_eq!(quick_expression(&mut options).as_deref(), Some("2 3 <"));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_preset_text() -> Result<(), EngineError> {
+    // `-e` and quick mode preset the source the way `cli` does, as does text read from stdin.
+    let args = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+    for (mut options, text) in [(args(&["-q", "-e", "2 3 +"])?, None), (args(&["-q", "2", "3", "+"])?, None), (args(&["-q"])?, Some("1 1 +\n3 +\n"))] {
+        let mut source = Source::default();
+        source.text = match text {
+            Some(text) => text.into(),
+            None => quick_expression(&mut options).unwrap(),
+        };
+        assert_eq!(run(&options, &mut source, &[])?, 0);
+        let mut engine = evaluator(&options)?;
+        let answer = evaluate_program(&options, &mut engine, &mut source, &[], &mut Trace::default())?;
+        assert_eq!(answer, Value::Operand(5.0));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_notify_command() -> Result<(), EngineError> {
+    let command = notify::command("sheet.qq: 42");
+    assert!(command.last().unwrap().contains("sheet.qq: 42"));
+    if cfg!(target_os = "linux") {
+        assert_eq!(command, ["notify-send", "qqc", "sheet.qq: 42"]);
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_prompt_missing() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::builder().prompt(|name| (name == "rate").then_some(Value::Operand(0.5))).build();
+
+    let result = evaluator.evaluate_commands(&parse("100 rate *\n= cost\ncost rate +")?)?;
+    assert_eq!(result, Value::Operand(50.5));
+    assert_eq!(evaluator.vars["rate"], Value::Operand(0.5));
+
+    let result = evaluator.evaluate_commands(&parse("1 other +")?);
+    assert!(matches!(result, Err(EngineError::MissingVariable(name)) if name == "other"));
+
+    let mut output = vec![];
+    assert_eq!(prompt_value("rate", &mut "abc\n0.5\n".as_bytes(), &mut output), Some(Value::Operand(0.5)));
+    assert_eq!(String::from_utf8_lossy(&output), "rate = error: 'abc' is not a valid number\nrate = ");
+    assert_eq!(prompt_value("rate", &mut "".as_bytes(), &mut output), None);
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_csv_references() -> Result<(), EngineError> {
+    let path = std::env::temp_dir().join(format!("qqc-test-{}.csv", std::process::id()));
+    std::fs::write(&path, "item,price,qty\nbolt,0.25,40\n\"nut, hex\",0.1,100\nwasher,,\n").unwrap();
+
+    let mut evaluator = Evaluator::new();
+    let program = format!("load-csv {}\nB2 C2 *\n= bolts\nB2:B4 sum", path.display());
+    let result = evaluator.evaluate_commands(&parse(&program)?)?;
+    assert_eq!(result, Value::Operand(0.35));
+    assert_eq!(evaluator.vars["bolts"], Value::Operand(10.0));
+
+    let result = evaluator.evaluate_commands(&parse("A2 1 +")?);
+    assert!(matches!(result, Err(EngineError::InvalidCell(cell)) if cell == "A2"));
+    let result = Evaluator::new().evaluate_commands(&parse("B2 1 +")?);
+    assert!(matches!(result, Err(EngineError::MissingVariable(_))));
+
+    std::fs::remove_file(path).ok();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_load_json() -> Result<(), EngineError> {
+    let path = std::env::temp_dir().join(format!("qqc-test-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"items": [{"price": 2.5}, {"price": 4, "sizes": [1, 2, 3]}], "name": "order"}"#).unwrap();
+
+    let mut evaluator = Evaluator::new();
+    let result = evaluator.evaluate_commands(&parse(&format!("load-json {} .items[1].price\n2 *", path.display()))?)?;
+    assert_eq!(result, Value::Operand(8.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-json {} .items[1].sizes\nsum", path.display()))?)?;
+    assert_eq!(result, Value::Operand(6.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-json {} .name", path.display()))?);
+    assert!(matches!(result, Err(EngineError::InvalidJson(_))));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-json {} .items[2].price", path.display()))?);
+    assert!(matches!(result, Err(EngineError::InvalidJson(_))));
+
+    std::fs::remove_file(path).ok();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_load_config() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir();
+    let toml = dir.join(format!("qqc-test-{}.toml", std::process::id()));
+    let yaml = dir.join(format!("qqc-test-{}.yaml", std::process::id()));
+    std::fs::write(&toml, "[package]\nname = \"qqc\" # the crate\n\n[package.metadata]\nbudget = 12_000\nrates = [\n  0.5,\n  1.5,\n]\n\n[[tier]]\nlimit = 10\n[[tier]]\nlimit = 20\n").unwrap();
+    std::fs::write(&yaml, "server:\n  port: 8080\n  weights:\n  - 1\n  - 2.5\nusers:\n  - name: ada\n    quota: 3\n  - name: bob\n    quota: 4\n").unwrap();
+
+    let mut evaluator = Evaluator::new();
+    let result = evaluator.evaluate_commands(&parse(&format!("load-config {} package.metadata.budget\n12 /", toml.display()))?)?;
+    assert_eq!(result, Value::Operand(1000.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-config {} package.metadata.rates\nsum", toml.display()))?)?;
+    assert_eq!(result, Value::Operand(2.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-config {} tier[1].limit", toml.display()))?)?;
+    assert_eq!(result, Value::Operand(20.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-config {} server.weights\nsum", yaml.display()))?)?;
+    assert_eq!(result, Value::Operand(3.5));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-config {} users[1].quota", yaml.display()))?)?;
+    assert_eq!(result, Value::Operand(4.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-config {} package.name", toml.display()))?);
+    assert!(matches!(result, Err(EngineError::InvalidConfig(_))));
+
+    std::fs::remove_file(toml).ok();
+    std::fs::remove_file(yaml).ok();
+    Ok(())
+}
+
+#[cfg(feature = "net")]
+#[test]
+fn test_fetch() -> Result<(), EngineError> {
+    use std::io::{Read, Write};
+
+    // A server answering each request with the next of `responses`.
+    let serve = |responses: Vec<&'static str>| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let _ = stream.read(&mut [0; 4096]);
+                stream.write_all(response.as_bytes()).ok();
+            }
+        });
+        url
+    };
+    let rates = "HTTP/1.1 200 OK\r\nContent-Length: 24\r\nConnection: close\r\n\r\n{\"rates\": {\"EUR\": 0.92}}";
+    let missing = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    let redirect = "HTTP/1.1 302 Found\r\nLocation: file:///etc/hostname\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    let mut evaluator = Evaluator::new();
+    let url = serve(vec![rates, missing, redirect]);
+    let result = evaluator.evaluate_commands(&parse(&format!("fetch {}/rates .rates.EUR\n100 *", url))?)?;
+    assert_eq!(result, Value::Operand(92.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("fetch {}/missing .rates.EUR", url))?);
+    assert!(matches!(result, Err(EngineError::FetchFailed(_))));
+    // Local files can't be read, directly or through a redirect.
+    let result = evaluator.evaluate_commands(&parse(&format!("fetch {}/moved .rates.EUR", url))?);
+    assert!(matches!(result, Err(EngineError::FetchFailed(_))));
+    let path = std::env::temp_dir().join(format!("qqc-test-fetch-{}.json", std::process::id()));
+    std::fs::write(&path, r#"{"rates": {"EUR": 0.92}}"#).unwrap();
+    let result = evaluator.evaluate_commands(&parse(&format!("fetch file://{} .rates.EUR", path.display()))?);
+    assert!(matches!(result, Err(EngineError::FetchFailed(_))));
+    assert!(matches!(net::failure("https://example.com", Some(28), ""), EngineError::FetchTimeout(_)));
+
+    let cache = std::env::temp_dir().join(format!("qqc-test-cache-{}", std::process::id()));
+    let hour = std::time::Duration::from_secs(3600);
+    assert_eq!(net::parse_ttl("1h"), Some(hour));
+    assert_eq!(net::parse_ttl("999999999999999999d"), None);
+    net::store(&cache, "https://example.com/rates", ".EUR", &Value::Operand(0.92));
+    assert_eq!(net::lookup(&cache, "https://example.com/rates", ".EUR", hour), Some(Value::Operand(0.92)));
+    assert_eq!(net::lookup(&cache, "https://example.com/rates", ".USD", hour), None);
+    assert_eq!(net::lookup(&cache, "https://example.com/rates", ".EUR", std::time::Duration::ZERO), None);
+
+    std::fs::remove_dir_all(cache).ok();
+    std::fs::remove_file(path).ok();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_comparisons_and_test_status() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    evaluator.vars.insert("disk_free", Value::Operand(42.0));
+    let answer = evaluator.evaluate_commands(&parse("disk_free 10 >")?)?;
+    assert_eq!(answer, Value::Bool(true));
+    assert_eq!(Evaluator::new().evaluate_commands(&parse("2 2 !=")?)?, Value::Bool(false));
+    assert_eq!(Evaluator::new().evaluate_commands(&parse("2 3 +\n5 <=")?)?, Value::Bool(true));
+
+    let options = parse_args(["--test", "check.qqc"].into_iter().map(String::from))?;
+    assert_eq!(test_status(&options, &answer)?, 0);
+    assert_eq!(test_status(&options, &Value::Bool(false))?, 1);
+    assert!(matches!(test_status(&options, &Value::Operand(1.0)), Err(EngineError::NotABoolean)));
+    assert_eq!(failure_status(&options, &EngineError::MissingOperands), 2);
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_env_format() -> Result<(), EngineError> {
+    let commands = parse("2 3 +\n= total\ntry\n1 0 /\nrecover\n4 2 *\n= run-length\nend\n7 1 +\n= total")?;
+    let mut names = vec![];
+    bindings(&commands, &mut names);
+    assert_eq!(names, ["total", "run-length"]);
+    assert_eq!(env_line("total", "5"), "TOTAL=5");
+    assert_eq!(env_line("run-length", "4 km"), "RUN_LENGTH='4 km'");
+    assert_eq!(env_line("2nd", "it's"), "_2ND='it'\\''s'");
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_profile() -> Result<(), EngineError> {
+    let mut source = Source::default();
+    source.text = "2 3 +\n= a\ntry\nmissing 1 +\nrecover\n1 1 +\nend\n= b\na 2 *".into();
+    let mut engine = Evaluator::new();
+    let (answer, timings) = profile::evaluate(&mut engine, watch::statements(&source.text)?)?;
+    assert_eq!(answer, Value::Operand(10.0));
+    assert_eq!(timings.iter().map(|timing| timing.line).collect::<Vec<_>>(), [1, 2, 3, 8, 9]);
+
+    let report = profile::report(&timings, &source);
+    assert_eq!(report.len(), 5);
+    assert!(report.iter().any(|line| line.ends_with("line 3  try")));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_stats() -> Result<(), EngineError> {
+    stats::start();
+    let mut engine = Evaluator::new();
+    engine.evaluate_commands(&parse("2 3 +\n= a\n1000 factor")?)?;
+    assert_eq!(engine.executed, 3);
+    let report = stats::report(&engine);
+    assert_eq!(report[2..], ["commands     3", "variables    1", "history      3"]);
+    assert!(stats::peak_heap() > 0 && stats::allocations() > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_step() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    evaluator.load(parse("2 3 +\n= a\nb 1 +\na 2 *")?);
+
+    let step = evaluator.step().unwrap();
+    assert_eq!((step.index, step.result?), (0, Value::Operand(5.0)));
+    assert_eq!(evaluator.answer(), &Value::Operand(5.0));
+    evaluator.step().unwrap().result?;
+    assert_eq!(evaluator.vars().get("a"), Some(&Value::Operand(5.0)));
+
+    // A failure stays at its command until the state lets it through, and names its line.
+    let step = evaluator.step().unwrap();
+    assert!(matches!(step.result, Err(EngineError::AtLine(3, err)) if matches!(*err, EngineError::MissingVariable(_))));
+    assert_eq!(evaluator.position(), 2);
+    evaluator.vars.insert("b", Value::Operand(1.0));
+    assert_eq!(evaluator.step().unwrap().result?, Value::Operand(2.0));
+    assert_eq!(evaluator.step().unwrap().result?, Value::Operand(20.0));
+    assert!(evaluator.step().is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_notation() -> Result<(), EngineError> {
+    let locale = notation::Locale::named("de_DE.UTF-8");
+    let mut evaluator = Evaluator::builder().notation(locale).build();
+    let result = evaluator.evaluate_commands(&parse("1234567 0.5 +")?)?;
+    assert_eq!(evaluator.format(&result)?, "1.234.567,5");
+    assert_eq!(evaluator.format(&parse_float("12345678901234567890")?)?, "12.345.678.901.234.567.890");
+
+    let currency = notation::Currency { symbol: "$".into(), decimals: 2, locale: notation::Locale::ENGLISH };
+    let evaluator = Evaluator::builder().notation(currency).build();
+    assert_eq!(evaluator.format(&Value::Operand(-1234.5))?, "-$1,234.50");
+    assert_eq!(evaluator.format(&Value::List(vec![0.004, 2.0]))?, "[$0.00, $2.00]");
+
+    let options = parse_args(["--notation", "eng:3", "sheet.qq"].into_iter().map(String::from))?;
+    let evaluator = crate::evaluator(&options)?;
+    assert_eq!(evaluator.format(&Value::Operand(12345.0))?, "12.3e3");
+    assert_eq!(evaluator.format(&Value::Operand(-0.00042))?, "-420e-6");
+    assert_eq!(notation::parse("fixed:2").unwrap().format(19.996), "20.00");
+    assert_eq!(notation::parse("sci").unwrap().format(1234.5), "1.2345e3");
+    assert!(matches!(parse_args(["--notation", "fixed"].into_iter().map(String::from)), Err(EngineError::UnknownOption(_))));
+
+    // Embedders can bring their own.
+    struct Percent;
+    impl NumberFormatter for Percent {
+        fn format(&self, x: f64) -> String {
+            format!("{}%", x * 100.0)
+        }
+    }
+    let evaluator = Evaluator::builder().notation(Percent).build();
+    assert_eq!(evaluator.format(&Value::Operand(0.25))?, "25%");
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_doctor() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir().join(format!("qqc-doctor-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("team")).unwrap();
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    let config = dir.join("rates.toml");
+    std::fs::write(&config, "[rates]\neur = 0.9\n").unwrap();
+    std::fs::write(dir.join("a.qq"), format!("load-config {} rates.eur\n= eur\n", config.display())).unwrap();
+    std::fs::write(dir.join("team/b.qqc"), format!("2 3 +\n\nload-config {} rates.usd\n", config.display())).unwrap();
+    std::fs::write(dir.join("team/c.qq"), "2 3 +\n1 pluss\n").unwrap();
+    std::fs::write(dir.join(".git/d.qq"), "nonsense").unwrap();
+    std::fs::write(dir.join("notes.txt"), "nonsense").unwrap();
+
+    let report = doctor::check(&dir)?;
+    assert_eq!((report.worksheets, report.data_files), (3, 2));
+    // Missing tools for compiled-in features are reported without a file.
+    let problems: Vec<_> = report.problems.iter().filter_map(|problem| Some((problem.file.clone()?, problem.line, problem.code))).collect();
+    let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    assert_eq!(problems, [(file("team/b.qqc"), Some(3), "invalid-config"), (file("team/c.qq"), Some(2), "unknown-command")]);
+    let located = report.problems.iter().find(|problem| problem.file.is_some()).unwrap().to_located_text();
+    assert!(located.starts_with(&format!("error at {}:3: ", file("team/b.qqc"))));
+
+    std::fs::remove_dir_all(dir).ok();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_batch() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir().join(format!("qqc-batch-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("q1")).unwrap();
+    std::fs::write(dir.join("total.qqc"), "2 3 +\n").unwrap();
+    std::fs::write(dir.join("q1/broken.qqc"), "2 3 +\n1 pluss\n").unwrap();
+
+    let options = parse_args(["eval", "--recursive"].into_iter().map(String::from))?;
+    let results = batch(&options, &dir)?;
+    let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    assert_eq!(results[0], BatchResult { file: file("q1/broken.qqc"), answer: Err("error: line 2: unknown command 'pluss'".into()) });
+    assert_eq!(results[1], BatchResult { file: file("total.qqc"), answer: Ok("5".into()) });
+    let table = batch_table(&results);
+    assert_eq!(table[2], format!("{:width$}  5", file("total.qqc"), width = file("q1/broken.qqc").len()));
+    assert_eq!(table[3], "2 files, 1 failed");
+
+    std::fs::remove_dir_all(dir).ok();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_golden() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir().join(format!("qqc-golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.qq"), "2 3 +\nexpect 5\nassert 10 <\n= x\nx 2 *\nexpect 11\n").unwrap();
+    std::fs::write(dir.join("b.qq"), "1 1 +\nexpect 2\nq +\nexpect 3\n").unwrap();
+
+    let results = golden::run(&dir, &Evaluator::new())?;
+    assert_eq!((results[0].passed, results[0].failures.len()), (2, 1));
+    assert_eq!((results[1].passed, results[1].failures.len()), (1, 1));
+    let report = golden::report(&results);
+    let file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    assert_eq!(report[..4], [format!("FAIL {}: 2 passed, 1 failed", file("a.qq")), format!("  {}:6: expect 11", file("a.qq")), "    expected: 11".into(), "    actual:   10".into()]);
+    assert_eq!(report[5], format!("  {}:3: error: missing variable 'q'", file("b.qq")));
+    assert_eq!(report[6], "2 files, 5 checks: 3 passed, 2 failed");
+
+    // Outside `qqc test`, a failed check is a warning and the answer is untouched.
+    let mut evaluator = Evaluator::new();
+    assert_eq!(evaluator.evaluate_commands(&parse("2 3 +\nassert 3 <\nexpect 5")?)?, Value::Operand(5.0));
+    assert_eq!(evaluator.warnings, ["assert 3 < failed: expected true, got false for the answer 5"]);
+
+    std::fs::remove_dir_all(dir).ok();
+    Ok(())
+}
+
+#[test]
+fn test_snapshot() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    let program = "1000 0 +\n= budget\nsnapshot baseline\nbudget 1.1 *\n= high\ndeg\nrestore baseline\nbudget 2 *";
+    assert_eq!(evaluator.evaluate_commands(&parse(program)?)?, Value::Operand(2000.0));
+    assert_eq!(evaluator.angle, AngleMode::Radians);
+
+    // Variables bound since the snapshot are gone again.
+    let result = evaluator.evaluate_commands(&parse("restore baseline\nhigh 1 +")?);
+    assert!(matches!(result, Err(EngineError::MissingVariable(name)) if name == "high"));
+    assert_eq!(evaluator.vars["budget"], Value::Operand(1000.0));
+    assert!(matches!(evaluator.evaluate_commands(&parse("restore nope")?), Err(EngineError::UnknownSnapshot(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_scenario() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    let program = "1000 0 +\n= principal\nscenario rate 0.03 0.05\nrate 1 +\nprincipal *\n= total\nend\nscenario rate,years 0.5,1 1,2\n1 rate +\nyears ^\nend";
+    assert_eq!(evaluator.evaluate_commands(&parse(program)?)?, Value::List(vec![1.5, 4.0]));
+    assert!(!evaluator.vars.contains_key("total") && !evaluator.vars.contains_key("rate"));
+    assert_eq!(evaluator.tables[0].render(false), ["       rate=0.03  rate=0.05", "total       1030       1050"]);
+    assert_eq!(evaluator.tables[1].render(false), ["        rate=0.5 years=1  rate=1 years=2", "answer               1.5               4"]);
+
+    assert!(matches!(parse("scenario rate,years 0.5 1,2\nend"), Err(EngineError::AtLine(1, err)) if matches!(*err, EngineError::MissingOperands)));
+    let program = "0.04 0 +\n= rate const\nscenario rate 0.03 0.05\nrate 1 +\nend";
+    assert!(matches!(Evaluator::new().evaluate_commands(&parse(program)?), Err(EngineError::ConstantReassigned(name, 2)) if name == "rate"));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_table() -> Result<(), EngineError> {
+    let bindings = table::Table { headers: vec!["name".into(), "value".into()], rows: vec![vec!["principal".into(), "1000".into()], vec!["rate".into(), "0.05".into()]], labels: 1 };
+    assert_eq!(bindings.render(false), ["name       value", "principal   1000", "rate        0.05"]);
+    assert_eq!(bindings.render(true), ["┌───────────┬───────┐", "│ name      │ value │", "├───────────┼───────┤", "│ principal │  1000 │", "│ rate      │  0.05 │", "└───────────┴───────┘"]);
+
+    let mut steps = vec![];
+    let step = |engine: &mut Evaluator, statement: &mut watch::Parsed| {
+        let answer = engine.evaluate_commands(&statement.commands)?;
+        steps.push((statement.first, answer.clone()));
+        Ok(answer)
+    };
+    let answer = evaluate_statements(&mut Evaluator::new(), watch::statements("2 3 +\n\n# total\n= total\ntotal 2 *")?, step)?;
+    assert_eq!(answer, Value::Operand(10.0));
+    assert_eq!(steps, [(1, Value::Operand(5.0)), (4, Value::Nothing), (5, Value::Operand(10.0))]);
+
+    Ok(())
+}
+
+#[test]
+fn test_namespaces() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    let program = "0.05 0 +\n= loan.rate\n30 0 +\n= loan.term\n2 0 +\n= loans\nvars loan.*\nloan.rate loan.term *";
+    assert_eq!(evaluator.evaluate_commands(&parse(program)?)?, Value::Operand(1.5));
+    assert_eq!(evaluator.tables[0].render(false), ["name       value", "loan.rate   0.05", "loan.term     30"]);
+
+    evaluator.evaluate_commands(&parse("unset loan.*")?)?;
+    let mut names: Vec<_> = evaluator.vars.keys().collect();
+    names.sort();
+    assert_eq!(names, ["loans"]);
+    evaluator.evaluate_commands(&parse("unset loan.*")?)?;
+    assert_eq!(evaluator.warnings, ["unset loan.* matched no variables"]);
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_const() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    assert_eq!(evaluator.evaluate_commands(&parse("3.5 0 +\n= pi_ish const\npi_ish 2 *")?)?, Value::Operand(7.0));
+    let err = evaluator.evaluate_commands(&parse("1 0 +\n= pi_ish")?).unwrap_err();
+    assert!(matches!(err, EngineError::ConstantReassigned(ref name, 2) if name == "pi_ish"));
+    assert_eq!(evaluator.vars["pi_ish"], Value::Operand(3.5));
+    evaluator.evaluate_commands(&parse("unset pi_ish")?)?;
+    assert!(evaluator.vars.contains_key("pi_ish"));
+
+    // Within one program, both lines are named before anything runs.
+    let err = parse("2 0 +\nconst rate\n\n3 0 +\n= rate").err().map(|err| err.to_string());
+    assert_eq!(err.as_deref(), Some("line 5: cannot reassign 'rate', declared const on line 2"));
+    assert!(matches!(parse("= rate const later").map_err(EngineError::unlocated), Err(EngineError::TooManyVariableNames)));
+
+    // Statements evaluated one at a time count lines from the top of the worksheet.
+    let mut engine = Evaluator::new();
+    for statement in watch::statements("1 0 +\n\n= one const")? {
+        engine.evaluate_commands(&statement.commands)?;
+    }
+    assert_eq!(engine.consts["one"], 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_defer() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    let program = "4 0 +\n= price\n3 0 +\n= qty\ndefer total \"price qty *\"\ntotal 1 +";
+    assert_eq!(evaluator.evaluate_commands(&parse(program)?)?, Value::Operand(13.0));
+    evaluator.evaluate_commands(&parse("= scratch\n5 0 +\n= price")?)?;
+    assert_eq!(evaluator.binding("total").map(Result::ok), Some(Some(Value::Operand(15.0))));
+    assert!(!evaluator.vars.contains_key("total"));
+
+    // Binding the name with `=` replaces the expression.
+    evaluator.evaluate_commands(&parse("1 0 +\n= total\nprice 0 +\n= qty")?)?;
+    assert_eq!(evaluator.binding("total").map(Result::ok), Some(Some(Value::Operand(1.0))));
+
+    let err = Evaluator::new().evaluate_commands(&parse("defer a \"b 1 +\"\ndefer b \"a 1 +\"\na 0 +")?).unwrap_err();
+    assert_eq!(err.to_string(), "'a' depends on itself: a -> b -> a");
+    assert!(matches!(parse("defer total").map_err(EngineError::unlocated), Err(EngineError::MissingOperands)));
+
+    Ok(())
+}
+
+#[test]
+fn test_cells() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::builder().cells(true).build();
+    let program = "2 0 +\n= x\n3 0 +\n= y\ndefer a \"x 1 +\"\ndefer b \"y 1 +\"\ndefer c \"a 10 *\"\nc b +";
+    assert_eq!(evaluator.evaluate_commands(&parse(program)?)?, Value::Operand(34.0));
+    let cells = evaluator.cells.as_mut().unwrap();
+    cells.take_stale();
+    assert_eq!(cells.values.borrow().len(), 3);
+
+    // Only the cells downstream of `x` are forgotten and worked out again.
+    evaluator.evaluate_commands(&parse("= scratch\n5 0 +\n= x")?)?;
+    let cells = evaluator.cells.as_mut().unwrap();
+    assert_eq!(cells.take_stale(), ["a", "c"]);
+    assert_eq!(cells.values.borrow().keys().collect::<Vec<_>>(), ["b"]);
+    assert_eq!(evaluator.binding("c").map(Result::ok), Some(Some(Value::Operand(60.0))));
+
+    evaluator.evaluate_commands(&parse("unset x")?)?;
+    let cells = evaluator.cells.as_mut().unwrap();
+    assert_eq!(cells.take_stale(), ["a", "c"]);
+    assert_eq!(cells.values.borrow().keys().collect::<Vec<_>>(), ["b"]);
+    assert!(matches!(evaluator.binding("c"), Some(Err(EngineError::MissingVariable(name))) if name == "x"));
+
+    // Inputs from `-D` win over the worksheet's own.
+    let mut evaluator = Evaluator::builder().cells(true).build();
+    evaluator.define_variables(&["x=7".to_string()])?;
+    assert_eq!(evaluator.evaluate_commands(&parse("2 0 +\n= x\ndefer a \"x 1 +\"\na 0 +")?)?, Value::Operand(8.0));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_journal() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir().join(format!("qqc-journal-{}", std::process::id()));
+    let path = journal::path_in(&dir);
+    assert!(path.ends_with(format!("session-{}.qq", std::process::id())));
+    let mut journal = journal::Journal::open(&path).unwrap();
+    journal.record("2 3 +\n");
+    journal.record("= total\n");
+
+    // A running session's journal is locked, so nothing offers to restore it.
+    assert!(journal::leftover(&dir).is_none());
+
+    // Once its session is gone, the journal is left over until discarded.
+    drop(journal);
+    let leftover = journal::leftover(&dir).unwrap();
+    assert_eq!(leftover.text, "2 3 +\n= total\n");
+    assert!(journal::leftover(&dir).is_none());
+    leftover.discard();
+    assert!(!path.exists());
+
+    // Empty journals are cleaned up rather than offered.
+    std::fs::write(dir.join("session-1.qq"), "").unwrap();
+    assert!(journal::leftover(&dir).is_none());
+    assert!(!dir.join("session-1.qq").exists());
+
+    journal::Journal::open(&path).unwrap().finish();
+    assert!(!path.exists());
+    std::fs::remove_dir(&dir).ok();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_shared_state() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir().join(format!("qqc-shared-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (constants, model) = (dir.join("constants.qqc"), dir.join("model.qqc"));
+    std::fs::write(&constants, "0.25 0 +\n= rate\n").unwrap();
+    std::fs::write(&model, "rate 4 *\n").unwrap();
+
+    let paths = [constants.to_str().unwrap(), model.to_str().unwrap()];
+    let options = parse_args(["--shared-state", paths[0], paths[1]].iter().map(|arg| arg.to_string()))?;
+    assert!(options.args.is_empty());
+    assert_eq!(options.files, [paths[1]]);
+
+    // The answer left by one file doesn't fold into the first line of the next.
+    std::fs::write(&constants, "0.25 0 +\n= rate\n7 0 +\n").unwrap();
+    let (mut engine, mut source) = (Evaluator::new(), Source::default());
+    assert_eq!(evaluate_program(&options, &mut engine, &mut source, &paths, &mut Trace::default())?, Value::Operand(1.0));
+    assert_eq!(source.origin(4).map(|origin| (origin.file.as_str(), origin.line)), Some((paths[1], 1)));
+
+    // A parse error in a later file still lets the ones before it run, and names its own line.
+    std::fs::write(&model, "rate 4 *\n= total\n1 pluss\n").unwrap();
+    let (mut engine, mut source) = (Evaluator::new(), Source::default());
+    let err = evaluate_program(&options, &mut engine, &mut source, &paths, &mut Trace::default()).unwrap_err();
+    assert_eq!(engine.vars.get("rate"), Some(&Value::Operand(0.25)));
+    assert_eq!(source.origin(err.line().unwrap()).map(|origin| (origin.file.as_str(), origin.line)), Some((paths[1], 3)));
+
+    // Functions defined in one file are called in the next.
+    std::fs::write(&constants, "def twice n\nn 2 *\nend\n").unwrap();
+    std::fs::write(&model, "4 twice\n").unwrap();
+    let (mut engine, mut source) = (Evaluator::new(), Source::default());
+    assert_eq!(evaluate_program(&options, &mut engine, &mut source, &paths, &mut Trace::default())?, Value::Operand(8.0));
+
+    std::fs::remove_dir_all(&dir).ok();
+    Ok(())
+}
+
+#[test]
+fn test_operand_counts() -> Result<(), EngineError> {
+    let message = |program: &str| parse(program).err().map(|err| err.to_string());
+    assert_eq!(message("1 2 +\n\n+").as_deref(), Some("line 3: '+' needs at least one operand"));
+    assert_eq!(message("1 2 3 <").as_deref(), Some("line 1: '<' takes 1 to 2 operands, got 3"));
+    assert_eq!(message("2 deg").as_deref(), Some("line 1: 'deg' takes no operands, got 1"));
+    assert_eq!(message("1 approx").as_deref(), Some("line 1: 'approx' needs 2 to 3 operands"));
+    assert!(matches!(parse("1 2 3 lt").map_err(EngineError::unlocated), Err(EngineError::OperandCount(op, _, 3)) if op == "lt"));
+
+    // The accumulator does not count, so these stay valid.
+    assert_eq!(Evaluator::new().evaluate_commands(&parse("5 +\n2 <")?)?, Value::Bool(false));
+    assert_eq!(Evaluator::new().evaluate_commands(&parse("3 1 2 sort\nsum")?)?, Value::Operand(6.0));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_lint_chaining() -> Result<(), EngineError> {
+    let program = "5 12 *\n15 -\n\n3 4 *\n= area\n\n2 3 +\n# tax\n5 +\n\n0.2 0.1 +";
+    let findings = lint::check(&[lint::Lint::Chaining], program, &watch::statements(program)?);
+    let lines: Vec<_> = findings.iter().map(|finding| (finding.line, finding.message.as_str())).collect();
+    assert_eq!(lines, [(4, "this line starts a new paragraph but folds in the answer of line 2"), (11, "this line starts a new paragraph but folds in the answer of line 9")]);
+    assert!(lint::check(&[], program, &watch::statements(program)?).is_empty());
+
+    let options = parse_args(["--lint", "chaining", "sheet.qq"].iter().map(|arg| arg.to_string()))?;
+    assert_eq!(options.lints, [lint::Lint::Chaining]);
+    assert!(matches!(parse_args(["--lint", "nope"].iter().map(|arg| arg.to_string())), Err(EngineError::UnknownOption(name)) if name == "nope"));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_explain() -> Result<(), EngineError> {
+    let mut engine = Evaluator::new();
+    let mut steps = vec![];
+    let program = "3 0 +\n= derp\n\n4 6 +\n5 derp +\n# squared\n2 ^\nderp 3 max\n= total";
+    assert_eq!(explain::evaluate(&mut engine, watch::statements(program)?, &mut steps)?, Value::Nothing);
+    let lines: Vec<_> = steps.iter().map(|step| (step.line, step.text.as_str())).collect();
+    assert_eq!(
+        lines,
+        [
+            (1, "3 + 0 = 3"),
+            (2, "derp = (ans:3)"),
+            (4, "4 + 6 = 10"),
+            (5, "(ans:10) + 5 + derp(3) = 18"),
+            (7, "(ans:18) ^ 2 = 324"),
+            (8, "max((ans:324), derp(3), 3) = 324"),
+            (9, "total = (ans:324)"),
+        ]
+    );
+    let mut source = Source::default();
+    source.text = program.into();
+    let diagnostic = explain_diagnostic(&steps[3], Some("audit.qq"), &source);
+    assert_eq!(diagnostic.to_text(), "note at audit.qq:5: (ans:10) + 5 + derp(3) = 18");
+    assert!(diagnostic.to_json().contains("\"severity\":\"note\",\"code\":\"explain\""));
+
+    // Lines before an error keep their explanations.
+    steps.clear();
+    assert!(explain::evaluate(&mut Evaluator::new(), watch::statements("1 2 +\nnope 1 +")?, &mut steps).is_err());
+    assert_eq!(steps.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_symbols() -> Result<(), EngineError> {
+    let commands = parse("5 chair +\n= desk\nchair desk *")?;
+    match (&commands[0], &commands[2]) {
+        (Command::Add(a), Command::Multiply(b)) => assert_eq!(a[1], b[0]),
+        _ => panic!("expected + and *"),
+    }
+    assert_eq!(Symbol::intern("chair"), Symbol::intern("chair"));
+    assert_eq!(&*Symbol::intern("chair"), "chair");
+
+    let mut vars = Vars::default();
+    vars.insert("chair", Value::Operand(2.0));
+    vars.insert("chair", Value::Operand(3.0));
+    assert_eq!(vars.len(), 1);
+    assert_eq!(vars.get_symbol(&Symbol::intern("chair")), Some(&Value::Operand(3.0)));
+    assert_eq!(vars.get("never used anywhere"), None);
+    assert_eq!(vars.remove("chair"), Some(Value::Operand(3.0)));
+    assert_eq!(vars.len(), 0);
+
+    // Values given outside a program intern nothing, and names nothing holds are forgotten.
+    assert!(parse_number("table").is_err());
+    assert!(Symbol::find("table").is_none());
+    vars.insert("stool", Value::Operand(1.0));
+    for i in 0..1000 {
+        Symbol::intern(&format!("passing{}", i));
+    }
+    assert!(Symbol::find("passing0").is_none());
+    assert_eq!(vars.get("stool"), Some(&Value::Operand(1.0)));
+    assert_eq!(Symbol::find("chair").map(|symbol| symbol.to_string()).as_deref(), Some("chair"));
+
+    Ok(())
+}
+
+#[test]
+fn test_function_name_taken() -> Result<(), EngineError> {
+    let message = |program| parse(program).map_err(EngineError::unlocated).err().map(|err| err.to_string());
+    assert_eq!(message("def sum a b\na b +\nend"), Some("cannot define 'sum', it is already the built-in operator `sum`".into()));
+    assert_eq!(message("memo sigma n\nn 2 *\nend"), Some("cannot define 'sigma', it is already the built-in keyword `sigma`".into()));
+    assert_eq!(message("def to-GB n\nn\nend"), Some("cannot define 'to-GB', it is already the conversion to `GB`".into()));
+    assert!(matches!(parse("def total a b\na b +\nend").map(|commands| commands.len()), Ok(1)));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_sandbox() -> Result<(), EngineError> {
+    let dir = std::env::temp_dir().join(format!("qqc-sandbox-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (data, main) = (dir.join("data.json"), dir.join("main.qq"));
+    std::fs::write(&data, "{\"price\": 4}").unwrap();
+    std::fs::write(&main, "include data.qq\n").unwrap();
+
+    let load = format!("load-json {} .price", data.display());
+    assert_eq!(Evaluator::new().evaluate_commands(&parse(&load)?)?, Value::Operand(4.0));
+    let mut engine = Evaluator::builder().sandbox(true).build();
+    let refused = engine.evaluate_commands(&parse(&load)?).err().map(|err| err.to_string());
+    assert_eq!(refused, Some("'load-json' is not permitted in the sandbox".into()));
+    let program = format!("def price\n{}\nend\nprice", load);
+    assert!(matches!(engine.evaluate_commands(&parse(&program)?), Err(EngineError::NotPermitted(_))));
+
+    let options = parse_args(["--sandbox", main.to_str().unwrap()].iter().map(|arg| arg.to_string()))?;
+    let mut source = Source::default();
+    source.sandbox = options.sandbox;
+    assert_eq!(source.load(main.to_str().unwrap()).err().map(|err| err.to_string()), Some("line 1: 'include' is not permitted in the sandbox".into()));
+
+    // `qqc render` and `--literate` run with the same evaluator, so they refuse the same things.
+    let options = parse_args(["--sandbox", "render", main.to_str().unwrap()].iter().map(|arg| arg.to_string()))?;
+    let refused = render::render("include data.json\n", &dir, &mut evaluator(&options)?).err().map(|err| err.to_string());
+    assert_eq!(refused, Some("line 1: 'include' is not permitted in the sandbox".into()));
+    let markdown = format!("```qqc\n{}\n```\n", load);
+    let refused = literate::evaluate(&markdown, &mut evaluator(&options)?).err().map(|err| err.to_string());
+    assert_eq!(refused, Some("line 1: 'load-json' is not permitted in the sandbox".into()));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_runtime_error_lines() -> Result<(), EngineError> {
+    let mut source = Source::default();
+    source.text = "1 2 +\n= a\n\n3 b *\n".into();
+    let err = evaluate_statements(&mut Evaluator::new(), watch::statements(&source.text)?, evaluate_statement).err().unwrap();
+    assert_eq!(err.to_string(), "line 4: missing variable 'b'");
+    let diagnostic = error_diagnostic(&err, Some("sheet.qq"), &source);
+    assert_eq!((diagnostic.line, diagnostic.column), (Some(4), Some(3)));
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_outcome() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    let outcome = evaluator.evaluate(&parse("2 3 +\n= price\ndefer total \"price 4 *\"\nprice 1 -\n0 +\n= left")?)?;
+    assert_eq!(outcome.value, Value::Nothing);
+    let bindings = [("left", 4.0), ("price", 5.0), ("total", 20.0)].map(|(name, value)| (name.to_string(), Value::Operand(value)));
+    assert_eq!(outcome.bindings, bindings);
+    let (four, five) = (Value::Operand(4.0), Value::Operand(5.0));
+    assert_eq!(outcome.history, [five, Value::Nothing, Value::Nothing, four.clone(), four, Value::Nothing]);
+    assert!(outcome.warnings.is_empty());
+
+    let outcome = evaluator.evaluate(&parse("left 1 +")?)?;
+    assert_eq!((outcome.value, outcome.history), (Value::Operand(5.0), vec![Value::Operand(5.0)]));
+
+    // Each command keeps the line it starts on, so errors from running one name it.
+    assert_eq!(parse("2 3 +\n\ndef twice n\nn 2 *\nend\n= x")?.lines, [1, 3, 6]);
+    let err = Evaluator::new().evaluate(&parse("2 3 +\n= a\n\nb 1 +")?).unwrap_err();
+    assert_eq!(err.to_string(), "line 4: missing variable 'b'");
+    let err = Evaluator::new().evaluate(&parse("def twice n\nn 2 *\nend\n5 twice\nmissing 1 +")?).unwrap_err();
+    assert!(matches!(err, EngineError::AtLine(5, err) if matches!(*err, EngineError::MissingVariable(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_roots() -> Result<(), EngineError> {
+    let eval = |program| Evaluator::new().evaluate_commands(&parse(program)?);
+    assert_eq!(eval("27 3 root")?, Value::Operand(3.0));
+    assert_eq!(eval("16 sqrt")?, Value::Operand(4.0));
+    assert_eq!(eval("3 6 +\nsqrt")?, Value::Operand(3.0));
+    assert_eq!(eval("1e6 6 root")?, Value::Operand(10.0));
+    assert_eq!(eval("-8 3 root")?, Value::Operand(-2.0));
+    assert_eq!(eval("2 sqrt")?, Value::Operand(2f64.sqrt()));
+    assert!(matches!(eval("-4 sqrt"), Err(EngineError::OutOfRange)));
+    assert!(matches!(eval("16 0 root"), Err(EngineError::OutOfRange)));
+    assert!(matches!(eval("27 root"), Err(EngineError::MissingOperands)));
+
+    Ok(())
+}
+
+#[cfg(feature = "test-suite")]
+#[test]
+fn test_conformance() {
+    let failures: Vec<_> = conformance::check(conformance::evaluate).into_iter().map(|failure| failure.name).collect();
+    assert!(failures.is_empty(), "failing cases: {:?}", failures);
+    assert_eq!(conformance::to_json(&conformance::CASES[1]), r#"{"name":"divide","program":"7 2 /","answer":"3.5"}"#);
+}
+
+#[test]
+fn test_parse_value() -> Result<(), EngineError> {
+    use tokens::{parse_value, ParseValueError, Token};
+    assert_eq!(parse_value("2.5"), Ok(Token::Number(Value::Operand(2.5))));
+    assert_eq!(parse_value("3_1/4").map(|token| matches!(token, Token::Number(_))), Ok(true));
+    assert_eq!(parse_value("const.c"), Ok(Token::Constant("const.c".into(), 299_792_458.0)));
+    assert_eq!(parse_value("loan.rate"), Ok(Token::Variable("loan.rate".into())));
+    assert_eq!(parse_value("4ac"), Ok(Token::Variable("4ac".into())));
+    assert_eq!(parse_value("$2"), Ok(Token::Variable("$2".into())));
+    assert_eq!(parse_value("1.2.3"), Err(ParseValueError::InvalidNumber("1.2.3".into())));
+    assert_eq!(parse_value("1e999"), Err(ParseValueError::NumberOutOfRange("1e999".into())));
+    assert_eq!(parse_value("const.nope"), Err(ParseValueError::UnknownConstant("const.nope".into())));
+    assert_eq!(parse_value("+"), Err(ParseValueError::InvalidToken("+".into())));
+    assert_eq!(parse_value("a=b").map_err(|err| err.to_string()), Err("'a=b' is neither a number nor a variable name".into()));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_trig() -> Result<(), EngineError> {
+    let eval = |program| Evaluator::new().evaluate_commands(&parse(program)?);
+    assert_eq!(eval("1 sin")?, Value::Operand(1f64.sin()));
+    assert_eq!(eval("0.5 tan")?, Value::Operand(0.5f64.tan()));
+    assert_eq!(eval("deg\n90 sin")?, Value::Operand(1.0));
+    assert_eq!(eval("deg\n180 cos")?, Value::Operand(-1.0));
+    assert_eq!(eval("deg\n-90 cos")?, Value::Operand(0.0));
+    assert_eq!(eval("deg\n60 cos")?, Value::Operand(60f64.to_radians().cos()));
+    assert!(matches!(eval("deg\n270 tan"), Err(EngineError::OutOfRange)));
+    assert_eq!(eval("1 atan")?, Value::Operand(1f64.atan()));
+    assert_eq!(eval("deg\n1 asin")?, Value::Operand(90.0));
+    assert_eq!(eval("deg\n-1 acos")?, Value::Operand(180.0));
+    assert!(matches!(eval("2 asin"), Err(EngineError::OutOfRange)));
+    assert!(matches!(eval("-1.5 acos"), Err(EngineError::OutOfRange)));
+
+    let options = parse_args(["--degrees".to_string(), "-e".into(), "90 sin".into()].into_iter())?;
+    assert_eq!(evaluator(&options)?.evaluate_commands(&parse("360 cos")?)?, Value::Operand(1.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_logarithms() -> Result<(), EngineError> {
+    let eval = |program| Evaluator::new().evaluate_commands(&parse(program)?);
+    assert_eq!(eval("1 ln")?, Value::Operand(0.0));
+    assert_eq!(eval("2 ln")?, Value::Operand(2f64.ln()));
+    assert_eq!(eval("1000 log")?, Value::Operand(3.0));
+    assert_eq!(eval("0.01 log")?, Value::Operand(-2.0));
+    assert_eq!(eval("1024 log2")?, Value::Operand(10.0));
+    assert_eq!(eval("81 3 logn")?, Value::Operand(4.0));
+    assert_eq!(eval("10 3 logn")?, Value::Operand(10f64.ln() / 3f64.ln()));
+    assert!(matches!(eval("0 ln"), Err(EngineError::OutOfRange)));
+    assert!(matches!(eval("-10 log"), Err(EngineError::OutOfRange)));
+    assert!(matches!(eval("8 1 logn"), Err(EngineError::OutOfRange)));
+    assert!(matches!(eval("8 logn"), Err(EngineError::MissingOperands)));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_complete() -> Result<(), EngineError> {
+    use complete::{complete, Kind};
+    let mut engine = Evaluator::new();
+    engine.evaluate_commands(&parse("5 0 +\n= rate\ndef rad2 x\nx 2 *\nend")?)?;
+    let texts = |line: &str, cursor| complete(line, cursor, &engine).into_iter().map(|completion| (completion.text, completion.kind)).collect::<Vec<_>>();
+    let expected = [("rate", Kind::Variable), ("rad2", Kind::Function), ("rad", Kind::Operator), ("radians", Kind::Operator)];
+    assert_eq!(texts("3 ra", 4), expected.map(|(text, kind)| (text.to_string(), kind)));
+    assert_eq!(texts("= ra", 4), [("rate".to_string(), Kind::Variable)]);
+    assert_eq!(texts("3 const.m_", 10).len(), 3);
+    assert!(texts("de", 2).contains(&("defer".to_string(), Kind::Keyword)));
+    assert!(!texts("3 de", 4).iter().any(|(_, kind)| *kind == Kind::Keyword));
+    assert!(texts("3 to-Gi", 7).contains(&("to-GiB".to_string(), Kind::Unit)));
+    assert_eq!(texts("3 ra 4 +", 4), texts("3 ra", 4));
+    assert_eq!(repl::meta_command(":complete 1 rat", &engine).unwrap(), "rate  (variable)");
+
+    Ok(())
+}
+
+#[test]
+fn test_eval_many() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    evaluator.evaluate_commands(&parse("4 0 +\n= qty\ndef price n\nn 2.5 *\nend")?)?;
+    let results = evaluator.eval_many(["qty price", "qty 1 +\n= qty\nqty 0 +", "qty 3 *", "nope 1 +"]);
+    let results: Vec<_> = results.into_iter().map(|result| result.map_err(|err| err.code())).collect();
+    assert_eq!(results, [Ok(Value::Operand(10.0)), Ok(Value::Operand(5.0)), Ok(Value::Operand(12.0)), Err("missing-variable")]);
+    assert_eq!(evaluator.vars["qty"], Value::Operand(4.0));
+    assert_eq!(evaluator.answer, Value::Nothing);
+
+    Ok(())
+}
+
+#[test]
+fn test_interrupt() -> Result<(), EngineError> {
+    let mut evaluator = Evaluator::new();
+    evaluator.interruptible = true;
+    interrupt::raise();
+    assert!(matches!(evaluator.evaluate_commands(&parse("2 3 +")?), Err(EngineError::Interrupted)));
+    // The interrupt is consumed, so the next line runs.
+    assert_eq!(evaluator.evaluate_commands(&parse("2 3 +")?)?, Value::Operand(5.0));
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_tty_modes() -> Result<(), EngineError> {
+    let terminal = tty::Streams { stdin: true, stdout: true, stderr: true };
+    assert_eq!(terminal.mode(), tty::Mode::Repl);
+    assert!(terminal.prompts() && terminal.escapes());
+    let piped = tty::Streams { stdin: false, stdout: true, stderr: true };
+    assert_eq!(piped.mode(), tty::Mode::Filter);
+    assert!(!piped.prompts());
+    assert!(!tty::Streams { stdin: true, stdout: true, stderr: false }.prompts());
+    // `qqc sheet.qq > out.txt` and `qqc --watch sheet.qq | tee log` get no escape codes.
+    let redirected = tty::Streams { stdin: true, stdout: false, stderr: true };
+    assert!(redirected.prompts() && !redirected.escapes());
+
+    Ok(())
+}
+
+// A ZIP archive of (name, method, data) entries, for building workbooks in tests.
+#[cfg(all(test, feature = "xlsx"))]
+fn zip_archive(entries: &[(&str, u16, &[u8])]) -> Vec<u8> {
+    let (mut archive, mut directory) = (vec![], vec![]);
+    for (name, method, data) in entries {
+        let header = |signature: &[u8], skip: usize| {
+            let mut header = signature.to_vec();
+            header.extend(vec![0; skip + 4]);
+            header.extend(method.to_le_bytes());
+            header.extend([0; 8]);
+            header.extend((data.len() as u32).to_le_bytes());
+            header.extend([0; 4]);
+            header.extend((name.len() as u16).to_le_bytes());
+            header
+        };
+        let mut local = header(b"PK\x03\x04", 0);
+        local.extend([0; 2]);
+        let mut central = header(b"PK\x01\x02", 2);
+        central.extend([0; 12]);
+        central.extend((archive.len() as u32).to_le_bytes());
+        for (header, body) in [(&mut archive, local), (&mut directory, central)] {
+            header.extend(body);
+            header.extend(name.as_bytes());
+        }
+        archive.extend(*data);
+    }
+    let offset = archive.len() as u32;
+    let mut end = b"PK\x05\x06".to_vec();
+    end.extend([0; 4]);
+    end.extend((entries.len() as u16).to_le_bytes());
+    end.extend((entries.len() as u16).to_le_bytes());
+    end.extend((directory.len() as u32).to_le_bytes());
+    end.extend(offset.to_le_bytes());
+    end.extend([0; 2]);
+    archive.extend(directory);
+    archive.extend(end);
+    archive
+}
+
+#[cfg(feature = "xlsx")]
+#[test]
+fn test_load_xlsx() -> Result<(), EngineError> {
+    // Rows 1 to 5 with a shared string in A and the row number squared in B, deflated.
+    let sheet: &[u8] = &[
+        125, 208, 109, 10, 132, 32, 16, 128, 225, 171, 132, 23, 152, 252, 90, 8, 166, 129, 162, 139, 72, 4, 193, 254, 8, 76, 234, 250, 213,
+        20, 211, 178, 136, 191, 212, 87, 124, 192, 193, 125, 137, 223, 117, 158, 166, 68, 200, 203, 16, 82, 32, 140, 203, 94, 197, 86, 105,
+        69, 56, 94, 155, 78, 171, 42, 181, 106, 61, 207, 27, 213, 8, 27, 33, 140, 207, 93, 175, 185, 106, 169, 112, 190, 22, 194, 8, 97,
+        10, 132, 225, 234, 242, 132, 21, 194, 22, 8, 203, 181, 201, 19, 78, 8, 87, 32, 220, 253, 145, 79, 222, 240, 98, 248, 130, 225,
+        185, 26, 255, 103, 192, 207, 112, 225, 157, 249, 1,
+    ];
+    let inflated = xlsx::inflate(sheet, xlsx::MAX_PART)?;
+    assert!(String::from_utf8(inflated.clone()).unwrap().ends_with(r#"<c r="B5"><v>25</v></c></row></sheetData></worksheet>"#));
+    // Past the limit inflating stops, however much more the stream would have made.
+    assert!(xlsx::inflate(sheet, inflated.len()).is_ok());
+    assert!(matches!(xlsx::inflate(sheet, 100), Err(EngineError::InvalidXlsx(what)) if what == "part too large"));
+
+    let workbook = br#"<workbook><sheets><sheet name="Q&amp;A" sheetId="1" r:id="rId1"/></sheets></workbook>"#;
+    let relationships = br#"<Relationships><Relationship Id="rId1" Target="worksheets/sheet1.xml"/></Relationships>"#;
+    let archive = zip_archive(&[("xl/workbook.xml", 0, workbook), ("xl/_rels/workbook.xml.rels", 0, relationships), ("xl/worksheets/sheet1.xml", 8, sheet)]);
+    let path = std::env::temp_dir().join(format!("qqc-test-{}.xlsx", std::process::id()));
+    std::fs::write(&path, archive).unwrap();
+
+    let mut evaluator = Evaluator::new();
+    let result = evaluator.evaluate_commands(&parse(&format!("load-xlsx {} \"Q&A\" B2:B4\nsum", path.display()))?)?;
+    assert_eq!(result, Value::Operand(29.0));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-xlsx {} Q&A A1:B1", path.display()))?);
+    assert!(matches!(result, Err(EngineError::InvalidCell(cell)) if cell == "A1"));
+    let result = evaluator.evaluate_commands(&parse(&format!("load-xlsx {} Sheet1 B1", path.display()))?);
+    assert!(matches!(result, Err(EngineError::InvalidXlsx(_))));
+
+    // A cell at the far corner of the grid costs nothing when it is outside the range.
+    let far = br#"<worksheet><sheetData><row r="2"><c r="B2"><v>4</v></c></row><row r="1048576"><c r="XFD1048576"><v>1</v></c></row></sheetData></worksheet>"#;
+    let archive = zip_archive(&[("xl/workbook.xml", 0, workbook), ("xl/_rels/workbook.xml.rels", 0, relationships), ("xl/worksheets/sheet1.xml", 0, far)]);
+    assert_eq!(xlsx::load(&archive, "Q&A", "B2")?, Value::Operand(4.0));
+    assert_eq!(xlsx::load(&archive, "Q&A", "A1:B2")?, Value::List(vec![4.0]));
+
+    // A part declaring a size past the limit is refused unread, as is a directory of too many entries.
+    let mut huge = archive.clone();
+    let central = huge.windows(4).position(|window| window == b"PK\x01\x02").unwrap();
+    huge[central + 24..central + 28].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(xlsx::unzip(&huge, "xl/workbook.xml"), Err(EngineError::InvalidXlsx(what)) if what == "part too large"));
+    let end = archive.len() - 22;
+    let mut crowded = archive.clone();
+    crowded[end + 10..end + 12].copy_from_slice(&u16::MAX.to_le_bytes());
+    assert!(matches!(xlsx::unzip(&crowded, "xl/workbook.xml"), Err(EngineError::InvalidXlsx(what)) if what == "too many entries"));
+
+    std::fs::remove_file(path).ok();
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_grammar() -> Result<(), EngineError> {
+    let textmate = grammar::generate("textmate")?;
+    assert!(textmate.starts_with(r#"{"name":"qqc","scopeName":"source.qqc""#));
+    assert!(textmate.contains("|nextafter|"));
+    assert!(textmate.contains("|to-gib|"));
+    assert!(textmate.contains(r"\\|\\|"));
+
+    let tree_sitter = grammar::generate("tree-sitter")?;
+    assert!(tree_sitter.contains("'molar-mass'"));
+    assert!(tree_sitter.contains("'recover'"));
+    assert!(matches!(grammar::generate("vim"), Err(EngineError::UnknownOption(_))));
+
+    Ok(())
+}
+
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+#[test]
+fn test_clipboard_backends() -> Result<(), EngineError> {
+    assert_eq!(clipboard::backends(true)[0], ["wl-copy"]);
+    assert_eq!(clipboard::backends(false)[0], ["xclip", "-selection", "clipboard"]);
+
+    Ok(())
+}
+
+#[cfg(feature = "jupyter")]
+#[test]
+fn test_jupyter_execute() -> Result<(), EngineError> {
+    let mut kernel = jupyter::Kernel::new(Evaluator::new());
+
+    let request = json::parse(r#"{"msg_type":"execute_request","content":{"code":"2 3 +\n= a"}}"#)?;
+    let replies = kernel.handle(&request);
+    assert_eq!(replies[0].to_string(), r#"{"msg_type":"execute_reply","content":{"status":"ok","execution_count":1}}"#);
+
+    let request = json::parse(r#"{"msg_type":"execute_request","content":{"code":"a 2 *"}}"#)?;
+    let replies = kernel.handle(&request);
+    assert_eq!(replies[0].get("content").and_then(|c| c.get("data")).unwrap().to_string(), r#"{"text/plain":"10"}"#);
+
+    let request = json::parse(r#"{"msg_type":"execute_request","content":{"code":"1 pluss"}}"#)?;
+    let replies = kernel.handle(&request);
+    assert_eq!(replies[0].get("msg_type").and_then(json::Json::as_str), Some("error"));
+
+    // Errors, from parsing or running, name their line within the cell.
+    let mut traceback = |code: &str| -> Result<String, EngineError> {
+        let request = json::parse(&format!(r#"{{"msg_type":"execute_request","content":{{"code":"{}"}}}}"#, code))?;
+        let replies = kernel.handle(&request);
+        Ok(replies[0].get("content").and_then(|c| c.get("traceback")).map(|t| t.to_string()).unwrap_or_default())
+    };
+    assert_eq!(traceback(r"a 1 +\n\n2 pluss")?, r#"["line 3: unknown command 'pluss'"]"#);
+    assert_eq!(traceback(r"def twice n\nn 2 *\nend\n= b\n\nb twice\nmissing 1 +")?, r#"["line 7: missing variable 'missing'"]"#);
+    assert!(!traceback(r"3 twice")?.contains("unknown command")); // Defined by the cell before
+
+    // Every line is answered with a reply, even one the kernel can't serve.
+    let mut reply = |line: &str| kernel.handle_line(line).pop().map(|reply| reply.to_string()).unwrap_or_default();
+    assert_eq!(reply("not json"), r#"{"msg_type":"error_reply","content":{"status":"error","ename":"invalid-json","evalue":"invalid JSON: expected 'null'"}}"#);
+    assert_eq!(reply(r#"{"msg_type":"complete_request","content":{}}"#), r#"{"msg_type":"complete_reply","content":{"status":"error","ename":"unknown-request","evalue":"complete_request"}}"#);
+    assert!(reply(r#"{"msg_type":"execute_request","content":{"code":"b 1 +"}}"#).contains(r#""status":"ok""#));
+
+    Ok(())
+}
+
+// What to do with the file, picked by an optional first word like `qqc render sheet.qq`.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Subcommand {
+    Run,
+    Render,
+    Grammar, // Prints an editor grammar built from the operator table
+    Doctor, // Checks every worksheet below a directory and what they depend on
+    Eval, // Runs the file, or with `--recursive` every worksheet below a directory
+    Test, // Checks the `expect` and `assert` directives of every worksheet below a directory
+    #[cfg(feature = "jupyter")]
+    Kernel,
+    #[cfg(feature = "test-suite")]
+    Conformance, // Runs the conformance cases, or lists them as JSON lines with `--format json`
+}
+
+#[cfg(feature = "std")]
+struct Options {
+    subcommand: Subcommand,
+    literate: bool, // The file is Markdown whose ```qqc blocks are evaluated
+    in_place: bool, // Write `render` and `--literate` results back into the file
+    watch: bool, // Re-run the file whenever it changes
+    symbolic: bool,
+    prompt_missing: bool, // Ask for missing variables when stdin is a terminal
+    cells: bool, // Keep `defer` values until their inputs change, and pin `-D` inputs
+    explain: bool, // Show every line on stderr with its operands substituted and its result
+    journal: bool, // Let the REPL keep a recovery file of the session
+    sandbox: bool, // Refuse file, network and environment access, and keep nothing on disk
+    streams: tty::Streams, // Which standard streams are terminals
+    file: Option<String>,
+    force_file: bool, // Set by `--file` or `--`: never read the arguments as an expression
+    expressions: Vec<String>, // Lines of the program given with `-e`
+    args: Vec<String>, // Everything after the script name, bound as `$1`, `$2`, …
+    defines: Vec<String>,
+    #[cfg(feature = "clipboard")]
+    copy: bool, // Put the final answer on the clipboard too
+    notify: bool, // Show a desktop notification with the outcome of the run
+    test: bool, // Exit with the truth of the final answer
+    profile: bool, // Time every statement and list the slowest
+    stats: bool, // Report peak heap, allocations, commands, variables and history after the run
+    recursive: bool, // `eval` runs every worksheet below the directory and prints a table
+    shared_state: bool, // The arguments after the file are more files, run on the same evaluator
+    files: Vec<String>, // Those later files
+    quiet: bool, // Print nothing at all; only the exit code remains
+    log_level: Option<logging::Level>,
+    log_format: logging::Format,
+    lints: Vec<lint::Lint>, // Checks from `--lint`, reported as warnings before the run
+    diagnostics: logging::Format, // How warnings and errors are written to stderr
+    portable_math: bool, // Software transcendentals with the same bits on every platform
+    degrees: bool, // Start in `deg` mode
+    threads: Option<usize>,
+    format: Option<String>,
+    borders: bool, // Draw tables with Unicode box characters
+    per_line: bool, // `--format table` also lists every statement's result
+    notation: Option<Rc<dyn NumberFormatter>>, // Set by `--notation`, like `fixed:2` or `locale`
+    #[cfg(feature = "net")]
+    fetch_cache: Option<std::time::Duration>, // How long fetched values are reused
+}
+
+#[cfg(feature = "std")]
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, EngineError> {
+    let mut options = Options {
+        subcommand: Subcommand::Run,
+        literate: false,
+        in_place: false,
+        watch: false,
+        symbolic: false,
+        prompt_missing: false,
+        cells: false,
+        explain: false,
+        journal: true,
+        sandbox: false,
+        streams: tty::Streams::default(),
+        file: None,
+        force_file: false,
+        expressions: vec![],
+        args: vec![],
+        defines: vec![],
+        #[cfg(feature = "clipboard")]
+        copy: false,
+        notify: false,
+        test: false,
+        profile: false,
+        stats: false,
+        recursive: false,
+        shared_state: false,
+        files: vec![],
+        quiet: false,
+        log_level: None,
+        log_format: logging::Format::Text,
+        lints: vec![],
+        diagnostics: logging::Format::Text,
+        portable_math: false,
+        degrees: false,
+        threads: None,
+        format: None,
+        borders: false,
+        per_line: false,
+        notation: None,
+        #[cfg(feature = "net")]
+        fetch_cache: None,
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            _ if options.file.is_some() => options.args.push(arg),
+            "render" if options.subcommand == Subcommand::Run => options.subcommand = Subcommand::Render,
+            "grammar" if options.subcommand == Subcommand::Run => options.subcommand = Subcommand::Grammar,
+            "doctor" if options.subcommand == Subcommand::Run => options.subcommand = Subcommand::Doctor,
+            "eval" if options.subcommand == Subcommand::Run => options.subcommand = Subcommand::Eval,
+            "test" if options.subcommand == Subcommand::Run => options.subcommand = Subcommand::Test,
+            #[cfg(feature = "jupyter")]
+            "kernel" if options.subcommand == Subcommand::Run => options.subcommand = Subcommand::Kernel,
+            #[cfg(feature = "test-suite")]
+            "conformance" if options.subcommand == Subcommand::Run => options.subcommand = Subcommand::Conformance,
+            "--file" | "--" => {
+                options.file = Some(args.next().ok_or(EngineError::UnknownOption(arg))?);
+                options.force_file = true;
+            }
+            "--in-place" | "-i" => options.in_place = true,
+            "--literate" => options.literate = true,
+            "--watch" => options.watch = true,
+            "--symbolic" => options.symbolic = true,
+            "--prompt-missing" => options.prompt_missing = true,
+            "--cells" => options.cells = true,
+            "-e" | "--expr" => options.expressions.push(args.next().ok_or(EngineError::UnknownOption(arg))?),
+            "--explain" => options.explain = true,
+            "--no-journal" => options.journal = false,
+            "--sandbox" => options.sandbox = true,
+            "-q" | "--quiet" => options.quiet = true,
+            "--notify" => options.notify = true,
+            "--test" => options.test = true,
+            "--profile" => options.profile = true,
+            "--stats" => options.stats = true,
+            "-r" | "--recursive" => options.recursive = true,
+            "--shared-state" => options.shared_state = true,
+            #[cfg(feature = "clipboard")]
+            "--copy" => options.copy = true,
+            "--portable-math" => options.portable_math = true,
+            "--degrees" => options.degrees = true,
+            "--threads" => {
+                let threads = args.next().ok_or(EngineError::UnknownOption(arg))?;
+                options.threads = Some(threads.parse().map_err(|_| EngineError::UnknownOption(threads))?);
+            }
+            "--format" => options.format = Some(args.next().ok_or(EngineError::UnknownOption(arg))?),
+            "--borders" => options.borders = true,
+            "--per-line" => options.per_line = true,
+            "--notation" => {
+                let spec = args.next().unwrap_or_default();
+                options.notation = Some(notation::parse(&spec).ok_or(EngineError::UnknownOption(spec))?);
+            }
+            #[cfg(feature = "net")]
+            "--fetch-cache" => {
+                let ttl = args.next().unwrap_or_default();
+                options.fetch_cache = Some(net::parse_ttl(&ttl).ok_or(EngineError::UnknownOption(ttl))?);
+            }
+            "--log-level" => {
+                let level = args.next().unwrap_or_default();
+                options.log_level = Some(logging::Level::parse(&level).ok_or(EngineError::UnknownOption(level))?);
+            }
+            "--diagnostics" => match args.next().as_deref() {
+                Some("json") => options.diagnostics = logging::Format::Json,
+                Some("text") => options.diagnostics = logging::Format::Text,
+                other => return Err(EngineError::UnknownOption(other.unwrap_or_default().to_string())),
+            },
+            "--log-format" => match args.next().as_deref() {
+                Some("json") => options.log_format = logging::Format::Json,
+                Some("text") => options.log_format = logging::Format::Text,
+                other => return Err(EngineError::UnknownOption(other.unwrap_or_default().to_string())),
+            },
+            "--lint" => {
+                let names = args.next().ok_or(EngineError::UnknownOption(arg))?;
+                for name in names.split(',') {
+                    options.lints.push(lint::Lint::parse(name).ok_or_else(|| EngineError::UnknownOption(name.into()))?);
+                }
+            }
+            "-D" | "--define" => options.defines.push(args.next().ok_or(EngineError::InvalidDefine(arg))?),
+            define if define.starts_with("-D") => options.defines.push(define[2..].to_string()),
+            flag if flag.starts_with("--") => return Err(EngineError::UnknownOption(arg)),
+            _ => options.file = Some(arg),
+        }
+    }
+    if options.shared_state {
+        options.files = std::mem::take(&mut options.args);
+    }
+    // With `-e` there is no file, so every other argument is one of `$1`, `$2`, …
+    if !options.expressions.is_empty() {
+        if let Some(file) = options.file.take() {
+            options.args.insert(0, file);
+        }
+    }
+
+    Ok(options)
+}
+
+// Quick mode: when the file argument names no file, the arguments are joined into a one-line
+// program, so `qqc 2 3 +` prints 5. Takes the file and arguments out of `options` when it applies.
+// Lines given with `-e` are the program instead, one per flag. A word that looks like a path,
+// like `missing.qq` or `sheets/q3`, is still a file, so a typo fails as a file that can't be read.
+#[cfg(feature = "std")]
+fn quick_expression(options: &mut Options) -> Option<String> {
+    if !options.expressions.is_empty() {
+        return Some(options.expressions.join("\n"));
+    }
+    let file = options.file.as_ref()?;
+    if options.force_file || options.shared_state || file == source::STDIN || Path::new(file).exists() {
+        return None;
+    }
+    let path_like = file.contains(['/', std::path::MAIN_SEPARATOR]) || file.ends_with(".qq") || file.ends_with(".qqc");
+    if path_like && !file.contains(char::is_whitespace) {
+        return None;
+    }
+    let mut words = vec![options.file.take()?];
+    words.append(&mut options.args);
+    Some(words.join(" "))
+}
+
+// An error as a diagnostic, pointing at the offending token when the error names one.
+#[cfg(feature = "std")]
+fn error_diagnostic(err: &EngineError, file: Option<&str>, source: &Source) -> Diagnostic {
+    let origin = err.line().and_then(|line| source.origin(line));
+    let text = err.line().and_then(|line| source.text.lines().nth(line - 1));
+    let column = text.map(|text| {
+        let start = match err.root() {
+            EngineError::UnknownCommand(name)
+            | EngineError::MissingVariable(name)
+            | EngineError::ReadOnlyVariable(name)
+            | EngineError::ConstantReassigned(name, _)
+            | EngineError::NameTaken(name, _) => {
+                text.rfind(name.as_str())
+            }
+            _ => None,
+        };
+        start.unwrap_or_else(|| text.len() - text.trim_start().len()) + 1
+    });
+    Diagnostic {
+        file: origin.map(|origin| origin.file.clone()).or(file.map(String::from)),
+        line: origin.map(|origin| origin.line).or(err.line()),
+        column,
+        severity: "error",
+        code: err.code(),
+        message: err.root().to_string(),
+        suggestion: err.suggestion(),
+        included_from: origin.map(|origin| origin.include_chain()).unwrap_or_default(),
+    }
+}
+
+// A `--lint` finding, placed like an error on its line.
+#[cfg(feature = "std")]
+fn lint_diagnostic(finding: &lint::Finding, file: Option<&str>, source: &Source) -> Diagnostic {
+    let origin = source.origin(finding.line);
+    let text = source.text.lines().nth(finding.line - 1).unwrap_or_default();
+    Diagnostic {
+        file: origin.map(|origin| origin.file.clone()).or(file.map(String::from)),
+        line: Some(origin.map_or(finding.line, |origin| origin.line)),
+        column: Some(text.len() - text.trim_start().len() + 1),
+        severity: "warning",
+        code: finding.code,
+        message: finding.message.clone(),
+        suggestion: Some(finding.suggestion.clone()),
+        included_from: origin.map(|origin| origin.include_chain()).unwrap_or_default(),
+    }
+}
+
+// A line as `--explain` shows it, at the file and line it came from.
+#[cfg(feature = "std")]
+fn explain_diagnostic(step: &explain::Step, file: Option<&str>, source: &Source) -> Diagnostic {
+    let origin = source.origin(step.line);
+    Diagnostic {
+        file: origin.map(|origin| origin.file.clone()).or(file.map(String::from)),
+        line: Some(origin.map_or(step.line, |origin| origin.line)),
+        column: None,
+        severity: "note",
+        code: "explain",
+        message: step.text.clone(),
+        suggestion: None,
+        included_from: origin.map(|origin| origin.include_chain()).unwrap_or_default(),
+    }
+}
+
+#[cfg(feature = "std")]
+fn warning_diagnostic(message: &str, file: Option<&str>) -> Diagnostic {
+    Diagnostic {
+        file: file.map(String::from),
+        line: None,
+        column: None,
+        severity: "warning",
+        code: "warning",
+        message: message.to_string(),
+        suggestion: None,
+        included_from: vec![],
+    }
+}
+
+// Everything that is not the answer goes to stderr, so stdout can be captured as is.
+// Prints an answer on stdout, unless `--quiet` asked for silence.
+#[cfg(feature = "std")]
+fn emit(options: &Options, text: &str) {
+    if !options.quiet {
+        println!("{}", text);
+    }
+}
+
+#[cfg(feature = "std")]
+fn diagnose(options: &Options, diagnostic: &Diagnostic) {
+    if options.quiet {
+        return;
+    }
+    match options.diagnostics {
+        logging::Format::Text => eprintln!("{}", diagnostic.to_text()),
+        logging::Format::Json => eprintln!("{}", diagnostic.to_json()),
+    }
+}
+
+// Asks for a variable on stderr and reads it from stdin like a `-D` value, or None at the end of input.
+#[cfg(feature = "std")]
+fn prompt_stdin(name: &str) -> Option<Value> {
+    prompt_value(name, &mut std::io::stdin().lock(), &mut std::io::stderr())
+}
+
+// Asks again until the answer is a number, so a typo doesn't bind a name.
+#[cfg(feature = "std")]
+fn prompt_value(name: &str, input: &mut impl std::io::BufRead, output: &mut impl Write) -> Option<Value> {
+    loop {
+        write!(output, "{} = ", name).ok();
+        output.flush().ok();
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        match parse_number(line.trim()) {
+            Ok(value) => return Some(value),
+            Err(err) => {
+                writeln!(output, "error: {}", err).ok();
+            }
+        }
+    }
+}
+
+// A fresh evaluator with the variables and settings from the command line.
+#[cfg(feature = "std")]
+fn evaluator(options: &Options) -> Result<Evaluator, EngineError> {
+    let mut builder = Evaluator::builder().symbolic(options.symbolic).cells(options.cells).sandbox(options.sandbox);
+    if options.degrees {
+        builder = builder.angle_mode(AngleMode::Degrees);
+    }
+    if options.prompt_missing && options.streams.prompts() {
+        builder = builder.prompt(prompt_stdin);
+    }
+    let mut engine = builder.build();
+    if let Some(notation) = &options.notation {
+        engine.notation = notation.clone();
+    }
+    if !options.sandbox {
+        engine.bind_environment(std::env::vars_os())?;
+    }
+    engine.define_variables(&options.defines)?;
+    engine.bind_arguments(&options.args)?;
+    Ok(engine)
+}
+
+// Evaluates a program a statement at a time, so an error names the line it came from. Each
+// statement is run by `each`, which can look at the evaluator before and after it runs.
+#[cfg(feature = "std")]
+fn evaluate_statements(
+    engine: &mut Evaluator,
+    statements: Vec<watch::Parsed>,
+    mut each: impl FnMut(&mut Evaluator, &mut watch::Parsed) -> Result<Value, EngineError>,
+) -> Result<Value, EngineError> {
+    let mut answer = Value::Nothing;
+    for mut statement in statements {
+        if engine.halted {
+            break;
+        }
+        if statement.commands.is_empty() {
+            continue; // Blank lines and comments
+        }
+        engine.line = Some(statement.first);
+        let result = each(engine, &mut statement);
+        engine.line = None;
+        answer = result.map_err(|err| err.at_line(statement.last))?;
+    }
+    Ok(answer)
+}
+
+// Runs one statement with nothing to look at around it.
+#[cfg(feature = "std")]
+fn evaluate_statement(engine: &mut Evaluator, statement: &mut watch::Parsed) -> Result<Value, EngineError> {
+    engine.evaluate_commands(&statement.commands)
+}
+
+// What a run kept while evaluating, for the reports printed after its answer.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Trace {
+    names: Vec<String>, // Everything the program binds, for `--format table` and `env`
+    steps: Vec<(usize, Value)>, // The first line and answer of each statement, for `--per-line`
+    explained: Vec<explain::Step>,
+    timings: Vec<profile::Timing>,
+}
+
+// Evaluates the program in `source` or, when there are `files`, loads, parses and evaluates
+// each in turn. With `--shared-state` a file carries on from the variables and functions the
+// one before it left, but starts without its answer, and a file's errors don't stop the ones
+// before it from running.
+#[cfg(feature = "std")]
+fn evaluate_program(options: &Options, engine: &mut Evaluator, source: &mut Source, files: &[&str], trace: &mut Trace) -> Result<Value, EngineError> {
+    let mut functions = HashSet::new();
+    let mut answer = Value::Nothing;
+    let parts: Vec<_> = match files {
+        [] => vec![None],
+        files => files.iter().map(Some).collect(),
+    };
+    for part in parts {
+        if engine.halted {
+            break;
+        }
+        // Each file is read after the ones before it; the text preset by `-e`, quick mode or
+        // stdin is evaluated from its first line.
+        let offset = match part {
+            Some(file) => {
+                let offset = source.text.lines().count();
+                source.load(file)?;
+                offset
+            }
+            None => 0,
+        };
+        let text = source.text.lines().skip(offset).collect::<Vec<_>>().join("\n");
+        let statements = watch::statements_from(&text, offset, &mut functions)?;
+        for finding in lint::check(&options.lints, &source.text, &statements) {
+            diagnose(options, &lint_diagnostic(&finding, options.file.as_deref(), source));
+        }
+        for statement in &statements {
+            bindings(&statement.commands, &mut trace.names);
+        }
+        engine.answer = Value::Nothing;
+        answer = match (options.profile, options.per_line && options.format.as_deref() == Some("table")) {
+            _ if options.explain => explain::evaluate(engine, statements, &mut trace.explained)?,
+            (true, _) => {
+                let (answer, timings) = profile::evaluate(engine, statements)?;
+                trace.timings.extend(timings);
+                answer
+            }
+            (false, true) => {
+                let step = |engine: &mut Evaluator, statement: &mut watch::Parsed| {
+                    let answer = engine.evaluate_commands(&statement.commands)?;
+                    trace.steps.push((statement.first, answer.clone()));
+                    Ok(answer)
+                };
+                evaluate_statements(engine, statements, step)?
+            }
+            (false, false) => evaluate_statements(engine, statements, evaluate_statement)?,
+        };
+    }
+    Ok(answer)
+}
+
+// Evaluates a whole program and prints its answer, returning the exit status.
+#[cfg(feature = "std")]
+fn run(options: &Options, source: &mut Source, files: &[&str]) -> Result<i32, EngineError> {
+    let mut engine = evaluator(options)?;
+    let mut trace = Trace::default();
+    let result = evaluate_program(options, &mut engine, source, files, &mut trace);
+    let Trace { names, steps, explained, timings } = trace;
+    for step in explained {
+        diagnose(options, &explain_diagnostic(&step, options.file.as_deref(), source));
+    }
+    for warning in &engine.warnings {
+        diagnose(options, &warning_diagnostic(warning, options.file.as_deref()));
+    }
+
+    let answer = result?;
+    let mut blocks: Vec<_> = engine.tables.iter().map(|table| table.render(options.borders).join("\n")).collect();
+    let text = match options.format.as_deref() {
+        None => {
+            // A worksheet ending in a scenario whose body only binds variables has its table as the answer.
+            if answer != Value::Nothing || blocks.is_empty() {
+                blocks.push(engine.format(&answer)?);
+            }
+            blocks.join("\n\n")
+        }
+        Some("table") => {
+            let mut rows = vec![];
+            for name in names {
+                if let Some(value) = engine.binding(&name) {
+                    rows.push(vec![name, engine.format(&value?)?]);
+                }
+            }
+            if answer != Value::Nothing {
+                rows.push(vec!["(answer)".into(), engine.format(&answer)?]);
+            }
+            let headers = vec!["name".into(), "value".into()];
+            blocks.push(table::Table { headers, rows, labels: 1 }.render(options.borders).join("\n"));
+            if options.per_line {
+                let mut rows = vec![];
+                for (line, value) in steps {
+                    let place = source.origin(line).map_or(line.to_string(), |origin| origin.line.to_string());
+                    let text = source.text.lines().nth(line - 1).unwrap_or("").trim().to_string();
+                    let value = if value == Value::Nothing { String::new() } else { engine.format(&value)? };
+                    rows.push(vec![place, text, value]);
+                }
+                let headers = vec!["line".into(), "statement".into(), "result".into()];
+                blocks.push(table::Table { headers, rows, labels: 2 }.render(options.borders).join("\n"));
+            }
+            blocks.join("\n\n")
+        }
+        Some("env") => {
+            let mut lines = vec![];
+            for name in names {
+                if let Some(value) = engine.binding(&name) {
+                    lines.push(env_line(&name, &engine.format(&value?)?));
+                }
+            }
+            lines.join("\n")
+        }
+        Some(format) => return Err(EngineError::UnknownOption(format.into())),
+    };
+    emit(options, &text);
+    if options.stats && !options.quiet {
+        eprintln!("stats:");
+        for line in stats::report(&engine) {
+            eprintln!("  {}", line);
+        }
+    }
+    if options.profile && !options.quiet {
+        eprintln!("slowest statements:");
+        for line in profile::report(&timings, source) {
+            eprintln!("{}", line);
+        }
+    }
+    notify_outcome(options, &text);
+    #[cfg(feature = "clipboard")]
+    if options.copy {
+        // The answer is already out, so like a failed notification this only warns.
+        if let Err(err) = clipboard::copy(&text) {
+            diagnose(options, &warning_diagnostic(&err.to_string(), options.file.as_deref()));
+        }
+    }
+
+    test_status(options, &answer)
+}
+
+// The names a program binds with `= name`, in the order they are first bound.
+fn bindings(commands: &[Command], names: &mut Vec<String>) {
+    for command in commands {
+        match command {
+            Command::SetVar(name) | Command::Const(name, _) | Command::Defer(name, _) if !names.contains(name) => names.push(name.clone()),
+            Command::Try(body, fallback) => {
+                bindings(body, names);
+                bindings(fallback, names);
+            }
+            _ => {}
+        }
+    }
+}
+
+// `NAME=value` for `--format env`, with the value single-quoted when a shell would split it.
+#[cfg(feature = "std")]
+fn env_line(name: &str, value: &str) -> String {
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+    let name = if name.starts_with(|c: char| c.is_ascii_digit()) { format!("_{}", name) } else { name };
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "+-.,:/_%".contains(c)) {
+        format!("{}={}", name, value)
+    } else {
+        format!("{}='{}'", name, value.replace('\'', "'\\''"))
+    }
+}
+
+// Reports every problem `qqc doctor` finds, then a summary; the status is 1 if there were any.
+#[cfg(feature = "std")]
+fn doctor(options: &Options) -> Result<i32, EngineError> {
+    let report = doctor::check(Path::new(options.file.as_deref().unwrap_or(".")))?;
+    for problem in report.problems.iter().filter(|_| !options.quiet) {
+        match options.diagnostics {
+            logging::Format::Text => eprintln!("{}", problem.to_located_text()),
+            logging::Format::Json => eprintln!("{}", problem.to_json()),
+        }
+    }
+    let plural = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+    emit(
+        options,
+        &format!(
+            "checked {} and {}: {}",
+            plural(report.worksheets, "worksheet"),
+            plural(report.data_files, "data file"),
+            match report.problems.len() {
+                0 => "no problems".to_string(),
+                n => plural(n, "problem"),
+            }
+        ),
+    );
+    Ok(if report.problems.is_empty() { 0 } else { 1 })
+}
+
+// One row of `eval --recursive`: the file and its formatted answer or error.
+#[cfg(feature = "std")]
+#[derive(PartialEq, Debug)]
+struct BatchResult {
+    file: String,
+    answer: Result<String, String>,
+}
+
+// Runs every worksheet below `dir` on a fresh evaluator.
+#[cfg(feature = "std")]
+fn batch(options: &Options, dir: &Path) -> Result<Vec<BatchResult>, EngineError> {
+    let mut results = vec![];
+    for path in doctor::worksheets(dir)? {
+        let file = path.to_string_lossy().into_owned();
+        let mut source = Source::default();
+        source.sandbox = options.sandbox;
+        let answer = source.load(&file).and_then(|_| {
+            let mut engine = evaluator(options)?;
+            let answer = evaluate_statements(&mut engine, watch::statements(&source.text)?, evaluate_statement)?;
+            engine.format(&answer)
+        });
+        let answer = answer.map_err(|err| error_diagnostic(&err, Some(&file), &source).to_text());
+        results.push(BatchResult { file, answer });
+    }
+    Ok(results)
+}
+
+// The `eval --recursive` summary: a row per file, then how many failed.
+#[cfg(feature = "std")]
+fn batch_table(results: &[BatchResult]) -> Vec<String> {
+    let width = results.iter().map(|result| result.file.chars().count()).max().unwrap_or(0).max("file".len());
+    let mut lines = vec![format!("{:width$}  result", "file")];
+    for BatchResult { file, answer: Ok(text) | Err(text) } in results {
+        lines.push(format!("{:width$}  {}", file, text));
+    }
+    let failed = results.iter().filter(|result| result.answer.is_err()).count();
+    lines.push(format!("{} files, {} failed", results.len(), failed));
+    lines
+}
+
+// With `--test`, a true answer exits with 0 and a false one with 1, like test(1) does.
+#[cfg(feature = "std")]
+fn test_status(options: &Options, answer: &Value) -> Result<i32, EngineError> {
+    match (options.test, answer) {
+        (false, _) => Ok(0),
+        (true, Value::Bool(true)) => Ok(0),
+        (true, Value::Bool(false)) => Ok(1),
+        (true, _) => Err(EngineError::NotABoolean),
+    }
+}
+
+// Errors exit with 2 under `--test`, so they can't be mistaken for a false answer.
+#[cfg(feature = "std")]
+fn failure_status(options: &Options, err: &EngineError) -> i32 {
+    match options.test {
+        true => 2,
+        false => err.exit_code(),
+    }
+}
+
+// With `--notify`, tells the desktop how the run ended; failing to do so is only a warning.
+#[cfg(feature = "std")]
+fn notify_outcome(options: &Options, outcome: &str) {
+    if !options.notify {
+        return;
+    }
+    let body = match &options.file {
+        Some(file) => format!("{}: {}", file, outcome),
+        None => outcome.to_string(),
+    };
+    if let Err(err) = notify::send(&body) {
+        diagnose(options, &warning_diagnostic(&err.to_string(), options.file.as_deref()));
+    }
+}
+
+// `qqc render` writes every answer next to the line producing it, and `--literate` runs
+// the qqc blocks of a Markdown file, printing their answers or writing them under each block.
+#[cfg(feature = "std")]
+fn render_file(options: &Options, file: &str) -> Result<(), EngineError> {
+    let text = std::fs::read_to_string(file).map_err(|_| EngineError::CannotRead(file.into()))?;
+    let mut engine = evaluator(options)?;
+    let rendered = if options.literate {
+        let answers = literate::evaluate(&text, &mut engine)?;
+        if !options.in_place {
+            for answer in answers.into_iter().flatten() {
+                emit(options, &answer);
+            }
+            return Ok(());
+        }
+        literate::write_back(&text, &answers)?
+    } else {
+        render::render(&text, Path::new(file).parent().unwrap_or(Path::new("")), &mut engine)?
+    };
+    if options.in_place {
+        std::fs::write(file, rendered).map_err(|_| EngineError::CannotWrite(file.into()))
+    } else {
+        if !options.quiet {
+            print!("{}", rendered);
+        }
+        Ok(())
+    }
+}
+
+// The `qqc` command line, which is all the binary runs.
+#[cfg(feature = "std")]
+pub fn cli() {
+    let mut options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(err.exit_code());
+        }
+    };
+    options.streams = tty::detect();
+    if let Some(level) = options.log_level {
+        logging::init(level, options.log_format);
+    }
+    if options.stats {
+        stats::start();
+    }
+    math::set_portable(options.portable_math);
+    if let Some(threads) = options.threads {
+        threads::set_threads(threads);
+    }
+    #[cfg(feature = "net")]
+    if let Some(ttl) = options.fetch_cache {
+        net::set_cache_ttl(ttl);
+    }
+
+    if options.subcommand == Subcommand::Grammar {
+        match grammar::generate(options.format.as_deref().unwrap_or("textmate")) {
+            Ok(grammar) => emit(&options, &grammar),
+            Err(err) => {
+                diagnose(&options, &error_diagnostic(&err, None, &Source::default()));
+                std::process::exit(err.exit_code());
+            }
+        }
+        return;
+    }
+
+    if options.subcommand == Subcommand::Doctor {
+        match doctor(&options) {
+            Ok(0) => {}
+            Ok(status) => std::process::exit(status),
+            Err(err) => {
+                diagnose(&options, &error_diagnostic(&err, None, &Source::default()));
+                std::process::exit(err.exit_code());
+            }
+        }
+        return;
+    }
+
+    if options.subcommand == Subcommand::Eval && options.recursive {
+        let result = batch(&options, Path::new(options.file.as_deref().unwrap_or(".")));
+        match result {
+            Ok(results) => {
+                for line in batch_table(&results) {
+                    emit(&options, &line);
+                }
+                if results.iter().any(|result| result.answer.is_err()) {
+                    std::process::exit(1);
+                }
+            }
+            Err(err) => {
+                diagnose(&options, &error_diagnostic(&err, None, &Source::default()));
+                std::process::exit(err.exit_code());
+            }
+        }
+        return;
+    }
+
+    if options.subcommand == Subcommand::Test {
+        let dir = Path::new(options.file.as_deref().unwrap_or("."));
+        match evaluator(&options).and_then(|base| golden::run(dir, &base)) {
+            Ok(results) => {
+                for line in golden::report(&results) {
+                    emit(&options, &line);
+                }
+                if results.iter().any(|result| !result.failures.is_empty()) {
+                    std::process::exit(1);
+                }
+            }
+            Err(err) => {
+                diagnose(&options, &error_diagnostic(&err, None, &Source::default()));
+                std::process::exit(err.exit_code());
+            }
+        }
+        return;
+    }
+
+    #[cfg(feature = "test-suite")]
+    if options.subcommand == Subcommand::Conformance {
+        if options.format.as_deref() == Some("json") {
+            emit(&options, &conformance::CASES.iter().map(conformance::to_json).collect::<Vec<_>>().join("\n"));
+            return;
+        }
+        let failures = conformance::check(conformance::evaluate);
+        let mut lines: Vec<_> = failures.iter().map(|failure| format!("FAIL {}: expected {}, got {}", failure.name, failure.expected, failure.actual)).collect();
+        lines.push(format!("{} cases: {} passed, {} failed", conformance::CASES.len(), conformance::CASES.len() - failures.len(), failures.len()));
+        emit(&options, &lines.join("\n"));
+        std::process::exit(if failures.is_empty() { 0 } else { 1 });
+    }
+
+    #[cfg(feature = "jupyter")]
+    if options.subcommand == Subcommand::Kernel {
+        if let Err(err) = evaluator(&options).and_then(jupyter::run) {
+            diagnose(&options, &error_diagnostic(&err, None, &Source::default()));
+            std::process::exit(err.exit_code());
+        }
+        return;
+    }
+
+    if let Some(expression) = quick_expression(&mut options) {
+        let mut source = Source::default();
+        source.text = expression;
+        match run(&options, &mut source, &[]) {
+            Ok(0) => {}
+            Ok(status) => std::process::exit(status),
+            Err(err) => {
+                diagnose(&options, &error_diagnostic(&err, None, &source));
+                std::process::exit(failure_status(&options, &err));
+            }
+        }
+        return;
+    }
+
+    let Some(file) = &options.file else {
+        let mut source = Source::default();
+        let result = match options.streams.mode() {
+            tty::Mode::Repl => repl::run(&options).map(|_| 0),
+            tty::Mode::Filter => {
+                let read = std::io::read_to_string(std::io::stdin()).map_err(|_| EngineError::CannotRead("stdin".into()));
+                read.and_then(|text| {
+                    source.text = text;
+                    run(&options, &mut source, &[])
+                })
+            }
+        };
+        match result {
+            Ok(0) => {}
+            Ok(status) => std::process::exit(status),
+            Err(err) => {
+                diagnose(&options, &error_diagnostic(&err, None, &source));
+                std::process::exit(failure_status(&options, &err));
+            }
+        }
+        return;
+    };
+    if options.subcommand == Subcommand::Render || options.literate {
+        if let Err(err) = render_file(&options, file) {
+            diagnose(&options, &error_diagnostic(&err, Some(file), &Source::default()));
+            std::process::exit(err.exit_code());
+        }
+        return;
+    }
+
+    if options.watch {
+        if let Err(err) = evaluator(&options).and_then(|engine| watch::run(&options, file, engine)) {
+            diagnose(&options, &error_diagnostic(&err, Some(file), &Source::default()));
+            std::process::exit(err.exit_code());
+        }
+        return;
+    }
+
+    // With `--shared-state`, each file carries on from the state the one before it left.
+    let mut source = Source::default();
+    source.sandbox = options.sandbox;
+    let files: Vec<_> = std::iter::once(file).chain(&options.files).map(String::as_str).collect();
+    match run(&options, &mut source, &files) {
+        Ok(0) => {}
+        Ok(status) => std::process::exit(status),
+        Err(err) => {
+            diagnose(&options, &error_diagnostic(&err, Some(file), &source));
+            notify_outcome(&options, &format!("error: {}", err));
+            std::process::exit(failure_status(&options, &err));
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Opt-in checks picked with `--lint NAME`, for habits that give wrong answers without any
+// error. They read the program without running it and report warnings.
+use crate::{watch, Command};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Lint {
+    // A line starting a new paragraph that still folds in the answer of the one before.
+    Chaining,
+}
+
+impl Lint {
+    pub fn parse(name: &str) -> Option<Lint> {
+        match name {
+            "chaining" => Some(Lint::Chaining),
+            _ => None,
+        }
+    }
+}
+
+pub struct Finding {
+    pub line: usize,
+    pub code: &'static str,
+    pub message: String,
+    pub suggestion: String,
+}
+
+// `statements` are those of `text`, parsed once for both the checks and the run.
+pub fn check(lints: &[Lint], text: &str, statements: &[watch::Parsed]) -> Vec<Finding> {
+    let mut findings = vec![];
+    for lint in lints {
+        match lint {
+            Lint::Chaining => findings.extend(chaining(text, statements)),
+        }
+    }
+    findings.sort_by_key(|finding| finding.line);
+    findings
+}
+
+// After a blank or comment line, a line with operands enough to stand alone looks like a
+// fresh calculation, but the answer left by the paragraph above is still prepended to it.
+fn chaining(text: &str, statements: &[watch::Parsed]) -> Vec<Finding> {
+    let lines: Vec<_> = text.lines().collect();
+    let mut findings = vec![];
+    let mut answer_from = None; // The line whose answer the next one would fold in
+    for statement in statements {
+        if statement.commands.is_empty() {
+            continue;
+        }
+        let after_break = statement.first > 1 && is_break(lines[statement.first - 2]);
+        let stands_alone = statement.commands.first().and_then(Command::operands).is_some_and(|operands| operands.len() >= 2);
+        if let (Some(from), true, true) = (answer_from, after_break, stands_alone) {
+            findings.push(Finding {
+                line: statement.first,
+                code: "lint-chaining",
+                message: format!("this line starts a new paragraph but folds in the answer of line {}", from),
+                suggestion: "end the paragraph above with `= name`, or drop the blank line if it continues".into(),
+            });
+        }
+        for command in &statement.commands {
+            if matches!(command, Command::SetVar(_) | Command::Const(..)) {
+                answer_from = None;
+            } else if matches!(command, Command::Call(..)) || command.operands().is_some() {
+                answer_from = Some(statement.last);
+            }
+        }
+    }
+    findings
+}
+
+fn is_break(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
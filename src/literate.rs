@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+
+use crate::{describe, parse_program_from, EngineError, Evaluator, Value};
+
+const FENCE: &str = "```qqc";
+const RESULT_FENCE: &str = "```qqc-result";
+
+// A fenced qqc block: the line numbers of its opening and closing fences.
+struct Block {
+    open: usize,
+    close: usize,
+}
+
+fn blocks(lines: &[&str]) -> Result<Vec<Block>, EngineError> {
+    let mut blocks = vec![];
+    let mut open = None;
+    for (index, line) in lines.iter().enumerate() {
+        match (open, line.trim_end()) {
+            (None, FENCE) => open = Some(index),
+            (Some(start), "```") => {
+                blocks.push(Block { open: start, close: index });
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    match open {
+        Some(start) => Err(EngineError::UnterminatedBlock.at_line(start + 1)),
+        None => Ok(blocks),
+    }
+}
+
+// Runs every ```qqc block of a Markdown document in order against one evaluator,
+// returning each block's answer.
+pub fn evaluate(markdown: &str, engine: &mut Evaluator) -> Result<Vec<Option<String>>, EngineError> {
+    let lines: Vec<_> = markdown.lines().collect();
+    let mut functions = HashSet::new();
+    let mut answers = vec![];
+    for block in blocks(&lines)? {
+        let code = lines[block.open + 1..block.close].join("\n");
+        let commands = parse_program_from(&code, block.open + 1, &mut functions)?;
+        engine.evaluate_commands(&commands).map_err(|err| err.at_line(block.open + 1))?;
+        answers.push(match &engine.answer {
+            Value::Nothing => None,
+            answer => Some(engine.format(answer).unwrap_or_else(|_| describe(answer))),
+        });
+    }
+    Ok(answers)
+}
+
+// The document with a ```qqc-result block after each qqc block that has an answer,
+// replacing the ones written by an earlier run.
+pub fn write_back(markdown: &str, answers: &[Option<String>]) -> Result<String, EngineError> {
+    let lines: Vec<_> = markdown.lines().collect();
+    let mut output = vec![];
+    let mut next = 0;
+    for (block, answer) in blocks(&lines)?.iter().zip(answers) {
+        output.extend(lines[next..=block.close].iter().map(|line| line.to_string()));
+        next = block.close + 1;
+        // Skip an old result block, along with the blank line before it.
+        let mut at = next;
+        while lines.get(at).is_some_and(|line| line.trim().is_empty()) {
+            at += 1;
+        }
+        if lines.get(at).map(|line| line.trim_end()) == Some(RESULT_FENCE) {
+            if let Some(end) = lines[at + 1..].iter().position(|line| line.trim_end() == "```") {
+                next = at + end + 2;
+            }
+        }
+        if let Some(answer) = answer {
+            output.extend(["".to_string(), RESULT_FENCE.to_string(), answer.clone(), "```".to_string()]);
+        }
+    }
+    output.extend(lines[next..].iter().map(|line| line.to_string()));
+    Ok(output.join("\n") + "\n")
+}
@@ -0,0 +1,123 @@
+use core::fmt;
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
+
+use crate::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Level> {
+        match name {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+struct Logger {
+    level: Level,
+    format: Format,
+}
+
+// Set once from the command line; until then every event is dropped. Without `std` there is
+// no command line and no stderr, so every event is.
+#[cfg(feature = "std")]
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+#[cfg(feature = "std")]
+pub fn init(level: Level, format: Format) {
+    let _ = LOGGER.set(Logger { level, format });
+}
+
+#[cfg(feature = "std")]
+fn logger() -> Option<&'static Logger> {
+    LOGGER.get()
+}
+
+#[cfg(not(feature = "std"))]
+fn logger() -> Option<&'static Logger> {
+    None
+}
+
+pub fn enabled(level: Level) -> bool {
+    logger().is_some_and(|logger| level >= logger.level)
+}
+
+// Escapes a string for use inside a JSON string literal.
+pub fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Writes one event to stderr as `[level] event key=value …` or a JSON object per line.
+#[cfg(feature = "std")]
+pub fn log(level: Level, event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    let Some(logger) = logger().filter(|logger| level >= logger.level) else {
+        return;
+    };
+    let line = match logger.format {
+        Format::Text => {
+            let fields: Vec<_> = fields
+                .iter()
+                .map(|(key, value)| match value.to_string() {
+                    value if value.contains(char::is_whitespace) => format!("{}={:?}", key, value),
+                    value => format!("{}={}", key, value),
+                })
+                .collect();
+            format!("[{}] {} {}", level.name(), event, fields.join(" "))
+        }
+        Format::Json => {
+            let mut parts = vec![
+                format!("\"level\":{}", json_string(level.name())),
+                format!("\"event\":{}", json_string(event)),
+            ];
+            parts.extend(fields.iter().map(|(key, value)| format!("{}:{}", json_string(key), json_string(&value.to_string()))));
+            format!("{{{}}}", parts.join(","))
+        }
+    };
+    eprintln!("{}", line.trim_end());
+}
+
+#[cfg(not(feature = "std"))]
+pub fn log(_: Level, _: &str, _: &[(&str, &dyn fmt::Display)]) {}
+
+pub fn debug(event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    log(Level::Debug, event, fields)
+}
+
+pub fn warn(event: &str, fields: &[(&str, &dyn fmt::Display)]) {
+    log(Level::Warn, event, fields)
+}
@@ -0,0 +1,561 @@
+// Transcendental functions for the evaluator. By default they are the platform's libm, which
+// may differ in the last bit between systems; `--portable-math` switches to the software
+// versions in `portable`, built from IEEE basic operations only, so results are bit-identical
+// everywhere.
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static PORTABLE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_portable(portable: bool) {
+    PORTABLE.store(portable, Ordering::Relaxed);
+}
+
+fn portable() -> bool {
+    PORTABLE.load(Ordering::Relaxed)
+}
+
+pub fn ln(x: f64) -> f64 {
+    if portable() { portable::ln(x) } else { x.ln() }
+}
+
+pub fn log10(x: f64) -> f64 {
+    if portable() { portable::ln(x) / core::f64::consts::LN_10 } else { x.log10() }
+}
+
+pub fn log2(x: f64) -> f64 {
+    if portable() { portable::ln(x) / core::f64::consts::LN_2 } else { x.log2() }
+}
+
+pub fn pow(x: f64, y: f64) -> f64 {
+    if portable() { portable::pow(x, y) } else { x.powf(y) }
+}
+
+pub fn sin(x: f64) -> f64 {
+    if portable() { portable::sin(x) } else { x.sin() }
+}
+
+pub fn cos(x: f64) -> f64 {
+    if portable() { portable::cos(x) } else { x.cos() }
+}
+
+pub fn tan(x: f64) -> f64 {
+    if portable() { portable::sin(x) / portable::cos(x) } else { x.tan() }
+}
+
+pub fn asin(x: f64) -> f64 {
+    if portable() { portable::asin(x) } else { x.asin() }
+}
+
+pub fn acos(x: f64) -> f64 {
+    if portable() { portable::acos(x) } else { x.acos() }
+}
+
+pub fn atan(x: f64) -> f64 {
+    if portable() { portable::atan2(x, 1.0) } else { x.atan() }
+}
+
+pub fn atan2(y: f64, x: f64) -> f64 {
+    if portable() { portable::atan2(y, x) } else { y.atan2(x) }
+}
+
+pub fn hypot(x: f64, y: f64) -> f64 {
+    if portable() { portable::hypot(x, y) } else { x.hypot(y) }
+}
+
+// Ports of the fdlibm/musl algorithms. Only +, -, *, / and sqrt are used, which IEEE 754
+// requires to be correctly rounded, so every platform computes the same bits.
+// The constants are copied digit for digit from fdlibm.
+#[allow(clippy::excessive_precision)]
+pub mod portable {
+    use core::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    // Tests link `std`, whose inherent methods take over from the trait's.
+    #[cfg(not(any(feature = "std", test)))]
+    use super::Float;
+
+    const LN2_HI: f64 = 6.93147180369123816490e-01;
+    const LN2_LO: f64 = 1.90821492927058770002e-10;
+    const PI_LO: f64 = 1.2246467991473531772e-16;
+
+    // x * 2^k without a libm call.
+    fn scale(mut x: f64, mut k: i32) -> f64 {
+        while k > 1023 {
+            x *= f64::from_bits(0x7fe0_0000_0000_0000);
+            k -= 1023;
+        }
+        while k < -1022 {
+            x *= f64::from_bits(0x0010_0000_0000_0000);
+            k += 1022;
+        }
+        x * f64::from_bits(((k + 1023) as u64) << 52)
+    }
+
+    pub fn exp(x: f64) -> f64 {
+        const P: [f64; 5] = [
+            1.66666666666666019037e-01,
+            -2.77777777770155933842e-03,
+            6.61375632143793436117e-05,
+            -1.65339022054652515390e-06,
+            4.13813679705723846039e-08,
+        ];
+        if x.is_nan() {
+            return x;
+        }
+        if x > 7.09782712893383973096e+02 {
+            return f64::INFINITY;
+        }
+        if x < -7.45133219101941108420e+02 {
+            return 0.0;
+        }
+        if x.abs() < 3.725290298461914e-9 {
+            return 1.0 + x;
+        }
+        let k = (x * core::f64::consts::LOG2_E).round();
+        let (hi, lo) = (x - k * LN2_HI, k * LN2_LO);
+        let r = hi - lo;
+        let t = r * r;
+        let c = r - t * (P[0] + t * (P[1] + t * (P[2] + t * (P[3] + t * P[4]))));
+        scale(1.0 - ((lo - (r * c) / (2.0 - c)) - hi), k as i32)
+    }
+
+    pub fn ln(x: f64) -> f64 {
+        const LG: [f64; 7] = [
+            6.666666666666735130e-01,
+            3.999999999940941908e-01,
+            2.857142874366239149e-01,
+            2.222219843214978396e-01,
+            1.818357216161805012e-01,
+            1.531383769920937332e-01,
+            1.479819860511658591e-01,
+        ];
+        let mut x = x;
+        let mut k = 0;
+        if x.is_nan() || x < 0.0 {
+            return f64::NAN;
+        }
+        if x == 0.0 {
+            return f64::NEG_INFINITY;
+        }
+        if x.is_infinite() {
+            return x;
+        }
+        if x == 1.0 {
+            return 0.0;
+        }
+        if x < f64::MIN_POSITIVE {
+            k -= 54;
+            x *= 18014398509481984.0; // 2^54
+        }
+        // Scale x into [sqrt(2)/2, sqrt(2)) and keep the power of two in k.
+        let bits = x.to_bits();
+        let hx = (bits >> 32) as u32 + (0x3ff0_0000 - 0x3fe6_a09e);
+        k += (hx >> 20) as i32 - 0x3ff;
+        let hx = (hx & 0x000f_ffff) + 0x3fe6_a09e;
+        let x = f64::from_bits(((hx as u64) << 32) | (bits & 0xffff_ffff));
+
+        let f = x - 1.0;
+        let hfsq = 0.5 * f * f;
+        let s = f / (2.0 + f);
+        let z = s * s;
+        let w = z * z;
+        let t1 = w * (LG[1] + w * (LG[3] + w * LG[5]));
+        let t2 = z * (LG[0] + w * (LG[2] + w * (LG[4] + w * LG[6])));
+        let dk = k as f64;
+        s * (hfsq + t2 + t1) + dk * LN2_LO - hfsq + f + dk * LN2_HI
+    }
+
+    pub fn pow(x: f64, y: f64) -> f64 {
+        // Small integer powers by repeated squaring, so 2^10 is exactly 1024.
+        if y.fract() == 0.0 && y.abs() <= 1024.0 {
+            let (mut base, mut n, mut result) = (x, y.abs() as u32, 1.0);
+            while n > 0 {
+                if n & 1 == 1 {
+                    result *= base;
+                }
+                base *= base;
+                n >>= 1;
+            }
+            return if y < 0.0 { 1.0 / result } else { result };
+        }
+        if x == 1.0 || y == 0.0 {
+            return 1.0;
+        }
+        if x < 0.0 {
+            return f64::NAN;
+        }
+        if x == 0.0 || x.is_infinite() {
+            return if (y > 0.0) == (x == 0.0) { 0.0 } else { f64::INFINITY };
+        }
+        // The whole part of the exponent exactly, leaving exp a small argument to lose bits on.
+        let whole = y.trunc();
+        let fraction = if y - whole == 0.5 { x.sqrt() } else { exp((y - whole) * ln(x)) };
+        if whole.abs() <= 1024.0 { pow(x, whole) * fraction } else { exp(y * ln(x)) }
+    }
+
+    fn sin_kernel(x: f64, y: f64) -> f64 {
+        const S: [f64; 6] = [
+            -1.66666666666666324348e-01,
+            8.33333333332248946124e-03,
+            -1.98412698298579493134e-04,
+            2.75573137070700676789e-06,
+            -2.50507602534068634195e-08,
+            1.58969099521155010221e-10,
+        ];
+        let z = x * x;
+        let w = z * z;
+        let r = S[1] + z * (S[2] + z * S[3]) + z * w * (S[4] + z * S[5]);
+        let v = z * x;
+        x - ((z * (0.5 * y - v * r) - y) - v * S[0])
+    }
+
+    fn cos_kernel(x: f64, y: f64) -> f64 {
+        const C: [f64; 6] = [
+            4.16666666666666019037e-02,
+            -1.38888888888741095749e-03,
+            2.48015872894767294178e-05,
+            -2.75573143513906633035e-07,
+            2.08757232129817482790e-09,
+            -1.13596475577881948265e-11,
+        ];
+        let z = x * x;
+        let w = z * z;
+        let r = z * (C[0] + z * (C[1] + z * C[2])) + w * w * (C[3] + z * (C[4] + z * C[5]));
+        let hz = 0.5 * z;
+        let w = 1.0 - hz;
+        w + (((1.0 - w) - hz) + (z * r - x * y))
+    }
+
+    fn exponent(x: f64) -> i32 {
+        ((x.to_bits() >> 52) & 0x7ff) as i32
+    }
+
+    // x - n*pi/2 as a head and tail, with the quadrant n mod 4. Pi/2 is taken in three
+    // parts, which keeps full precision for |x| up to about 1e6 and degrades slowly after.
+    fn reduce(x: f64) -> (i32, f64, f64) {
+        const PIO2: [(f64, f64); 3] = [
+            (1.57079632673412561417e+00, 6.07710050650619224932e-11),
+            (6.07710050630396597660e-11, 2.02226624879595063154e-21),
+            (2.02226624871116645580e-21, 8.47842766036889956997e-32),
+        ];
+        let n = (x * core::f64::consts::FRAC_2_PI).round();
+        let mut r = x - n * PIO2[0].0;
+        let mut w = n * PIO2[0].1;
+        let mut y = r - w;
+        for (threshold, (part, tail)) in [(16, PIO2[1]), (49, PIO2[2])] {
+            if exponent(x) - exponent(y) <= threshold {
+                break;
+            }
+            let t = r;
+            w = n * part;
+            r = t - w;
+            w = n * tail - ((t - r) - w);
+            y = r - w;
+        }
+        let quadrant = (n % 4.0 + 4.0) % 4.0;
+        (quadrant as i32, y, (r - y) - w)
+    }
+
+    pub fn sin(x: f64) -> f64 {
+        if !x.is_finite() {
+            return f64::NAN;
+        }
+        if x.abs() <= FRAC_PI_4 {
+            return sin_kernel(x, 0.0);
+        }
+        let (n, y0, y1) = reduce(x);
+        match n {
+            0 => sin_kernel(y0, y1),
+            1 => cos_kernel(y0, y1),
+            2 => -sin_kernel(y0, y1),
+            _ => -cos_kernel(y0, y1),
+        }
+    }
+
+    pub fn cos(x: f64) -> f64 {
+        if !x.is_finite() {
+            return f64::NAN;
+        }
+        if x.abs() <= FRAC_PI_4 {
+            return cos_kernel(x, 0.0);
+        }
+        let (n, y0, y1) = reduce(x);
+        match n {
+            0 => cos_kernel(y0, y1),
+            1 => -sin_kernel(y0, y1),
+            2 => -cos_kernel(y0, y1),
+            _ => sin_kernel(y0, y1),
+        }
+    }
+
+    fn atan(x: f64) -> f64 {
+        const HI: [f64; 4] = [4.63647609000806093515e-01, FRAC_PI_4, 9.82793723247329054082e-01, FRAC_PI_2];
+        const LO: [f64; 4] = [2.26987774529616870924e-17, 3.06161699786838301793e-17, 1.39033110312309984516e-17, 6.12323399573676603587e-17];
+        const T: [f64; 11] = [
+            3.33333333333329318027e-01,
+            -1.99999999998764832476e-01,
+            1.42857142725034663711e-01,
+            -1.11111104054623557880e-01,
+            9.09088713343650656196e-02,
+            -7.69187620504482999495e-02,
+            6.66107313738753120669e-02,
+            -5.83357013379057348645e-02,
+            4.97687799461593236017e-02,
+            -3.65315727442169155270e-02,
+            1.62858201153657823623e-02,
+        ];
+        if x.is_nan() {
+            return x;
+        }
+        let sign = if x < 0.0 { -1.0 } else { 1.0 };
+        let x = x.abs();
+        if x >= 7.378697629483821e19 {
+            return sign * (HI[3] + LO[3]);
+        }
+        let (id, x) = if x < 0.4375 {
+            if x < 7.450580596923828e-9 {
+                return sign * x;
+            }
+            (None, x)
+        } else if x < 0.6875 {
+            (Some(0), (2.0 * x - 1.0) / (2.0 + x))
+        } else if x < 1.1875 {
+            (Some(1), (x - 1.0) / (x + 1.0))
+        } else if x < 2.4375 {
+            (Some(2), (x - 1.5) / (1.0 + 1.5 * x))
+        } else {
+            (Some(3), -1.0 / x)
+        };
+        let z = x * x;
+        let w = z * z;
+        let s1 = z * (T[0] + w * (T[2] + w * (T[4] + w * (T[6] + w * (T[8] + w * T[10])))));
+        let s2 = w * (T[1] + w * (T[3] + w * (T[5] + w * (T[7] + w * T[9]))));
+        match id {
+            None => sign * (x - x * (s1 + s2)),
+            Some(id) => sign * (HI[id] - ((x * (s1 + s2) - LO[id]) - x)),
+        }
+    }
+
+    pub fn atan2(y: f64, x: f64) -> f64 {
+        if x.is_nan() || y.is_nan() {
+            return f64::NAN;
+        }
+        if y == 0.0 {
+            return if x.is_sign_negative() { PI.copysign(y) } else { y };
+        }
+        if x == 0.0 || (y.is_infinite() && x.is_finite()) {
+            return FRAC_PI_2.copysign(y);
+        }
+        if x.is_infinite() {
+            let angle = match (y.is_infinite(), x > 0.0) {
+                (true, true) => FRAC_PI_4,
+                (true, false) => 3.0 * FRAC_PI_4,
+                (false, true) => 0.0,
+                (false, false) => PI,
+            };
+            return angle.copysign(y);
+        }
+        let angle = atan((y / x).abs());
+        if x > 0.0 {
+            angle.copysign(y)
+        } else {
+            (PI - (angle - PI_LO)).copysign(y)
+        }
+    }
+
+    pub fn asin(x: f64) -> f64 {
+        if x.abs() > 1.0 {
+            return f64::NAN;
+        }
+        atan2(x, ((1.0 - x) * (1.0 + x)).sqrt())
+    }
+
+    pub fn acos(x: f64) -> f64 {
+        if x.abs() > 1.0 {
+            return f64::NAN;
+        }
+        atan2(((1.0 - x) * (1.0 + x)).sqrt(), x)
+    }
+
+    pub fn hypot(x: f64, y: f64) -> f64 {
+        let (x, y) = (x.abs(), y.abs());
+        if x.is_infinite() || y.is_infinite() {
+            return f64::INFINITY;
+        }
+        let (big, small) = if x > y { (x, y) } else { (y, x) };
+        if big == 0.0 || big.is_nan() || small.is_nan() {
+            return if small.is_nan() { small } else { big };
+        }
+        let ratio = small / big;
+        big * (1.0 + ratio * ratio).sqrt()
+    }
+}
+
+// Without `std`, `f64` lacks the methods that call into libm. These stand in for them: the
+// rounding and `sqrt` work on the bits, exactly as IEEE 754 defines them, and the rest are
+// the portable versions, so a `no_std` build computes what `--portable-math` does.
+#[cfg(any(test, not(feature = "std")))]
+pub trait Float {
+    fn trunc(self) -> f64;
+    fn floor(self) -> f64;
+    fn ceil(self) -> f64;
+    fn round(self) -> f64;
+    fn fract(self) -> f64;
+    fn rem_euclid(self, rhs: f64) -> f64;
+    fn sqrt(self) -> f64;
+    fn powi(self, n: i32) -> f64;
+    fn powf(self, y: f64) -> f64;
+    fn exp(self) -> f64;
+    fn ln(self) -> f64;
+    fn log10(self) -> f64;
+    fn log2(self) -> f64;
+    fn sin(self) -> f64;
+    fn cos(self) -> f64;
+    fn tan(self) -> f64;
+    fn asin(self) -> f64;
+    fn acos(self) -> f64;
+    fn atan(self) -> f64;
+    fn atan2(self, x: f64) -> f64;
+    fn hypot(self, y: f64) -> f64;
+}
+
+#[cfg(any(test, not(feature = "std")))]
+impl Float for f64 {
+    fn trunc(self) -> f64 {
+        let bits = self.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as i32 - 1023;
+        match exponent {
+            52.. => self, // Already whole, or not finite
+            ..0 => f64::from_bits(bits & (1 << 63)),
+            _ => f64::from_bits(bits & !((1 << (52 - exponent)) - 1)),
+        }
+    }
+
+    fn floor(self) -> f64 {
+        let whole = self.trunc();
+        if whole > self { whole - 1.0 } else { whole }
+    }
+
+    fn ceil(self) -> f64 {
+        let whole = self.trunc();
+        if whole < self { whole + 1.0 } else { whole }
+    }
+
+    // Halves away from zero. The fraction `self - whole` is exact, so nothing rounds early.
+    fn round(self) -> f64 {
+        let whole = self.trunc();
+        if (self - whole).abs() >= 0.5 { whole + 1.0f64.copysign(self) } else { whole }
+    }
+
+    fn fract(self) -> f64 {
+        self - self.trunc()
+    }
+
+    fn rem_euclid(self, rhs: f64) -> f64 {
+        let r = self % rhs;
+        if r < 0.0 { r + rhs.abs() } else { r }
+    }
+
+    // The integer square root of the significand, widened to 106 bits so the root has 53,
+    // rounded up when the remainder shows the exact root is past the halfway point.
+    fn sqrt(self) -> f64 {
+        if self.is_nan() || self < 0.0 {
+            return f64::NAN;
+        }
+        if self == 0.0 || self.is_infinite() {
+            return self;
+        }
+        let bits = self.to_bits();
+        let (mut significand, mut exponent) = match ((bits >> 52) & 0x7ff) as i32 {
+            0 => {
+                let shift = (bits.leading_zeros() - 11) as i32;
+                (bits << shift, -1074 - shift)
+            }
+            biased => ((bits & ((1 << 52) - 1)) | (1 << 52), biased - 1075),
+        };
+        if exponent % 2 != 0 {
+            significand <<= 1;
+            exponent -= 1;
+        }
+        let n = (significand as u128) << 52;
+        let mut root = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+        loop {
+            let next = (root + n / root) / 2;
+            if next >= root {
+                break;
+            }
+            root = next;
+        }
+        if n - root * root > root {
+            root += 1;
+        }
+        (root as f64) * f64::from_bits(((exponent / 2 - 26 + 1023) as u64) << 52)
+    }
+
+    // By repeated squaring, like the compiler's own `powi`.
+    fn powi(self, n: i32) -> f64 {
+        let (mut base, mut rest, mut result) = (self, n.unsigned_abs(), 1.0);
+        loop {
+            if rest & 1 == 1 {
+                result *= base;
+            }
+            rest >>= 1;
+            if rest == 0 {
+                break;
+            }
+            base *= base;
+        }
+        if n < 0 { 1.0 / result } else { result }
+    }
+
+    fn powf(self, y: f64) -> f64 {
+        portable::pow(self, y)
+    }
+
+    fn exp(self) -> f64 {
+        portable::exp(self)
+    }
+
+    fn ln(self) -> f64 {
+        portable::ln(self)
+    }
+
+    fn log10(self) -> f64 {
+        portable::ln(self) / core::f64::consts::LN_10
+    }
+
+    fn log2(self) -> f64 {
+        portable::ln(self) / core::f64::consts::LN_2
+    }
+
+    fn sin(self) -> f64 {
+        portable::sin(self)
+    }
+
+    fn cos(self) -> f64 {
+        portable::cos(self)
+    }
+
+    fn tan(self) -> f64 {
+        portable::sin(self) / portable::cos(self)
+    }
+
+    fn asin(self) -> f64 {
+        portable::asin(self)
+    }
+
+    fn acos(self) -> f64 {
+        portable::acos(self)
+    }
+
+    fn atan(self) -> f64 {
+        portable::atan2(self, 1.0)
+    }
+
+    fn atan2(self, x: f64) -> f64 {
+        portable::atan2(self, x)
+    }
+
+    fn hypot(self, y: f64) -> f64 {
+        portable::hypot(self, y)
+    }
+}
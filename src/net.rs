@@ -0,0 +1,116 @@
+// `fetch url .rates.EUR` takes a number from a JSON endpoint. Requests go through curl, which
+// ships with macOS, Windows 10 and nearly every Linux, so no TLS stack is linked in.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::{EngineError, Value};
+
+pub const TIMEOUT_SECONDS: u32 = 10;
+
+// How long fetched values are reused, in seconds, set by `--fetch-cache`; 0 turns caching off.
+static CACHE_TTL: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_cache_ttl(ttl: Duration) {
+    CACHE_TTL.store(ttl.as_secs(), Ordering::Relaxed);
+}
+
+// `90`, `90s`, `30m`, `1h` or `2d`.
+pub fn parse_ttl(text: &str) -> Option<Duration> {
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    number.parse::<u64>().ok().filter(|n| *n > 0)?.checked_mul(scale).map(Duration::from_secs)
+}
+
+pub fn get(url: &str) -> Result<String, EngineError> {
+    let output = Command::new("curl")
+        // Only the web is fetched, also through redirects: never `file://` or the other schemes curl knows.
+        .args(["--silent", "--show-error", "--fail", "--location", "--proto", "=http,https", "--proto-redir", "=http,https"])
+        .args(["--max-time", &TIMEOUT_SECONDS.to_string(), "--", url])
+        .output()
+        .map_err(|_| EngineError::FetchFailed(format!("{}: curl is not installed", url)))?;
+    if !output.status.success() {
+        return Err(failure(url, output.status.code(), &String::from_utf8_lossy(&output.stderr)));
+    }
+    String::from_utf8(output.stdout).map_err(|_| EngineError::FetchFailed(format!("{}: the response is not UTF-8", url)))
+}
+
+// The error for a failed curl run; exit status 28 is curl's timeout.
+pub fn failure(url: &str, status: Option<i32>, stderr: &str) -> EngineError {
+    match (status, stderr.trim().trim_start_matches("curl: ")) {
+        (Some(28), _) => EngineError::FetchTimeout(url.into()),
+        (_, "") => EngineError::FetchFailed(format!("{}: curl failed", url)),
+        (_, message) => EngineError::FetchFailed(format!("{}: {}", url, message)),
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("LOCALAPPDATA")?),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(base.join("qqc").join("fetch"))
+}
+
+// Cache files are named by an FNV-1a hash of the URL and selector, and start with both so that
+// a hash collision reads as a miss.
+fn cache_file(dir: &Path, url: &str, selector: &str) -> PathBuf {
+    let hash = format!("{}\0{}", url, selector).bytes().fold(0xcbf29ce484222325u64, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3));
+    dir.join(format!("{:016x}", hash))
+}
+
+// A value stored less than `ttl` ago.
+pub fn lookup(dir: &Path, url: &str, selector: &str, ttl: Duration) -> Option<Value> {
+    let file = cache_file(dir, url, selector);
+    let age = std::fs::metadata(&file).and_then(|metadata| metadata.modified()).ok()?.elapsed().unwrap_or_default();
+    if age >= ttl {
+        return None;
+    }
+    let text = std::fs::read_to_string(file).ok()?;
+    let mut lines = text.lines();
+    if lines.next() != Some(url) || lines.next() != Some(selector) {
+        return None;
+    }
+    let (kind, numbers) = lines.next()?.split_once(' ').unwrap_or(("list", ""));
+    let numbers = numbers.split_whitespace().map(|n| n.parse().ok()).collect::<Option<Vec<f64>>>()?;
+    match (kind, numbers.as_slice()) {
+        ("number", [n]) => Some(Value::Operand(*n)),
+        ("list", _) => Some(Value::List(numbers)),
+        _ => None,
+    }
+}
+
+// Caching is best effort: a value that can't be stored is simply fetched again next time.
+pub fn store(dir: &Path, url: &str, selector: &str, value: &Value) {
+    let numbers = match value {
+        Value::Operand(n) => format!("number {}", n),
+        Value::List(items) => format!("list {}", items.iter().map(f64::to_string).collect::<Vec<_>>().join(" ")),
+        _ => return,
+    };
+    if std::fs::create_dir_all(dir).is_ok() {
+        std::fs::write(cache_file(dir, url, selector), format!("{}\n{}\n{}\n", url, selector, numbers)).ok();
+    }
+}
+
+// The value at `selector` in the document at `url`, from the cache when `--fetch-cache` allows.
+pub fn fetch(url: &str, selector: &str, select: impl FnOnce(&str) -> Result<Value, EngineError>) -> Result<Value, EngineError> {
+    let ttl = Duration::from_secs(CACHE_TTL.load(Ordering::Relaxed));
+    let dir = cache_dir().filter(|_| !ttl.is_zero());
+    if let Some(value) = dir.as_deref().and_then(|dir| lookup(dir, url, selector, ttl)) {
+        return Ok(value);
+    }
+    let value = select(&get(url)?)?;
+    if let Some(dir) = dir {
+        store(&dir, url, selector, &value);
+    }
+    Ok(value)
+}
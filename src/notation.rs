@@ -0,0 +1,203 @@
+// How plain numbers in an answer are written, picked with `--notation` or supplied by an
+// embedder through `EvaluatorBuilder::notation`. Display modes like `to-base 16` or `i8` and
+// quantities with units keep their own formatting.
+
+use crate::format_number;
+use crate::prelude::*;
+
+pub trait NumberFormatter {
+    fn format(&self, x: f64) -> String;
+
+    // An exact integer too long for an f64, as its decimal digits with an optional `-`.
+    fn format_integer(&self, digits: &str) -> String {
+        digits.to_string()
+    }
+}
+
+// The shortest text that reads back as the same number, like `0.1` or `42`.
+pub struct Plain;
+
+// A fixed number of decimals, like `3.14` for `fixed:2`.
+pub struct Fixed(pub usize);
+
+// `1.2345e3`; with a digit count, rounded to that many significant digits.
+pub struct Scientific(pub Option<usize>);
+
+// Like scientific, with the exponent a multiple of three: `12.345e3`.
+pub struct Engineering(pub Option<usize>);
+
+// Digit grouping and decimal mark, like `1.234.567,5` for German.
+pub struct Locale {
+    pub group: char,
+    pub decimal: char,
+}
+
+// An amount with a currency symbol, like `$1,234.50`.
+pub struct Currency {
+    pub symbol: String,
+    pub decimals: usize,
+    pub locale: Locale,
+}
+
+impl NumberFormatter for Plain {
+    fn format(&self, x: f64) -> String {
+        format_number(x)
+    }
+}
+
+impl NumberFormatter for Fixed {
+    fn format(&self, x: f64) -> String {
+        match x.is_finite() {
+            true => format!("{:.*}", self.0, x),
+            false => format_number(x),
+        }
+    }
+}
+
+impl NumberFormatter for Scientific {
+    fn format(&self, x: f64) -> String {
+        match (x.is_finite(), self.0) {
+            (false, _) => format_number(x),
+            (true, Some(digits)) => format!("{:.*e}", digits.max(1) - 1, x),
+            (true, None) => format!("{:e}", x),
+        }
+    }
+
+    fn format_integer(&self, digits: &str) -> String {
+        digits.parse().map_or_else(|_| digits.to_string(), |x| self.format(x))
+    }
+}
+
+impl NumberFormatter for Engineering {
+    fn format(&self, x: f64) -> String {
+        if !x.is_finite() {
+            return format_number(x);
+        }
+        // Moves the point of the scientific form instead of dividing, which could round.
+        let scientific = Scientific(self.0).format(x);
+        let (mantissa, exponent) = scientific.split_once('e').expect("scientific notation has an exponent");
+        let exponent: i32 = exponent.parse().expect("scientific exponent is an integer");
+        let (sign, mantissa) = match mantissa.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", mantissa),
+        };
+        let mut digits = mantissa.replace('.', "");
+        let shift = exponent.rem_euclid(3) as usize;
+        while digits.len() < shift + 1 {
+            digits.push('0');
+        }
+        let (whole, fraction) = digits.split_at(shift + 1);
+        match fraction.is_empty() {
+            true => format!("{}{}e{}", sign, whole, exponent - shift as i32),
+            false => format!("{}{}.{}e{}", sign, whole, fraction, exponent - shift as i32),
+        }
+    }
+
+    fn format_integer(&self, digits: &str) -> String {
+        digits.parse().map_or_else(|_| digits.to_string(), |x| self.format(x))
+    }
+}
+
+impl Locale {
+    pub const ENGLISH: Locale = Locale { group: ',', decimal: '.' };
+
+    // The conventions of a locale name like `de_DE.UTF-8`, by its language.
+    pub fn named(name: &str) -> Locale {
+        let language = name.split(['_', '-', '.', '@']).next().unwrap_or_default().to_ascii_lowercase();
+        match language.as_str() {
+            "de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" | "el" | "ro" => Locale { group: '.', decimal: ',' },
+            "fr" | "ru" | "pl" | "sv" | "fi" | "nb" | "cs" | "uk" | "hu" | "sk" => Locale { group: ' ', decimal: ',' },
+            _ => Locale::ENGLISH,
+        }
+    }
+
+    // The locale numbers are written in, from `LC_ALL`, `LC_NUMERIC` or `LANG`.
+    #[cfg(feature = "std")]
+    pub fn from_env() -> Locale {
+        let name = ["LC_ALL", "LC_NUMERIC", "LANG"].iter().find_map(|var| std::env::var(var).ok().filter(|name| !name.is_empty()));
+        Locale::named(&name.unwrap_or_default())
+    }
+
+    // Without `std` there is no environment to read.
+    #[cfg(not(feature = "std"))]
+    pub fn from_env() -> Locale {
+        Locale::ENGLISH
+    }
+
+    // `-1234567.5` as `-1,234,567.5`; an exponent like in `1e300` is kept as is.
+    fn apply(&self, text: &str) -> String {
+        let (sign, text) = match text.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", text),
+        };
+        let (whole, rest) = text.split_at(text.find(['.', 'e']).unwrap_or(text.len()));
+        let mut grouped = String::new();
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0 && (whole.len() - i) % 3 == 0 {
+                grouped.push(self.group);
+            }
+            grouped.push(digit);
+        }
+        format!("{}{}{}", sign, grouped, rest.replacen('.', &self.decimal.to_string(), 1))
+    }
+}
+
+impl NumberFormatter for Locale {
+    fn format(&self, x: f64) -> String {
+        match x.is_finite() {
+            true => self.apply(&format_number(x)),
+            false => format_number(x),
+        }
+    }
+
+    fn format_integer(&self, digits: &str) -> String {
+        self.apply(digits)
+    }
+}
+
+impl NumberFormatter for Currency {
+    fn format(&self, x: f64) -> String {
+        if !x.is_finite() {
+            return format_number(x);
+        }
+        let amount = self.locale.apply(&format!("{:.*}", self.decimals, x.abs()));
+        let sign = if x < 0.0 && amount.bytes().any(|b| (b'1'..=b'9').contains(&b)) { "-" } else { "" };
+        format!("{}{}{}", sign, self.symbol, amount)
+    }
+
+    fn format_integer(&self, digits: &str) -> String {
+        let (sign, digits) = match digits.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", digits),
+        };
+        let cents = match self.decimals {
+            0 => String::new(),
+            decimals => format!("{}{}", self.locale.decimal, "0".repeat(decimals)),
+        };
+        format!("{}{}{}{}", sign, self.symbol, self.locale.apply(digits), cents)
+    }
+}
+
+// A `--notation` value: `plain`, `fixed:N`, `sci`, `sci:N`, `eng`, `eng:N`, `locale`,
+// `locale:NAME`, `currency` or `currency:SYMBOL`. Locales default to the environment's.
+pub fn parse(spec: &str) -> Option<Rc<dyn NumberFormatter>> {
+    let (name, argument) = match spec.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (spec, None),
+    };
+    // No digit count means the shortest form; a bad one makes the whole spec invalid.
+    let digits = |argument: Option<&str>| match argument {
+        None => Some(None),
+        Some(digits) => digits.parse().ok().filter(|n| *n > 0).map(Some),
+    };
+    Some(match (name, argument) {
+        ("plain", None) => Rc::new(Plain),
+        ("fixed", Some(decimals)) => Rc::new(Fixed(decimals.parse().ok()?)),
+        ("sci", argument) => Rc::new(Scientific(digits(argument)?)),
+        ("eng", argument) => Rc::new(Engineering(digits(argument)?)),
+        ("locale", None) => Rc::new(Locale::from_env()),
+        ("locale", Some(name)) => Rc::new(Locale::named(name)),
+        ("currency", symbol) => Rc::new(Currency { symbol: symbol.unwrap_or("$").to_string(), decimals: 2, locale: Locale::from_env() }),
+        _ => return None,
+    })
+}
@@ -0,0 +1,37 @@
+// `--notify` shows a desktop notification through the platform's own tool once a run finishes.
+use std::process::{Command, Stdio};
+
+use crate::EngineError;
+
+// The program and arguments showing `body` under the title "qqc".
+pub fn command(body: &str) -> Vec<String> {
+    if cfg!(target_os = "macos") {
+        let quoted = body.replace('\\', "\\\\").replace('"', "\\\"");
+        vec!["osascript".into(), "-e".into(), format!("display notification \"{}\" with title \"qqc\"", quoted)]
+    } else if cfg!(windows) {
+        let quoted = body.replace('\'', "''");
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms; $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Information; $n.Visible = $true; \
+             $n.ShowBalloonTip(5000, 'qqc', '{}', 'Info'); Start-Sleep 5",
+            quoted
+        );
+        vec!["powershell".into(), "-NoProfile".into(), "-Command".into(), script]
+    } else {
+        vec!["notify-send".into(), "qqc".into(), body.into()]
+    }
+}
+
+pub fn send(body: &str) -> Result<(), EngineError> {
+    let command = command(body);
+    let mut process = Command::new(&command[0]);
+    process.args(&command[1..]).stdout(Stdio::null()).stderr(Stdio::null());
+    // PowerShell stays up for as long as the balloon shows, so it is left to finish on its own.
+    if cfg!(windows) {
+        return process.spawn().map(drop).map_err(|_| EngineError::CannotWrite("notification".into()));
+    }
+    match process.status() {
+        Ok(status) if status.success() => Ok(()),
+        _ => Err(EngineError::CannotWrite("notification".into())),
+    }
+}
@@ -0,0 +1,222 @@
+use core::fmt;
+
+use crate::*;
+
+// Postfix operators come last on a line, prefix ones first and are parsed by `parse_lines` itself.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Position {
+    Postfix,
+    Prefix,
+}
+
+// How many operands may be written before a postfix operator, not counting the accumulator,
+// or after a prefix one. Checked for postfix operators before their parser runs, so a wrong
+// count fails with the operator's name.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Operands {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Operands {
+    pub fn check(&self, op: &str, found: usize) -> Result<(), EngineError> {
+        match found < self.min || self.max.is_some_and(|max| found > max) {
+            true => Err(EngineError::OperandCount(op.into(), *self, found)),
+            false => Ok(()),
+        }
+    }
+}
+
+// `no operands`, `one operand`, `2 operands`.
+fn count(n: usize) -> String {
+    match n {
+        0 => "no operands".into(),
+        1 => "one operand".into(),
+        n => format!("{} operands", n),
+    }
+}
+
+impl fmt::Display for Operands {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.max {
+            None => write!(f, "at least {}", count(self.min)),
+            Some(max) if max == self.min => write!(f, "{}", count(max)),
+            Some(max) => write!(f, "{} to {} operands", self.min, max),
+        }
+    }
+}
+
+pub type Parser = fn(&[&str]) -> Result<Command, EngineError>;
+
+pub struct Operator {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub position: Position,
+    pub operands: Operands,
+    pub description: &'static str,
+    pub parse: Option<Parser>,
+}
+
+const fn postfix(name: &'static str, aliases: &'static [&'static str], operands: Operands, description: &'static str, parse: Parser) -> Operator {
+    Operator { name, aliases, position: Position::Postfix, operands, description, parse: Some(parse) }
+}
+
+// Prefix operators check their own operands; the count here is for `:help`.
+const fn prefix(name: &'static str, operands: Operands, description: &'static str) -> Operator {
+    Operator { name, aliases: &[], position: Position::Prefix, operands, description, parse: None }
+}
+
+const fn exactly(n: usize) -> Operands {
+    between(n, n)
+}
+
+const fn between(min: usize, max: usize) -> Operands {
+    Operands { min, max: Some(max) }
+}
+
+const fn at_least(min: usize) -> Operands {
+    Operands { min, max: None }
+}
+
+const NO_OPERANDS: Operands = between(0, 0);
+
+// Every built-in operator. The parser dispatches postfix operators through this table,
+// and `:help` lists it, so an entry here is all a new operator needs to be found and documented.
+pub const OPERATORS: &[Operator] = &[
+    postfix("+", &["plus", "add"], at_least(1), "adds the values", parse_add),
+    postfix("-", &["minus", "subtract"], at_least(1), "subtracts the rest from the first value", parse_subtract),
+    postfix("*", &["x", "times", "multiply"], at_least(1), "multiplies the values", parse_multiply),
+    postfix("/", &["div", "divide"], at_least(1), "divides the first value by the rest", parse_divide),
+    postfix("^", &["**", "power"], at_least(1), "raises the first value to the rest in turn", parse_power),
+    postfix("%", &["mod", "modulus", "modulo"], at_least(1), "remainder of the first value by the rest", parse_modulo),
+    postfix("ulp", &[], between(0, 1), "gap to the next representable float", parse_ulp),
+    postfix("sqrt", &[], between(0, 1), "square root", parse_sqrt),
+    postfix("root", &[], between(0, 2), "x n root is the nth root of x, negative for odd n and negative x", parse_root),
+    postfix("ln", &[], between(0, 1), "natural logarithm", parse_log),
+    postfix("log", &[], between(0, 1), "base-10 logarithm", parse_log),
+    postfix("log2", &[], between(0, 1), "base-2 logarithm", parse_log),
+    postfix("logn", &[], between(0, 2), "x b logn is the logarithm of x in base b", parse_log),
+    postfix("nextafter", &[], between(0, 2), "next float after the first value toward the second", parse_next_after),
+    postfix("approx", &[], between(2, 3), "whether actual is within tolerance of expected", parse_approx),
+    postfix("<", &["lt"], between(1, 2), "whether the first value is less than the second", parse_compare),
+    postfix("<=", &["le"], between(1, 2), "whether the first value is at most the second", parse_compare),
+    postfix(">", &["gt"], between(1, 2), "whether the first value is greater than the second", parse_compare),
+    postfix(">=", &["ge"], between(1, 2), "whether the first value is at least the second", parse_compare),
+    postfix("==", &["eq"], between(1, 2), "whether the two values are equal", parse_compare),
+    postfix("!=", &["ne"], between(1, 2), "whether the two values differ", parse_compare),
+    postfix("simplify", &[], NO_OPERANDS, "simplifies a symbolic answer", parse_simplify),
+    postfix("solve", &[], between(0, 2), "solves a linear equation between two sides for its unknown", parse_solve),
+    postfix("isprime", &[], between(0, 1), "whether a natural number is prime", parse_is_prime),
+    postfix("factor", &[], between(0, 1), "prime factors of a natural number", parse_factor),
+    postfix("choose", &["ncr"], between(0, 2), "ways to choose k of n", parse_choose),
+    postfix("permute", &["npr"], between(0, 2), "ordered ways to pick k of n", parse_permute),
+    postfix("powmod", &[], between(2, 3), "base to the exponent modulo m, exactly", parse_pow_mod),
+    postfix("diff", &[], between(0, 2), "time between two timestamps", parse_diff),
+    postfix("transfer-time", &[], between(0, 2), "time to move a size at a data rate", parse_transfer_time),
+    postfix("hz-to-period", &[], between(0, 1), "period of a frequency", parse_reciprocal),
+    postfix("period-to-hz", &[], between(0, 1), "frequency of a period", parse_reciprocal),
+    postfix("parallel", &["||"], at_least(1), "resistors in parallel", parse_parallel),
+    postfix("min", &[], at_least(1), "smallest of the values", parse_extremum),
+    postfix("max", &[], at_least(1), "largest of the values", parse_extremum),
+    postfix("sum", &[], at_least(0), "sum of the values and any list among them", parse_aggregate),
+    postfix("mean", &[], at_least(0), "mean of the values and any list among them", parse_aggregate),
+    postfix("stddev", &[], at_least(0), "sample standard deviation of the values and lists", parse_aggregate),
+    postfix("sort", &[], at_least(0), "the values and lists as one sorted list", parse_aggregate),
+    postfix("vdiv", &[], between(2, 3), "voltage divider output for vin r1 r2", parse_voltage_divider),
+    postfix("sin", &[], between(0, 1), "sine of an angle in the angle mode", parse_trig),
+    postfix("cos", &[], between(0, 1), "cosine of an angle in the angle mode", parse_trig),
+    postfix("tan", &[], between(0, 1), "tangent of an angle in the angle mode", parse_trig),
+    postfix("asin", &[], between(0, 1), "angle whose sine is the value, in the angle mode", parse_inverse_trig),
+    postfix("acos", &[], between(0, 1), "angle whose cosine is the value, in the angle mode", parse_inverse_trig),
+    postfix("atan", &[], between(0, 1), "angle whose tangent is the value, in the angle mode", parse_inverse_trig),
+    postfix("deg", &["degrees"], NO_OPERANDS, "reads and shows angles in degrees", parse_angle),
+    postfix("rad", &["radians"], NO_OPERANDS, "reads and shows angles in radians", parse_angle),
+    postfix("km", &[], NO_OPERANDS, "gives distances in kilometres", parse_distance),
+    postfix("mi", &[], NO_OPERANDS, "gives distances in miles", parse_distance),
+    postfix("to-polar", &[], between(0, 2), "x y as a [radius, angle] list", parse_coordinates),
+    postfix("to-cartesian", &[], between(0, 2), "radius angle as an [x, y] list", parse_coordinates),
+    postfix("i8", &[], NO_OPERANDS, "shows the answer as a signed 8-bit integer", parse_display),
+    postfix("i16", &[], NO_OPERANDS, "shows the answer as a signed 16-bit integer", parse_display),
+    postfix("i32", &[], NO_OPERANDS, "shows the answer as a signed 32-bit integer", parse_display),
+    postfix("u32", &[], NO_OPERANDS, "shows the answer as an unsigned 32-bit integer", parse_display),
+    postfix("bits", &[], NO_OPERANDS, "shows the bits of the answer", parse_display),
+    postfix("to-clock", &[], NO_OPERANDS, "shows seconds as hh:mm", parse_display),
+    // Matched against the unit table by the parser rather than by name.
+    Operator {
+        name: "to-<unit>",
+        aliases: &[],
+        position: Position::Postfix,
+        operands: NO_OPERANDS,
+        description: "shows the answer in a unit, like to-gib or to-ms",
+        parse: None,
+    },
+    prefix("=", exactly(1), "= name stores the answer in a variable"),
+    prefix("deriv", exactly(1), "deriv v differentiates the answer by v"),
+    prefix("def", at_least(1), "def name params... starts a function ending at 'end'"),
+    prefix("memo", at_least(1), "memo name params... is a def that caches its results and sees only its parameters"),
+    prefix("findroot", exactly(3), "findroot f a b finds a root of f between a and b"),
+    prefix("integrate", exactly(3), "integrate f a b integrates f from a to b"),
+    prefix("sigma", exactly(3), "sigma i from to 'line sums the line over i"),
+    prefix("prod", exactly(3), "prod i from to 'line multiplies the line over i"),
+    prefix("to-base", exactly(1), "to-base N shows integers in radix N"),
+    prefix("mixed", at_least(0), "mixed [N] shows mixed numbers, optionally to the nearest 1/N"),
+    prefix("sigfigs", exactly(0), "tracks significant figures from here on"),
+    prefix("scenario", at_least(2), "scenario rate 0.03 0.05 ... end runs the block per value and tabulates the results"),
+    prefix("try", exactly(0), "try ... recover ... end runs the fallback if the block fails"),
+    prefix("error", at_least(1), "error \"message\" stops with a custom error"),
+    prefix("exit", at_least(0), "exit [value] stops early with the answer or the value"),
+    prefix("const", exactly(1), "const name, or = name const, binds the answer for good"),
+    prefix("defer", at_least(2), "defer name \"expr\" recomputes the expression whenever name is read"),
+    prefix("vars", at_least(0), "vars [loan.*] lists the variables, or those under a prefix"),
+    prefix("unset", exactly(1), "unset name or unset loan.* removes variables"),
+    prefix("snapshot", exactly(1), "snapshot name saves the variables, answer, functions and modes"),
+    prefix("restore", exactly(1), "restore name goes back to a snapshot"),
+    prefix("expect", exactly(1), "expect value checks that the answer equals the value, for qqc test"),
+    prefix("assert", at_least(1), "assert 10 < checks that the rest of the line is true for the answer, for qqc test"),
+    prefix("molar-mass", exactly(1), "molar-mass H2O is the mass of a formula in g/mol"),
+    prefix("haversine", exactly(4), "haversine lat1 lon1 lat2 lon2 is the great-circle distance"),
+    prefix("include", exactly(1), "include path splices in another file"),
+    prefix("load-csv", exactly(1), "load-csv path makes the cells of a CSV file operands like A1 and B2:B9"),
+    prefix("load-json", exactly(2), "load-json path .items[3].price is a number or list of numbers in a JSON file"),
+    prefix("load-config", exactly(2), "load-config Cargo.toml package.metadata.budget is a number from a TOML, YAML or JSON file"),
+    #[cfg(feature = "net")]
+    prefix("fetch", exactly(2), "fetch url .rates.EUR is a number from a JSON document at a URL"),
+    #[cfg(feature = "xlsx")]
+    prefix("load-xlsx", exactly(3), "load-xlsx path sheet B2:B20 is the numbers in a range of an Excel workbook"),
+];
+
+// Finds an operator by its name or one of its aliases.
+pub fn find(token: &str) -> Option<&'static Operator> {
+    OPERATORS.iter().find(|op| op.name == token || op.aliases.contains(&token))
+}
+
+// What a new function name would collide with: an operator named or aliased so, or a unit
+// conversion. The parser tries those first, so a function by such a name could never run.
+pub fn conflicts(name: &str) -> Vec<String> {
+    let mut conflicts = vec![];
+    for op in OPERATORS {
+        let position = match op.position {
+            Position::Postfix => "operator",
+            Position::Prefix => "keyword",
+        };
+        if op.name == name {
+            conflicts.push(format!("the built-in {} `{}`", position, op.name));
+        } else if op.aliases.contains(&name) {
+            conflicts.push(format!("an alias of the built-in {} `{}`", position, op.name));
+        }
+    }
+    if let Some(unit) = name.strip_prefix("to-").filter(|unit| units::find_unit(unit).is_some()) {
+        conflicts.push(format!("the conversion to `{}`", unit));
+    }
+    conflicts
+}
+
+// A one-line summary for `:help`.
+pub fn describe(op: &Operator) -> String {
+    let aliases = if op.aliases.is_empty() { String::new() } else { format!(" ({})", op.aliases.join(", ")) };
+    let position = match op.position {
+        Position::Postfix => "postfix",
+        Position::Prefix => "prefix",
+    };
+    format!("{}{}  [{}, {}]  {}", op.name, aliases, position, op.operands, op.description)
+}
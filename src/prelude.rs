@@ -0,0 +1,18 @@
+// What the engine's modules use from `alloc`, so the same code builds with and without `std`.
+// Without it the maps and sets are B-trees, which is why every key type is ordered; nothing
+// depends on the order a map iterates in.
+pub use alloc::boxed::Box;
+pub use alloc::format;
+pub use alloc::rc::Rc;
+pub use alloc::string::{String, ToString};
+pub use alloc::vec;
+pub use alloc::vec::Vec;
+
+#[cfg(not(feature = "std"))]
+pub use alloc::collections::{BTreeMap as HashMap, BTreeSet as HashSet};
+#[cfg(feature = "std")]
+pub use std::collections::{HashMap, HashSet};
+
+// The float methods `std` provides and `core` doesn't, like `floor` and `sqrt`.
+#[cfg(not(feature = "std"))]
+pub use crate::math::Float;
@@ -0,0 +1,43 @@
+// `--profile` runs a program one statement at a time, timing each with the wall clock, and
+// lists the slowest statements once the answer is printed.
+use std::time::{Duration, Instant};
+
+use crate::{evaluate_statements, watch, EngineError, Evaluator, Source, Value};
+
+// How many statements the report lists.
+pub const SLOWEST: usize = 10;
+
+pub struct Timing {
+    pub line: usize, // The first line of the statement
+    pub elapsed: Duration,
+}
+
+// Evaluates a program the way `Evaluator::evaluate` would, timing every statement.
+pub fn evaluate(engine: &mut Evaluator, statements: Vec<watch::Parsed>) -> Result<(Value, Vec<Timing>), EngineError> {
+    let mut timings = vec![];
+    let answer = evaluate_statements(engine, statements, |engine, statement| {
+        let start = Instant::now();
+        let answer = engine.evaluate_commands(&statement.commands)?;
+        timings.push(Timing { line: statement.first, elapsed: start.elapsed() });
+        Ok(answer)
+    })?;
+    Ok((answer, timings))
+}
+
+// The slowest statements, slowest first, each with its place in the source and its first line.
+pub fn report(timings: &[Timing], source: &Source) -> Vec<String> {
+    let mut slowest: Vec<_> = timings.iter().collect();
+    slowest.sort_by_key(|timing| std::cmp::Reverse(timing.elapsed));
+    slowest
+        .into_iter()
+        .take(SLOWEST)
+        .map(|timing| {
+            let place = match source.origin(timing.line) {
+                Some(origin) => format!("{}:{}", origin.file, origin.line),
+                None => format!("line {}", timing.line),
+            };
+            let text = source.text.lines().nth(timing.line - 1).unwrap_or("").trim();
+            format!("{:>10.3} ms  {}  {}", timing.elapsed.as_secs_f64() * 1000.0, place, text)
+        })
+        .collect()
+}
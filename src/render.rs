@@ -0,0 +1,65 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::{describe, parse_program, parse_program_from, EngineError, Evaluator, Source, Value};
+
+const MARKER: &str = "# = ";
+
+// Drops an annotation left by an earlier render, so rendering twice changes nothing.
+fn strip_annotation(line: &str) -> &str {
+    match line.find(MARKER) {
+        Some(at) => line[..at].trim_end(),
+        None => line,
+    }
+}
+
+// Evaluates `text` one statement at a time and returns it with each statement's answer
+// appended to its last line as an aligned `# = answer` comment. `dir` is where
+// `include` paths are resolved from.
+pub fn render(text: &str, dir: &Path, engine: &mut Evaluator) -> Result<String, EngineError> {
+    let lines: Vec<_> = text.lines().map(strip_annotation).collect();
+    let mut answers: Vec<Option<String>> = vec![None; lines.len()];
+    let mut functions = HashSet::new();
+    let mut start = 0;
+
+    for (index, line) in lines.iter().enumerate() {
+        let commands = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["include", path] => {
+                if engine.sandbox {
+                    return Err(EngineError::NotPermitted("include".into()).at_line(index + 1));
+                }
+                let mut source = Source::default();
+                source.load(&dir.join(path.trim_matches('"')).to_string_lossy())?;
+                parse_program(&source.text, &mut functions).map_err(|err| err.unlocated().in_block(start))
+            }
+            _ => {
+                let mut defined = functions.clone();
+                match parse_program_from(&lines[start..=index].join("\n"), start, &mut defined) {
+                    Err(err) if matches!(err.root(), EngineError::UnterminatedBlock) && index + 1 < lines.len() => continue,
+                    result => {
+                        functions = defined;
+                        result
+                    }
+                }
+            }
+        }?;
+
+        if !commands.is_empty() {
+            engine.evaluate_commands(&commands).map_err(|err| err.at_line(index + 1))?;
+            if engine.answer != Value::Nothing {
+                answers[index] = Some(engine.format(&engine.answer).unwrap_or_else(|_| describe(&engine.answer)));
+            }
+        }
+        start = index + 1;
+    }
+
+    let width = lines.iter().zip(&answers).filter(|(_, answer)| answer.is_some()).map(|(line, _)| line.len()).max().unwrap_or(0);
+    let mut output = String::new();
+    for (line, answer) in lines.iter().zip(answers) {
+        match answer {
+            Some(answer) => output.push_str(&format!("{:width$}  {}{}\n", line, MARKER, answer, width = width)),
+            None => output.push_str(&format!("{}\n", line)),
+        }
+    }
+    Ok(output)
+}
@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+
+use crate::complete;
+use crate::journal::{self, Journal};
+use crate::operators::{self, OPERATORS};
+use crate::{diagnose, emit, interrupt, error_diagnostic, evaluator, parse_program_from, warning_diagnostic, EngineError, Evaluator, Options, Source, Value};
+
+// The reply to a `:` line, or None when the session should end.
+pub fn meta_command(line: &str, engine: &Evaluator) -> Option<String> {
+    if let Some(text) = line.trim_start().strip_prefix(":complete ") {
+        let completions = complete::complete(text, text.len(), engine);
+        return Some(completions.iter().map(|completion| format!("{}  ({})", completion.text, completion.kind.name())).collect::<Vec<_>>().join("\n"));
+    }
+    let words: Vec<_> = line.split_whitespace().collect();
+    match words[..] {
+        [":quit" | ":q"] => None,
+        [":help"] => Some(OPERATORS.iter().map(operators::describe).collect::<Vec<_>>().join("\n")),
+        [":help", name] => Some(match operators::find(name) {
+            Some(op) => operators::describe(op),
+            None => format!("no operator named '{}'", name),
+        }),
+        _ => Some(format!("unknown command '{}', try :help", line.trim())),
+    }
+}
+
+// Reads lines from stdin, evaluating each one against the same evaluator. Lines opening a
+// block are held until its `end` arrives.
+pub fn run(options: &Options) -> Result<(), EngineError> {
+    let mut engine = evaluator(options)?;
+    engine.interruptible = true;
+    interrupt::install();
+    let mut functions = HashSet::new();
+    let mut pending = String::new();
+    let mut entered = 0; // Lines before `pending`, so `const` declarations have a line to name
+    let mut lines = io::stdin().lock().lines();
+
+    // Recovery needs someone to ask, so sessions without prompts keep no journal.
+    let dir = journal::default_dir().filter(|_| options.journal && !options.sandbox && options.streams.prompts() && !options.quiet);
+    let leftover = dir.as_deref().and_then(journal::leftover);
+    let mut restored = None;
+    if let Some(text) = leftover.as_ref().map(|leftover| &leftover.text) {
+        eprint!("an earlier session ended without :quit; restore its {} lines? [y/N] ", text.lines().count());
+        io::stderr().flush().ok();
+        let reply = lines.next().and_then(Result::ok).unwrap_or_default();
+        if matches!(reply.trim(), "y" | "Y" | "yes") {
+            match parse_program_from(text, 0, &mut functions).and_then(|commands| engine.evaluate_commands(&commands)) {
+                Ok(_) => {
+                    engine.warnings.clear();
+                    engine.tables.clear();
+                    entered = text.lines().count();
+                    restored = Some(text.clone());
+                    eprintln!("restored {} lines", entered);
+                }
+                Err(err) => diagnose(options, &error_diagnostic(&err, None, &Source::default())),
+            }
+        }
+    }
+    // The restored lines carry on in this session's journal before the old one goes.
+    let mut journal = dir.and_then(|dir| Journal::open(&journal::path_in(&dir)));
+    if let (Some(journal), Some(text)) = (&mut journal, &restored) {
+        journal.record(text);
+    }
+    if let Some(leftover) = leftover {
+        leftover.discard();
+    }
+
+    loop {
+        // The prompt goes to stderr with the other diagnostics, leaving stdout to answers.
+        if !options.quiet && options.streams.prompts() {
+            eprint!("{}", if pending.is_empty() { "> " } else { ".. " });
+            io::stderr().flush().ok();
+        }
+        interrupt::set_reading(true);
+        let line = lines.next();
+        interrupt::set_reading(false);
+        let Some(line) = line else {
+            break;
+        };
+        let line = line.map_err(|_| EngineError::CannotRead("stdin".into()))?;
+        // Ctrl-C while typing throws away the half-entered block along with the line.
+        if interrupt::take() {
+            entered += pending.lines().count();
+            pending.clear();
+        }
+        if pending.is_empty() && line.trim_start().starts_with(':') {
+            entered += 1;
+            match meta_command(&line, &engine) {
+                Some(reply) => emit(options, &reply),
+                None => break,
+            }
+            continue;
+        }
+
+        pending.push_str(&line);
+        pending.push('\n');
+        let mut defined = functions.clone();
+        let parsed = parse_program_from(&pending, entered, &mut defined);
+        if parsed.as_ref().is_err_and(|err| matches!(err.root(), EngineError::UnterminatedBlock)) {
+            continue;
+        }
+        entered += pending.lines().count();
+        let commands = match parsed {
+            Err(err) => {
+                pending.clear();
+                diagnose(options, &error_diagnostic(&err, None, &Source::default()));
+                continue;
+            }
+            Ok(commands) => commands,
+        };
+        let entry = std::mem::take(&mut pending);
+        functions = defined;
+
+        let result = engine.evaluate_commands(&commands);
+        if let (Some(journal), Ok(_)) = (&mut journal, &result) {
+            journal.record(&entry);
+        }
+        for table in engine.tables.drain(..) {
+            emit(options, &table.render(options.borders).join("\n"));
+        }
+        // With `--cells`, every cell a change reached is shown with its new value.
+        if let Some(stale) = engine.cells.as_mut().map(|cells| cells.take_stale()) {
+            for name in stale.into_iter().filter(|name| engine.deferred.contains_key(name)) {
+                match engine.binding(&name).expect("stale cells are deferred").and_then(|value| engine.format(&value)) {
+                    Ok(text) => emit(options, &format!("{} = {}", name, text)),
+                    Err(err) => diagnose(options, &error_diagnostic(&err, None, &Source::default())),
+                }
+            }
+        }
+        match result {
+            Ok(Value::Nothing) => {}
+            Ok(answer) => match engine.format(&answer) {
+                Ok(text) => emit(options, &text),
+                Err(err) => diagnose(options, &error_diagnostic(&err, None, &Source::default())),
+            },
+            Err(err) => diagnose(options, &error_diagnostic(&err, None, &Source::default())),
+        }
+        for warning in engine.warnings.drain(..) {
+            diagnose(options, &warning_diagnostic(&warning, None));
+        }
+        if engine.halted {
+            break;
+        }
+    }
+    if let Some(journal) = journal {
+        journal.finish();
+    }
+    Ok(())
+}
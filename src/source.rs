@@ -0,0 +1,100 @@
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::EngineError;
+
+// The file name that reads the program from standard input, as in `cat calc.qqc | qqc -`.
+pub const STDIN: &str = "-";
+
+// Where a line of the expanded program came from, and through which `include`s.
+#[derive(Debug, PartialEq)]
+pub struct Origin {
+    pub file: String,
+    pub line: usize,
+    pub included_from: Option<Rc<Origin>>,
+}
+
+impl Origin {
+    // The chain of `include` lines leading here, innermost first.
+    pub fn include_chain(&self) -> Vec<String> {
+        let mut chain = vec![];
+        let mut at = self.included_from.as_ref();
+        while let Some(origin) = at {
+            chain.push(format!("{}:{}", origin.file, origin.line));
+            at = origin.included_from.as_ref();
+        }
+        chain
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)?;
+        for include in self.include_chain() {
+            write!(f, ", included from {}", include)?;
+        }
+        Ok(())
+    }
+}
+
+// A program with its `include` lines spliced in, and the origin of every resulting line.
+#[derive(Default)]
+pub struct Source {
+    pub text: String,
+    origins: Vec<Rc<Origin>>,
+    pub sandbox: bool, // Refuse `include`, for `--sandbox`
+}
+
+impl Source {
+    // Appends a file, or standard input for `-`. On failure the lines read so far stay, so the
+    // error can be placed.
+    pub fn load(&mut self, path: &str) -> Result<(), EngineError> {
+        self.splice(path, None)
+    }
+
+    // The origin of a line of the expanded text, numbered from 1.
+    pub fn origin(&self, line: usize) -> Option<&Origin> {
+        self.origins.get(line.checked_sub(1)?).map(Rc::as_ref)
+    }
+
+    fn splice(&mut self, path: &str, included_from: Option<Rc<Origin>>) -> Result<(), EngineError> {
+        let mut parent = included_from.as_ref();
+        while let Some(origin) = parent {
+            if origin.file == path {
+                return Err(EngineError::IncludeCycle(path.into()));
+            }
+            parent = origin.included_from.as_ref();
+        }
+        let text = match path {
+            STDIN => std::io::read_to_string(std::io::stdin()).map_err(|_| EngineError::CannotRead("stdin".into()))?,
+            path => std::fs::read_to_string(path).map_err(|_| EngineError::CannotRead(path.into()))?,
+        };
+
+        for (index, line) in text.lines().enumerate() {
+            let origin = Rc::new(Origin { file: path.into(), line: index + 1, included_from: included_from.clone() });
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["include", name] => {
+                    // Kept as a blank line so errors raised by the include itself have a place.
+                    self.push("", origin.clone());
+                    if self.sandbox {
+                        return Err(EngineError::NotPermitted("include".into()).at_line(self.origins.len()));
+                    }
+                    let name = name.trim_matches('"');
+                    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+                    let included = base.join(name).to_string_lossy().into_owned();
+                    let at = self.origins.len();
+                    self.splice(&included, Some(origin)).map_err(|err| err.at_line(at))?;
+                }
+                _ => self.push(line, origin),
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, line: &str, origin: Rc<Origin>) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.origins.push(origin);
+    }
+}
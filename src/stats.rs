@@ -0,0 +1,95 @@
+// `--stats` reports what a run cost. Heap use is counted by a thin wrapper around the system
+// allocator, so the figures are the same on every platform and need no OS support. Counting
+// only starts with `start`, so without `--stats` the wrapper costs one relaxed load per call.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::Evaluator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static COUNTING: AtomicBool = AtomicBool::new(false);
+
+pub struct Counting;
+
+pub fn start() {
+    COUNTING.store(true, Ordering::Relaxed);
+}
+
+fn allocated(size: usize) {
+    if !COUNTING.load(Ordering::Relaxed) {
+        return;
+    }
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    let now = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+// Memory allocated before counting started may be freed after, so the count stops at zero.
+fn freed(size: usize) {
+    if COUNTING.load(Ordering::Relaxed) {
+        CURRENT.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| Some(current.saturating_sub(size))).ok();
+    }
+}
+
+// SAFETY: every call goes straight to the system allocator; only the counters are added.
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        freed(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            freed(layout.size());
+            allocated(new_size);
+        }
+        new
+    }
+}
+
+// The most heap in use at once since counting started, in bytes.
+pub fn peak_heap() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+pub fn allocations() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+fn bytes(n: usize) -> String {
+    match n {
+        n if n < 1 << 10 => format!("{} B", n),
+        n if n < 1 << 20 => format!("{:.1} KiB", n as f64 / (1u64 << 10) as f64),
+        n if n < 1 << 30 => format!("{:.1} MiB", n as f64 / (1u64 << 20) as f64),
+        n => format!("{:.1} GiB", n as f64 / (1u64 << 30) as f64),
+    }
+}
+
+pub fn report(engine: &Evaluator) -> Vec<String> {
+    vec![
+        format!("peak heap    {}", bytes(peak_heap())),
+        format!("allocations  {}", allocations()),
+        format!("commands     {}", engine.executed),
+        format!("variables    {}", engine.vars.len()),
+        format!("history      {}", engine.answers.len()),
+    ]
+}
@@ -0,0 +1,309 @@
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{format_number, ArithOp, EngineError};
+
+// A formula over unknown variables, built up when `--symbolic` lets missing variables through.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Binary(ArithOp, Box<Expr>, Box<Expr>),
+    Func(String, Box<Expr>), // Elementary functions such as `ln(x)`
+}
+
+impl Expr {
+    // Builds `lhs op rhs`, folding constants and keeping numeric coefficients in front.
+    pub fn binary(op: ArithOp, lhs: Expr, rhs: Expr) -> Expr {
+        match (op, lhs, rhs) {
+            (op, Expr::Num(a), Expr::Num(b)) => Expr::Num(op.apply(a, b)),
+            (ArithOp::Multiply, lhs, Expr::Num(b)) => Expr::binary(ArithOp::Multiply, Expr::Num(b), lhs),
+            (ArithOp::Add, lhs, Expr::Num(b)) if b < 0.0 => Expr::binary(ArithOp::Subtract, lhs, Expr::Num(-b)),
+            (op, lhs, rhs) => Expr::Binary(op, Box::new(lhs), Box::new(rhs)),
+        }
+    }
+
+    fn func(name: &str, arg: Expr) -> Expr {
+        Expr::Func(name.into(), Box::new(arg))
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Num(n) if *n < 0.0 => 2,
+            Expr::Num(_) | Expr::Var(_) | Expr::Func(_, _) => 4,
+            Expr::Binary(op, _, _) => op_precedence(*op),
+        }
+    }
+
+    fn contains(&self, var: &str) -> bool {
+        match self {
+            Expr::Num(_) => false,
+            Expr::Var(name) => name == var,
+            Expr::Binary(_, lhs, rhs) => lhs.contains(var) || rhs.contains(var),
+            Expr::Func(_, arg) => arg.contains(var),
+        }
+    }
+
+    // Collects like terms when the expression is a polynomial, otherwise
+    // simplifies each part and drops identities like `x*1` and `x + 0`.
+    pub fn simplify(&self) -> Expr {
+        if let Some(poly) = Poly::from_expr(self) {
+            return poly.to_expr();
+        }
+        match self {
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.simplify(), rhs.simplify());
+                match (op, lhs, rhs) {
+                    (ArithOp::Add, Expr::Num(z), e) | (ArithOp::Add | ArithOp::Subtract, e, Expr::Num(z)) if z == 0.0 => e,
+                    (ArithOp::Multiply, Expr::Num(z), _) | (ArithOp::Multiply, _, Expr::Num(z)) if z == 0.0 => Expr::Num(0.0),
+                    (ArithOp::Multiply, Expr::Num(one), e) | (ArithOp::Multiply | ArithOp::Divide | ArithOp::Power, e, Expr::Num(one)) if one == 1.0 => e,
+                    (ArithOp::Power, _, Expr::Num(0.0)) => Expr::Num(1.0),
+                    (op, lhs, rhs) => Expr::binary(*op, lhs, rhs),
+                }
+            }
+            Expr::Func(name, arg) => Expr::func(name, arg.simplify()),
+            e => e.clone(),
+        }
+    }
+
+    // Differentiates with respect to `var`, leaving the result simplified.
+    pub fn derivative(&self, var: &str) -> Result<Expr, EngineError> {
+        Ok(self.derive(var)?.simplify())
+    }
+
+    fn derive(&self, var: &str) -> Result<Expr, EngineError> {
+        let d = match self {
+            Expr::Num(_) => Expr::Num(0.0),
+            Expr::Var(name) => Expr::Num(if name == var { 1.0 } else { 0.0 }),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.as_ref().clone(), b.as_ref().clone());
+                match op {
+                    ArithOp::Add | ArithOp::Subtract => Expr::binary(*op, a.derive(var)?, b.derive(var)?),
+                    ArithOp::Multiply => Expr::binary(
+                        ArithOp::Add,
+                        Expr::binary(ArithOp::Multiply, a.derive(var)?, b.clone()),
+                        Expr::binary(ArithOp::Multiply, a, b.derive(var)?),
+                    ),
+                    ArithOp::Divide => Expr::binary(
+                        ArithOp::Divide,
+                        Expr::binary(
+                            ArithOp::Subtract,
+                            Expr::binary(ArithOp::Multiply, a.derive(var)?, b.clone()),
+                            Expr::binary(ArithOp::Multiply, a, b.derive(var)?),
+                        ),
+                        Expr::binary(ArithOp::Power, b, Expr::Num(2.0)),
+                    ),
+                    ArithOp::Power if !b.contains(var) => Expr::binary(
+                        ArithOp::Multiply,
+                        Expr::binary(
+                            ArithOp::Multiply,
+                            b.clone(),
+                            Expr::binary(ArithOp::Power, a.clone(), Expr::binary(ArithOp::Subtract, b, Expr::Num(1.0))),
+                        ),
+                        a.derive(var)?,
+                    ),
+                    // d(a^b) = a^b * (b' ln(a) + b a'/a)
+                    ArithOp::Power => Expr::binary(
+                        ArithOp::Multiply,
+                        self.clone(),
+                        Expr::binary(
+                            ArithOp::Add,
+                            Expr::binary(ArithOp::Multiply, b.derive(var)?, Expr::func("ln", a.clone())),
+                            Expr::binary(ArithOp::Divide, Expr::binary(ArithOp::Multiply, b, a.derive(var)?), a),
+                        ),
+                    ),
+                    ArithOp::Modulo => return Err(EngineError::UnsupportedExpression),
+                }
+            }
+            Expr::Func(name, arg) if name == "ln" => {
+                Expr::binary(ArithOp::Divide, arg.derive(var)?, arg.as_ref().clone())
+            }
+            Expr::Func(_, _) => return Err(EngineError::UnsupportedExpression),
+        };
+        Ok(d)
+    }
+}
+
+// Solves `lhs = rhs` for the single unknown they contain, when the equation is linear in it.
+pub fn solve_linear(lhs: &Expr, rhs: &Expr) -> Result<(String, f64), EngineError> {
+    let difference = Expr::Binary(ArithOp::Subtract, Box::new(lhs.clone()), Box::new(rhs.clone()));
+    let poly = Poly::from_expr(&difference).ok_or(EngineError::NotSolvable)?;
+
+    let mut unknown = None;
+    let (mut slope, mut intercept) = (0.0, 0.0);
+    for (mono, c) in &poly.0 {
+        match &mono[..] {
+            [] => intercept = *c,
+            [(name, 1)] if unknown.is_none() || unknown == Some(name) => {
+                unknown = Some(name);
+                slope = *c;
+            }
+            _ => return Err(EngineError::NotSolvable),
+        }
+    }
+
+    match unknown {
+        Some(name) if slope != 0.0 => Ok((name.clone(), -intercept / slope)),
+        _ => Err(EngineError::NotSolvable),
+    }
+}
+
+// A monomial such as `x^2*y`, as variable names with their exponents in name order.
+type Monomial = Vec<(String, u32)>;
+
+// A polynomial in normal form: each monomial mapped to its coefficient.
+#[derive(Clone, Debug)]
+struct Poly(BTreeMap<Monomial, f64>);
+
+impl Poly {
+    fn constant(c: f64) -> Poly {
+        let mut terms = BTreeMap::new();
+        if c != 0.0 {
+            terms.insert(vec![], c);
+        }
+        Poly(terms)
+    }
+
+    fn as_constant(&self) -> Option<f64> {
+        match self.0.len() {
+            0 => Some(0.0),
+            1 => self.0.get(&vec![]).copied(),
+            _ => None,
+        }
+    }
+
+    fn add(mut self, other: Poly, sign: f64) -> Poly {
+        for (mono, c) in other.0 {
+            let sum = self.0.get(&mono).copied().unwrap_or(0.0) + sign * c;
+            if sum == 0.0 {
+                self.0.remove(&mono);
+            } else {
+                self.0.insert(mono, sum);
+            }
+        }
+        self
+    }
+
+    fn mul(&self, other: &Poly) -> Poly {
+        let mut product = Poly::constant(0.0);
+        for (ma, ca) in &self.0 {
+            for (mb, cb) in &other.0 {
+                let mut mono: BTreeMap<String, u32> = ma.iter().cloned().collect();
+                for (name, exp) in mb {
+                    *mono.entry(name.clone()).or_insert(0) += exp;
+                }
+                let mut term = BTreeMap::new();
+                term.insert(mono.into_iter().collect(), ca * cb);
+                product = product.add(Poly(term), 1.0);
+            }
+        }
+        product
+    }
+
+    fn from_expr(expr: &Expr) -> Option<Poly> {
+        match expr {
+            Expr::Num(n) => Some(Poly::constant(*n)),
+            Expr::Var(name) => {
+                let mut terms = BTreeMap::new();
+                terms.insert(vec![(name.clone(), 1)], 1.0);
+                Some(Poly(terms))
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (Poly::from_expr(lhs)?, Poly::from_expr(rhs)?);
+                match op {
+                    ArithOp::Add => Some(lhs.add(rhs, 1.0)),
+                    ArithOp::Subtract => Some(lhs.add(rhs, -1.0)),
+                    ArithOp::Multiply => Some(lhs.mul(&rhs)),
+                    ArithOp::Divide => match rhs.as_constant() {
+                        Some(c) if c != 0.0 => Some(lhs.mul(&Poly::constant(1.0 / c))),
+                        _ => None,
+                    },
+                    ArithOp::Power => match rhs.as_constant() {
+                        Some(n) if n.fract() == 0.0 && (0.0..=64.0).contains(&n) => {
+                            Some((0..n as u32).fold(Poly::constant(1.0), |acc, _| acc.mul(&lhs)))
+                        }
+                        _ => None,
+                    },
+                    ArithOp::Modulo => None,
+                }
+            }
+            Expr::Func(_, _) => None,
+        }
+    }
+
+    // Writes the terms out highest degree first, e.g. `3*x^2 + 2*x - 5`.
+    fn to_expr(&self) -> Expr {
+        let mut terms: Vec<_> = self.0.iter().collect();
+        terms.sort_by_key(|(mono, _)| core::cmp::Reverse(mono.iter().map(|(_, exp)| exp).sum::<u32>()));
+
+        let mut result: Option<Expr> = None;
+        for (mono, c) in terms {
+            let factors = mono.iter().map(|(name, exp)| match exp {
+                1 => Expr::Var(name.clone()),
+                _ => Expr::Binary(ArithOp::Power, Box::new(Expr::Var(name.clone())), Box::new(Expr::Num(*exp as f64))),
+            });
+            let monomial = factors.reduce(|acc, f| Expr::Binary(ArithOp::Multiply, Box::new(acc), Box::new(f)));
+            let term = |c: f64| match &monomial {
+                None => Expr::Num(c),
+                Some(m) if c == 1.0 => m.clone(),
+                Some(m) => Expr::Binary(ArithOp::Multiply, Box::new(Expr::Num(c)), Box::new(m.clone())),
+            };
+            result = Some(match result {
+                None => term(*c),
+                Some(acc) if *c < 0.0 => Expr::Binary(ArithOp::Subtract, Box::new(acc), Box::new(term(-c))),
+                Some(acc) => Expr::Binary(ArithOp::Add, Box::new(acc), Box::new(term(*c))),
+            });
+        }
+        result.unwrap_or(Expr::Num(0.0))
+    }
+}
+
+fn op_precedence(op: ArithOp) -> u8 {
+    match op {
+        ArithOp::Add | ArithOp::Subtract => 1,
+        ArithOp::Multiply | ArithOp::Divide | ArithOp::Modulo => 2,
+        ArithOp::Power => 3,
+    }
+}
+
+fn op_symbol(op: ArithOp) -> &'static str {
+    match op {
+        ArithOp::Add => " + ",
+        ArithOp::Subtract => " - ",
+        ArithOp::Multiply => "*",
+        ArithOp::Divide => "/",
+        ArithOp::Power => "^",
+        ArithOp::Modulo => " % ",
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter, expr: &Expr, parens: bool) -> fmt::Result {
+    if parens {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{}", format_number(*n)),
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Binary(op, lhs, rhs) => {
+                let prec = op_precedence(*op);
+                // `^` groups to the right, everything else to the left.
+                let (lhs_parens, rhs_parens) = match op {
+                    ArithOp::Power => (lhs.precedence() <= prec, rhs.precedence() < prec),
+                    ArithOp::Add | ArithOp::Multiply => (lhs.precedence() < prec, rhs.precedence() < prec),
+                    _ => (lhs.precedence() < prec, rhs.precedence() <= prec),
+                };
+                write_operand(f, lhs, lhs_parens)?;
+                write!(f, "{}", op_symbol(*op))?;
+                write_operand(f, rhs, rhs_parens)
+            }
+            Expr::Func(name, arg) => write!(f, "{}({})", name, arg),
+        }
+    }
+}
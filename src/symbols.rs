@@ -0,0 +1,243 @@
+// Variable names are interned to small ids when a program is parsed, so reading a variable
+// while evaluating indexes a table instead of hashing and comparing its name. A symbol carries
+// its name as well, so showing one looks nothing up. The table belongs to the thread, like the
+// evaluators reading it, and forgets names nothing holds any more, so a long REPL, watch or
+// kernel session only keeps the names still in use.
+#[cfg(feature = "std")]
+use core::cell::RefCell;
+#[cfg(not(feature = "std"))]
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::prelude::*;
+use crate::Value;
+
+// Without `std` there are no thread locals, and one table serves every thread, so the names it
+// shares with symbols are counted atomically.
+#[cfg(feature = "std")]
+type Name = Rc<str>;
+#[cfg(not(feature = "std"))]
+type Name = alloc::sync::Arc<str>;
+
+#[derive(Clone)]
+pub struct Symbol {
+    id: u32,
+    name: Name,
+}
+
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<Name, u32>,
+    free: Vec<u32>, // The ids of forgotten names, given out again first
+    next: u32,
+    sweep_at: usize, // How many names the table holds before it next looks for unused ones
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::default();
+}
+
+#[cfg(feature = "std")]
+fn with_interner<T>(f: impl FnOnce(&mut Interner) -> T) -> T {
+    INTERNER.with_borrow_mut(f)
+}
+
+#[cfg(not(feature = "std"))]
+struct Shared {
+    locked: AtomicBool,
+    interner: UnsafeCell<Interner>,
+}
+
+// SAFETY: the interner is only reached through `with_interner`, which holds the lock.
+#[cfg(not(feature = "std"))]
+unsafe impl Sync for Shared {}
+
+#[cfg(not(feature = "std"))]
+static INTERNER: Shared = Shared {
+    locked: AtomicBool::new(false),
+    interner: UnsafeCell::new(Interner { ids: HashMap::new(), free: Vec::new(), next: 0, sweep_at: 0 }),
+};
+
+// The table behind a spin lock, held only while a name is looked up or added.
+#[cfg(not(feature = "std"))]
+fn with_interner<T>(f: impl FnOnce(&mut Interner) -> T) -> T {
+    struct Unlock;
+    impl Drop for Unlock {
+        fn drop(&mut self) {
+            INTERNER.locked.store(false, Ordering::Release);
+        }
+    }
+    while INTERNER.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        core::hint::spin_loop();
+    }
+    let _unlock = Unlock;
+    // SAFETY: holding the lock makes this the only reference to the interner.
+    f(unsafe { &mut *INTERNER.interner.get() })
+}
+
+impl Interner {
+    // Forgets the names only the table still holds. Sweeping once the table has doubled keeps
+    // the cost spread thin over the names interned in between.
+    fn sweep(&mut self) {
+        let free = &mut self.free;
+        self.ids.retain(|name, id| {
+            let used = Name::strong_count(name) > 1;
+            if !used {
+                free.push(*id);
+            }
+            used
+        });
+        self.sweep_at = (self.ids.len() * 2).max(64);
+    }
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        with_interner(|interner| {
+            if let Some((name, id)) = interner.ids.get_key_value(name) {
+                return Symbol { id: *id, name: name.clone() };
+            }
+            if interner.ids.len() >= interner.sweep_at {
+                interner.sweep();
+            }
+            let id = interner.free.pop().unwrap_or_else(|| {
+                interner.next += 1;
+                interner.next - 1
+            });
+            let name: Name = name.into();
+            interner.ids.insert(name.clone(), id);
+            Symbol { id, name }
+        })
+    }
+
+    // The symbol of a name, if a program still uses it.
+    pub fn find(name: &str) -> Option<Symbol> {
+        with_interner(|interner| interner.ids.get_key_value(name).map(|(name, id)| Symbol { id: *id, name: name.clone() }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.name()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// The values of variables, indexed by symbol. Lookups by name intern nothing, so asking
+// about a name no program used is cheap and leaves the interner alone. Each bound value keeps
+// its symbol, which keeps the name from being forgotten while it is bound.
+#[derive(Clone, Default)]
+pub struct Vars {
+    values: Vec<Option<(Symbol, Value)>>,
+    len: usize,
+}
+
+impl Vars {
+    pub fn get_symbol(&self, symbol: &Symbol) -> Option<&Value> {
+        self.values.get(symbol.id as usize)?.as_ref().map(|(_, value)| value)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.get_symbol(&Symbol::find(name)?)
+    }
+
+    pub fn insert(&mut self, name: impl AsRef<str>, value: Value) -> Option<Value> {
+        self.insert_symbol(Symbol::intern(name.as_ref()), value)
+    }
+
+    pub fn insert_symbol(&mut self, symbol: Symbol, value: Value) -> Option<Value> {
+        let index = symbol.id as usize;
+        if self.values.len() <= index {
+            self.values.resize(index + 1, None);
+        }
+        let old = self.values[index].replace((symbol, value)).map(|(_, value)| value);
+        self.len += old.is_none() as usize;
+        old
+    }
+
+    #[cfg(test)]
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let old = self.values.get_mut(Symbol::find(name)?.id as usize)?.take().map(|(_, value)| value);
+        self.len -= old.is_some() as usize;
+        old
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.values.iter().filter_map(|slot| slot.as_ref().map(|(symbol, value)| (symbol.name(), value)))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.iter().map(|(name, _)| name)
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&str, &Value) -> bool) {
+        for slot in &mut self.values {
+            if slot.as_ref().is_some_and(|(symbol, value)| !keep(symbol.name(), value)) {
+                *slot = None;
+                self.len -= 1;
+            }
+        }
+    }
+}
+
+impl core::ops::Index<&str> for Vars {
+    type Output = Value;
+
+    fn index(&self, name: &str) -> &Value {
+        self.get(name).expect("variable is bound")
+    }
+}
@@ -0,0 +1,57 @@
+// Aligned text tables for output with several values side by side, like the columns of a
+// `scenario` block or the bindings shown by `--format table`. Label columns come first and
+// are aligned left; the value columns after them are aligned right, so the digits of numbers
+// line up.
+use crate::prelude::*;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub labels: usize, // How many leading columns hold labels
+}
+
+impl Table {
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        widths
+    }
+
+    fn cells(&self, widths: &[usize], cells: &[String]) -> Vec<String> {
+        widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| {
+                let cell = cells.get(i).map_or("", String::as_str);
+                if i < self.labels { format!("{:<width$}", cell) } else { format!("{:>width$}", cell) }
+            })
+            .collect()
+    }
+
+    // With `borders`, the table is drawn in Unicode box characters with a rule under the headers.
+    pub fn render(&self, borders: bool) -> Vec<String> {
+        let widths = self.widths();
+        let rows = core::iter::once(&self.headers).chain(&self.rows).map(|row| self.cells(&widths, row));
+        if !borders {
+            return rows.map(|cells| cells.join("  ").trim_end().to_string()).collect();
+        }
+        let rule = |left: &str, middle: &str, right: &str| {
+            let segments: Vec<_> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+            format!("{}{}{}", left, segments.join(middle), right)
+        };
+        let mut lines = vec![rule("┌", "┬", "┐")];
+        for (i, cells) in rows.enumerate() {
+            lines.push(format!("│ {} │", cells.join(" │ ")));
+            if i == 0 {
+                lines.push(rule("├", "┼", "┤"));
+            }
+        }
+        lines.push(rule("└", "┴", "┘"));
+        lines
+    }
+}
@@ -0,0 +1,111 @@
+// List aggregates spread over worker threads. Work is cut into fixed-size chunks whose
+// partial results are combined in chunk order, so the thread count changes only the speed,
+// never the answer. Without `std` there are no threads, and the chunks are worked through in
+// turn.
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::thread;
+
+use crate::fold;
+use crate::prelude::*;
+
+static THREADS: AtomicUsize = AtomicUsize::new(1);
+
+const CHUNK: usize = 1 << 16;
+
+// Set by `--threads`; 0 picks one thread per core.
+#[cfg(feature = "std")]
+pub fn set_threads(threads: usize) {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    THREADS.store(threads, Ordering::Relaxed);
+}
+
+// A number of threads to spread the work over. The free functions use the one set by `--threads`.
+#[derive(Clone, Copy)]
+pub struct Workers(pub usize);
+
+impl Workers {
+    fn configured() -> Workers {
+        Workers(THREADS.load(Ordering::Relaxed))
+    }
+
+    fn threads(self, chunks: usize) -> usize {
+        self.0.clamp(1, chunks.max(1))
+    }
+
+    // Applies `f` to every chunk, returning the results in chunk order.
+    #[cfg(feature = "std")]
+    fn map_chunks<T: Send>(self, values: &[f64], f: impl Fn(&[f64]) -> T + Sync) -> Vec<T> {
+        let chunks: Vec<_> = values.chunks(CHUNK).collect();
+        let threads = self.threads(chunks.len());
+        if threads == 1 {
+            return chunks.into_iter().map(f).collect();
+        }
+        let per_thread = chunks.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let f = &f;
+            let workers: Vec<_> = chunks
+                .chunks(per_thread)
+                .map(|group| scope.spawn(move || group.iter().map(|chunk| f(chunk)).collect::<Vec<_>>()))
+                .collect();
+            workers.into_iter().flat_map(|worker| worker.join().expect("aggregate worker panicked")).collect()
+        })
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn map_chunks<T>(self, values: &[f64], f: impl Fn(&[f64]) -> T) -> Vec<T> {
+        values.chunks(CHUNK).map(f).collect()
+    }
+
+    pub fn sum(self, values: &[f64]) -> f64 {
+        fold::sum(&self.map_chunks(values, fold::sum))
+    }
+
+    pub fn mean(self, values: &[f64]) -> f64 {
+        self.sum(values) / values.len() as f64
+    }
+
+    // The sample standard deviation, taking the mean first so large offsets don't cancel.
+    pub fn stddev(self, values: &[f64]) -> f64 {
+        let mean = self.mean(values);
+        let squares = self.map_chunks(values, |chunk| {
+            let deviations: Vec<_> = chunk.iter().map(|x| (x - mean) * (x - mean)).collect();
+            fold::sum(&deviations)
+        });
+        (fold::sum(&squares) / (values.len() - 1) as f64).sqrt()
+    }
+
+    // Chunks are sorted in parallel; the final sort then only merges the sorted runs.
+    pub fn sort(self, mut values: Vec<f64>) -> Vec<f64> {
+        #[cfg(feature = "std")]
+        if let threads @ 2.. = self.threads(values.len().div_ceil(CHUNK)) {
+            let per_thread = values.len().div_ceil(threads);
+            thread::scope(|scope| {
+                for part in values.chunks_mut(per_thread) {
+                    scope.spawn(move || part.sort_by(f64::total_cmp));
+                }
+            });
+        }
+        values.sort_by(f64::total_cmp);
+        values
+    }
+}
+
+pub fn sum(values: &[f64]) -> f64 {
+    Workers::configured().sum(values)
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    Workers::configured().mean(values)
+}
+
+pub fn stddev(values: &[f64]) -> f64 {
+    Workers::configured().stddev(values)
+}
+
+pub fn sort(values: Vec<f64>) -> Vec<f64> {
+    Workers::configured().sort(values)
+}
@@ -0,0 +1,57 @@
+// Classifies a single operand token without running anything, for front-ends that check input
+// as it is typed and want to say what is wrong with it rather than fail the whole line.
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{constants, operators, parse_float, EngineError, Value};
+
+#[derive(Debug, PartialEq)]
+pub enum Token {
+    Number(Value), // Any literal: plain, exact, fraction, quantity, time, uncertain
+    Constant(String, f64), // `const.c` and the rest of the constant table
+    Variable(String), // A name a variable could have, bound or not
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseValueError {
+    InvalidNumber(String), // Starts like a number but isn't one, like `1.2.3`
+    NumberOutOfRange(String), // Too large or too small for an f64
+    UnknownConstant(String), // Under `const.` but not in the table
+    InvalidToken(String), // Neither a number nor a name, like `+` or `a=b`
+}
+
+impl fmt::Display for ParseValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseValueError::InvalidNumber(token) => write!(f, "'{}' is not a valid number", token),
+            ParseValueError::NumberOutOfRange(token) => write!(f, "'{}' is out of the range of a number", token),
+            ParseValueError::UnknownConstant(token) => write!(f, "'{}' is not a known constant", token),
+            ParseValueError::InvalidToken(token) => write!(f, "'{}' is neither a number nor a variable name", token),
+        }
+    }
+}
+
+// Names are letters, digits, `_` and `.`, like `loan.rate` or `4ac`, or `$1` for arguments.
+pub fn is_identifier(token: &str) -> bool {
+    let name = token.strip_prefix('$').unwrap_or(token);
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+}
+
+// An identifier that doesn't also spell an operator, so it can only be read as a variable.
+fn is_name(token: &str) -> bool {
+    is_identifier(token) && operators::find(token).is_none()
+}
+
+pub fn parse_value(token: &str) -> Result<Token, ParseValueError> {
+    match parse_float(token) {
+        Ok(Value::Variable(name)) if name.starts_with(constants::PREFIX) => match constants::lookup(&name) {
+            Some(value) => Ok(Token::Constant(name.to_string(), value)),
+            None => Err(ParseValueError::UnknownConstant(token.into())),
+        },
+        Ok(Value::Variable(name)) if is_name(&name) => Ok(Token::Variable(name.to_string())),
+        Ok(Value::Variable(_)) => Err(ParseValueError::InvalidToken(token.into())),
+        Ok(value) => Ok(Token::Number(value)),
+        Err(EngineError::NumberOutOfRange(_)) => Err(ParseValueError::NumberOutOfRange(token.into())),
+        Err(_) => Err(ParseValueError::InvalidNumber(token.into())),
+    }
+}
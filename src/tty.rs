@@ -0,0 +1,43 @@
+// Whether each standard stream is a terminal, read once at startup. What qqc does when it isn't
+// told follows from how it was started: `qqc` alone opens the REPL, `... | qqc` reads a
+// program from stdin, prompts only appear where someone can answer them, and escape codes
+// only go to a terminal.
+use std::io::IsTerminal;
+
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Streams {
+    pub stdin: bool,
+    pub stdout: bool,
+    pub stderr: bool,
+}
+
+pub fn detect() -> Streams {
+    Streams { stdin: std::io::stdin().is_terminal(), stdout: std::io::stdout().is_terminal(), stderr: std::io::stderr().is_terminal() }
+}
+
+// How to run when no file was given.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Repl,
+    Filter, // Evaluate all of stdin as one program and print its answer
+}
+
+impl Streams {
+    pub fn mode(&self) -> Mode {
+        match self.stdin {
+            true => Mode::Repl,
+            false => Mode::Filter,
+        }
+    }
+
+    // Prompts are read from a terminal and written to stderr, so both have to be one.
+    pub fn prompts(&self) -> bool {
+        self.stdin && self.stderr
+    }
+
+    // Escape codes, like clearing the screen between `--watch` answers, would corrupt a file
+    // or a pipe, so they are only written when stdout is a terminal.
+    pub fn escapes(&self) -> bool {
+        self.stdout
+    }
+}
@@ -0,0 +1,272 @@
+use crate::prelude::*;
+use crate::{format_number, ArithOp};
+
+// The physical dimension of a quantity as powers of time and data.
+// Values are stored in base units: seconds and bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Dim {
+    pub time: i8,
+    pub data: i8,
+}
+
+pub const NONE: Dim = Dim { time: 0, data: 0 };
+pub const TIME: Dim = Dim { time: 1, data: 0 };
+pub const DATA: Dim = Dim { time: 0, data: 1 };
+pub const RATE: Dim = Dim { time: -1, data: 1 };
+pub const FREQUENCY: Dim = Dim { time: -1, data: 0 };
+
+// Unit suffixes accepted on literals and by `to-<unit>`, with their size in base units.
+const UNITS: &[(&str, f64, Dim)] = &[
+    ("B", 1.0, DATA),
+    ("kB", 1e3, DATA),
+    ("KB", 1e3, DATA),
+    ("MB", 1e6, DATA),
+    ("GB", 1e9, DATA),
+    ("TB", 1e12, DATA),
+    ("PB", 1e15, DATA),
+    ("KiB", 1024.0, DATA),
+    ("MiB", 1048576.0, DATA),
+    ("GiB", 1073741824.0, DATA),
+    ("TiB", 1099511627776.0, DATA),
+    ("PiB", 1125899906842624.0, DATA),
+    ("bps", 0.125, RATE),
+    ("kbps", 125.0, RATE),
+    ("Kbps", 125.0, RATE),
+    ("Mbps", 125e3, RATE),
+    ("Gbps", 125e6, RATE),
+    ("B/s", 1.0, RATE),
+    ("kB/s", 1e3, RATE),
+    ("KB/s", 1e3, RATE),
+    ("MB/s", 1e6, RATE),
+    ("GB/s", 1e9, RATE),
+    ("KiB/s", 1024.0, RATE),
+    ("MiB/s", 1048576.0, RATE),
+    ("GiB/s", 1073741824.0, RATE),
+    ("ns", 1e-9, TIME),
+    ("us", 1e-6, TIME),
+    ("µs", 1e-6, TIME),
+    ("ms", 1e-3, TIME),
+    ("s", 1.0, TIME),
+    ("min", 60.0, TIME),
+    ("h", 3600.0, TIME),
+    ("Hz", 1.0, FREQUENCY),
+    ("kHz", 1e3, FREQUENCY),
+    ("MHz", 1e6, FREQUENCY),
+    ("GHz", 1e9, FREQUENCY),
+];
+
+// Literals like `1.5GiB`, `300MB` or `250Mbps`, as an amount in base units.
+pub fn parse_quantity(input: &str) -> Option<(f64, Dim)> {
+    UNITS.iter().find_map(|(suffix, scale, dim)| {
+        let amount = input.strip_suffix(suffix)?.parse::<f64>().ok()?;
+        Some((amount * scale, *dim))
+    })
+}
+
+// The unit named by a `to-<unit>` directive, matched case-insensitively.
+pub fn find_unit(name: &str) -> Option<&'static str> {
+    if name.eq_ignore_ascii_case("bytes") {
+        return Some("B");
+    }
+    UNITS.iter().find(|(suffix, _, _)| suffix.eq_ignore_ascii_case(name)).map(|(suffix, _, _)| *suffix)
+}
+
+// Every unit suffix in the table.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    UNITS.iter().map(|(suffix, _, _)| *suffix)
+}
+
+fn lookup(unit: &str) -> Option<&'static (&'static str, f64, Dim)> {
+    UNITS.iter().find(|(suffix, _, _)| *suffix == unit)
+}
+
+// Rounds to 15 significant digits, hiding the noise from scaling by powers of ten.
+fn round_scaled(x: f64) -> f64 {
+    if x == 0.0 || !x.is_finite() {
+        return x;
+    }
+    let scale = 10f64.powi(14 - x.abs().log10().floor() as i32);
+    (x * scale).round() / scale
+}
+
+// Shows a quantity in a unit from the table, if the dimensions agree.
+pub fn format_in_unit(value: f64, dim: Dim, unit: &str) -> Option<String> {
+    let (suffix, scale, unit_dim) = lookup(unit)?;
+    (dim == *unit_dim).then(|| format!("{} {}", format_number(round_scaled(value / scale)), suffix))
+}
+
+// Picks the largest binary prefix that keeps at least one whole unit.
+fn format_bytes(bytes: f64) -> String {
+    let unit = ["PiB", "TiB", "GiB", "MiB", "KiB"]
+        .into_iter()
+        .find(|unit| bytes.abs() >= lookup(unit).unwrap().1)
+        .unwrap_or("B");
+    format_in_unit(bytes, DATA, unit).unwrap()
+}
+
+fn format_rate(bytes_per_second: f64) -> String {
+    let unit = ["GB/s", "MB/s", "KB/s"]
+        .into_iter()
+        .find(|unit| bytes_per_second.abs() >= lookup(unit).unwrap().1)
+        .unwrap_or("B/s");
+    format_in_unit(bytes_per_second, RATE, unit).unwrap()
+}
+
+// Multiplying and dividing combine dimensions, the other operators need matching ones.
+pub fn combine(op: ArithOp, (a, da): (f64, Dim), (b, db): (f64, Dim)) -> Option<(f64, Dim)> {
+    let dim = match op {
+        ArithOp::Add | ArithOp::Subtract | ArithOp::Modulo if da == db => da,
+        ArithOp::Multiply => Dim { time: da.time + db.time, data: da.data + db.data },
+        ArithOp::Divide => Dim { time: da.time - db.time, data: da.data - db.data },
+        _ => return None,
+    };
+    Some((op.apply(a, b), dim))
+}
+
+// Clock times like `14:35` or `1:02:30`, as seconds.
+pub fn parse_clock(input: &str) -> Option<f64> {
+    let parts: Vec<_> = input.split(':').collect();
+    let (hours, minutes, seconds) = match parts[..] {
+        [h, m] => (h, m, "0"),
+        [h, m, s] => (h, m, s),
+        _ => return None,
+    };
+    let hours = hours.parse::<u64>().ok()?;
+    let minutes = minutes.parse::<u64>().ok().filter(|m| *m < 60)?;
+    let seconds = seconds.parse::<f64>().ok().filter(|s| (0.0..60.0).contains(s))?;
+    Some(hours.checked_mul(3600)?.checked_add(minutes * 60)? as f64 + seconds)
+}
+
+// Shows seconds as `hh:mm`, adding `:ss` when there are leftover seconds.
+pub fn format_clock(seconds: f64) -> String {
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let total = seconds.abs();
+    let hours = (total / 3600.0).floor();
+    let minutes = ((total - hours * 3600.0) / 60.0).floor();
+    let secs = total - hours * 3600.0 - minutes * 60.0;
+    if secs == 0.0 {
+        format!("{}{:02}:{:02}", sign, hours, minutes)
+    } else {
+        format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, secs)
+    }
+}
+
+// Shows seconds as `1d 2h 3m 4.5s`, leaving out zero parts, or in
+// ms/us/ns below a second.
+pub fn format_duration(seconds: f64) -> String {
+    if seconds != 0.0 && seconds.abs() < 1.0 {
+        let unit = ["ms", "us"].into_iter().find(|unit| seconds.abs() >= lookup(unit).unwrap().1).unwrap_or("ns");
+        return format_in_unit(seconds, TIME, unit).unwrap();
+    }
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let mut rest = seconds.abs();
+    let mut parts = vec![];
+    for (size, suffix) in [(86400.0, "d"), (3600.0, "h"), (60.0, "m")] {
+        let count = (rest / size).floor();
+        if count > 0.0 {
+            parts.push(format!("{}{}", count, suffix));
+            rest -= count * size;
+        }
+    }
+    if rest > 0.0 || parts.is_empty() {
+        // Round away the noise left by peeling off the larger parts.
+        parts.push(format!("{}s", format_number((rest * 1e9).round() / 1e9)));
+    }
+    format!("{}{}", sign, parts.join(" "))
+}
+
+pub fn format_quantity(value: f64, dim: Dim) -> String {
+    match dim {
+        TIME => format_duration(value),
+        DATA => format_bytes(value),
+        RATE => format_rate(value),
+        FREQUENCY => {
+            let unit = ["GHz", "MHz", "kHz"].into_iter().find(|unit| value.abs() >= lookup(unit).unwrap().1).unwrap_or("Hz");
+            format_in_unit(value, FREQUENCY, unit).unwrap()
+        }
+        Dim { time, data } => {
+            let mut units = vec![];
+            for (exp, unit) in [(data, "B"), (time, "s")] {
+                match exp {
+                    0 => {}
+                    1 => units.push(unit.to_string()),
+                    _ => units.push(format!("{}^{}", unit, exp)),
+                }
+            }
+            format!("{} {}", format_number(value), units.join("*"))
+        }
+    }
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+// ISO 8601 timestamps like `2024-03-01`, `2024-03-01T12:00:00Z` or
+// `2024-03-01T12:00:00.250+05:30`, as seconds since the Unix epoch.
+pub fn parse_timestamp(input: &str) -> Option<f64> {
+    let (date, time) = match input.split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (input, None),
+    };
+    let date: Vec<_> = date.split('-').collect();
+    let (year, month, day) = match date[..] {
+        [y, m, d] if y.len() == 4 && m.len() == 2 && d.len() == 2 => {
+            (y.parse::<i64>().ok()?, m.parse::<i64>().ok()?, d.parse::<i64>().ok()?)
+        }
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let mut seconds = days_from_civil(year, month, day) as f64 * 86400.0;
+
+    if let Some(time) = time {
+        let (clock, offset) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+            (clock, 0.0)
+        } else if let Some(at) = time.rfind(['+', '-']) {
+            let sign = if time[at..].starts_with('-') { -1.0 } else { 1.0 };
+            (&time[..at], sign * parse_clock(&time[at + 1..])?)
+        } else {
+            (time, 0.0)
+        };
+        seconds += parse_clock(clock).filter(|s| *s < 86400.0)? - offset;
+    }
+    Some(seconds)
+}
+
+// Shows seconds since the Unix epoch as a UTC ISO 8601 timestamp.
+pub fn format_timestamp(seconds: f64) -> String {
+    let days = (seconds / 86400.0).floor();
+    let (year, month, day) = civil_from_days(days as i64);
+    let clock = format_clock(seconds - days * 86400.0);
+    let clock = if clock.matches(':').count() == 1 { format!("{}:00", clock) } else { clock };
+    format!("{:04}-{:02}-{:02}T{}Z", year, month, day, clock)
+}
@@ -0,0 +1,138 @@
+// `--watch` re-runs a worksheet whenever it changes. Each statement's evaluator state is
+// kept, so after an edit evaluation resumes from the snapshot before the first changed
+// statement instead of starting over.
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::Duration;
+
+use crate::{diagnose, emit, error_diagnostic, interrupt, notify_outcome, parse_program_from, warning_diagnostic, Command, EngineError, Evaluator, Options, Source};
+
+const POLL: Duration = Duration::from_millis(250);
+
+struct Statement {
+    text: String,
+    engine: Evaluator, // The state right after this statement ran
+}
+
+pub struct Worksheet {
+    base: Evaluator,
+    statements: Vec<Statement>,
+}
+
+// A statement as written, with the lines it starts and ends on.
+pub struct Parsed {
+    pub text: String,
+    pub first: usize,
+    pub last: usize,
+    pub commands: Vec<Command>,
+}
+
+// Splits a program into statements. `sigfigs` only changes how later lines parse, so it is
+// replayed before each of them.
+pub fn statements(text: &str) -> Result<Vec<Parsed>, EngineError> {
+    statements_from(text, 0, &mut HashSet::new())
+}
+
+// The same for text that follows earlier input, like a kernel cell or the next file of a
+// `--shared-state` run, that defined `functions`. Lines count on from `offset`, and the names
+// of the functions `text` defines are added to `functions`.
+pub fn statements_from(text: &str, offset: usize, functions: &mut HashSet<String>) -> Result<Vec<Parsed>, EngineError> {
+    let lines: Vec<_> = text.lines().collect();
+    let mut statements = vec![];
+    let (mut start, mut sigfigs) = (0, false);
+
+    for index in 0..lines.len() {
+        // The replayed line comes after an earlier `sigfigs`, so `start` is at least 1 then.
+        let (text, first) = match sigfigs {
+            true => (format!("sigfigs\n{}", lines[start..=index].join("\n")), offset + start - 1),
+            false => (lines[start..=index].join("\n"), offset + start),
+        };
+        let mut defined = functions.clone();
+        let commands = match parse_program_from(&text, first, &mut defined) {
+            Err(err) if matches!(err.root(), EngineError::UnterminatedBlock) && index + 1 < lines.len() => continue,
+            result => result?.commands,
+        };
+        *functions = defined;
+        sigfigs |= lines[start..=index].iter().any(|line| line.split_whitespace().take_while(|word| !word.starts_with('#')).eq(["sigfigs"]));
+        statements.push(Parsed { text, first: offset + start + 1, last: offset + index + 1, commands });
+        start = index + 1;
+    }
+    Ok(statements)
+}
+
+impl Worksheet {
+    // `base` is the evaluator every run starts from, with arguments and defines bound.
+    pub fn new(base: Evaluator) -> Worksheet {
+        Worksheet { base, statements: vec![] }
+    }
+
+    // The evaluator after the last statement that ran.
+    pub fn engine(&self) -> &Evaluator {
+        self.statements.last().map_or(&self.base, |statement| &statement.engine)
+    }
+
+    // Brings the worksheet up to date with `text`, returning how many statements were run.
+    pub fn update(&mut self, text: &str) -> Result<usize, EngineError> {
+        let statements = statements(text)?;
+        let unchanged = self.statements.iter().zip(&statements).take_while(|(old, new)| old.text == new.text).count();
+        self.statements.truncate(unchanged);
+
+        let mut evaluated = 0;
+        for Parsed { text, last, commands, .. } in statements.into_iter().skip(unchanged) {
+            if self.engine().halted {
+                break;
+            }
+            let mut engine = self.engine().clone();
+            engine.evaluate_commands(&commands).map_err(|err| err.at_line(last))?;
+            self.statements.push(Statement { text, engine });
+            evaluated += 1;
+        }
+        Ok(evaluated)
+    }
+}
+
+// Reloads the file, with its includes, and prints the answer after every change until
+// interrupted with Ctrl-C.
+pub fn run(options: &Options, file: &str, mut base: Evaluator) -> Result<(), EngineError> {
+    // The handler replaces the default of ending the process, so a runaway evaluation has to stop itself.
+    base.interruptible = true;
+    let mut worksheet = Worksheet::new(base);
+    let mut last = None;
+    interrupt::install();
+    while !interrupt::take() {
+        let mut source = Source::default();
+        source.sandbox = options.sandbox;
+        let loaded = source.load(file);
+        let current = loaded.as_ref().map(|_| source.text.clone()).map_err(|err| err.to_string());
+        if last.as_ref() != Some(&current) {
+            last = Some(current);
+            // On a terminal the new answer replaces the old one; piped, each is a line more.
+            if options.streams.escapes() && !options.quiet {
+                print!("\x1b[2J\x1b[H");
+            }
+            match loaded.and_then(|_| worksheet.update(&source.text)) {
+                Ok(_) => {
+                    let engine = worksheet.engine();
+                    for warning in &engine.warnings {
+                        diagnose(options, &warning_diagnostic(warning, Some(file)));
+                    }
+                    match engine.format(&engine.answer) {
+                        Ok(answer) => {
+                            emit(options, &answer);
+                            notify_outcome(options, &answer);
+                        }
+                        Err(err) => diagnose(options, &error_diagnostic(&err, Some(file), &source)),
+                    }
+                }
+                Err(err) if matches!(err.root(), EngineError::Interrupted) => break,
+                Err(err) => {
+                    diagnose(options, &error_diagnostic(&err, Some(file), &source));
+                    notify_outcome(options, &format!("error: {}", err));
+                }
+            }
+        }
+        std::thread::sleep(POLL);
+    }
+    std::io::stdout().flush().ok();
+    Ok(())
+}
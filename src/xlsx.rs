@@ -0,0 +1,301 @@
+// `load-xlsx book.xlsx Sheet1 B2:B20` reads a range of an Excel workbook. A workbook is a ZIP
+// archive of XML parts, so this holds just enough ZIP, DEFLATE and XML reading for the
+// worksheet cells, without pulling in any crates.
+use crate::csv::{self, Sheet};
+use crate::{EngineError, Value};
+
+// Far beyond any real workbook, so an archive claiming or inflating to more is refused before
+// it can take the memory: a few kilobytes of DEFLATE can expand to gigabytes.
+pub const MAX_PART: usize = 256 << 20;
+const MAX_ENTRIES: usize = 10_000;
+
+fn invalid(what: &str) -> EngineError {
+    EngineError::InvalidXlsx(what.into())
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn take(&mut self, n: u32) -> Result<u32, EngineError> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or_else(|| invalid("truncated deflate stream"))?;
+            self.buffer |= (byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer = self.buffer.checked_shr(n).unwrap_or(0);
+        self.count -= n;
+        Ok(value)
+    }
+}
+
+// A canonical Huffman code, as the number of codes of each length and the symbols in code order.
+struct Huffman {
+    counts: [i32; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|&s| lengths[s as usize] != 0).collect();
+        symbols.sort_by_key(|&s| lengths[s as usize]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, EngineError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= bits.take(1)? as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("bad Huffman code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+fn codes(bits: &mut Bits, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman, limit: usize) -> Result<(), EngineError> {
+    loop {
+        if out.len() > limit {
+            return Err(invalid("part too large"));
+        }
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(invalid("bad length code"));
+                }
+                let length = LENGTH_BASE[index] as usize + bits.take(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(bits)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(invalid("bad distance code"));
+                }
+                let distance = DISTANCE_BASE[index] as usize + bits.take(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(invalid("distance before the start of the data"));
+                }
+                for _ in 0..length {
+                    out.push(out[out.len() - distance]);
+                }
+            }
+        }
+    }
+}
+
+fn dynamic_tables(bits: &mut Bits) -> Result<(Huffman, Huffman), EngineError> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let literals = bits.take(5)? as usize + 257;
+    let distances = bits.take(5)? as usize + 1;
+    let mut code_lengths = [0u8; 19];
+    for &index in &ORDER[..bits.take(4)? as usize + 4] {
+        code_lengths[index] = bits.take(3)? as u8;
+    }
+    let code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![];
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or_else(|| invalid("repeat with no length"))?, 3 + bits.take(2)?),
+            17 => (0, 3 + bits.take(3)?),
+            _ => (0, 11 + bits.take(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err(invalid("too many code lengths"));
+    }
+    Ok((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+// Decompresses a raw DEFLATE stream (RFC 1951), failing once the output passes `limit` bytes.
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, EngineError> {
+    let mut bits = Bits { data, pos: 0, buffer: 0, count: 0 };
+    let mut out = vec![];
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                // Stored blocks start on a byte boundary.
+                (bits.buffer, bits.count) = (0, 0);
+                let header = data.get(bits.pos..bits.pos + 4).ok_or_else(|| invalid("truncated stored block"))?;
+                let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let block = data.get(bits.pos + 4..bits.pos + 4 + length).ok_or_else(|| invalid("truncated stored block"))?;
+                if out.len() + length > limit {
+                    return Err(invalid("part too large"));
+                }
+                out.extend_from_slice(block);
+                bits.pos += 4 + length;
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                codes(&mut bits, &mut out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]), limit)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut bits)?;
+                codes(&mut bits, &mut out, &literals, &distances, limit)?;
+            }
+            _ => return Err(invalid("bad block type")),
+        }
+        if out.len() > limit {
+            return Err(invalid("part too large"));
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn u16_at(data: &[u8], at: usize) -> Result<usize, EngineError> {
+    data.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or_else(|| invalid("truncated archive"))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<usize, EngineError> {
+    data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or_else(|| invalid("truncated archive"))
+}
+
+// The contents of one file in a ZIP archive, found through the central directory.
+pub fn unzip(archive: &[u8], name: &str) -> Result<Vec<u8>, EngineError> {
+    let end = (0..archive.len().saturating_sub(21)).rev().find(|&at| archive[at..].starts_with(b"PK\x05\x06")).ok_or_else(|| invalid("not a ZIP archive"))?;
+    let (entries, mut at) = (u16_at(archive, end + 10)?, u32_at(archive, end + 16)?);
+    if entries > MAX_ENTRIES {
+        return Err(invalid("too many entries"));
+    }
+    for _ in 0..entries {
+        if !archive.get(at..).is_some_and(|rest| rest.starts_with(b"PK\x01\x02")) {
+            return Err(invalid("bad central directory"));
+        }
+        let (method, size, unpacked) = (u16_at(archive, at + 10)?, u32_at(archive, at + 20)?, u32_at(archive, at + 24)?);
+        let name_length = u16_at(archive, at + 28)?;
+        let entry = archive.get(at + 46..at + 46 + name_length).ok_or_else(|| invalid("truncated archive"))?;
+        if entry == name.as_bytes() {
+            if size.max(unpacked) > MAX_PART {
+                return Err(invalid("part too large"));
+            }
+            let local = u32_at(archive, at + 42)?;
+            let start = local + 30 + u16_at(archive, local + 26)? + u16_at(archive, local + 28)?;
+            let data = archive.get(start..start + size).ok_or_else(|| invalid("truncated archive"))?;
+            return match method {
+                0 => Ok(data.to_vec()),
+                8 => inflate(data, MAX_PART),
+                _ => Err(invalid("unsupported compression")),
+            };
+        }
+        at += 46 + name_length + u16_at(archive, at + 30)? + u16_at(archive, at + 32)?;
+    }
+    Err(invalid(&format!("no part named {}", name)))
+}
+
+// Each `<name ...>` element as its attribute text and its content, empty for `<name .../>`.
+fn elements<'a>(xml: &'a str, name: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let mut rest = xml;
+    std::iter::from_fn(move || loop {
+        let start = rest.find(&open)? + open.len();
+        rest = &rest[start..];
+        // A longer tag name sharing the prefix, like `<cols` when looking for `<c`.
+        if !rest.starts_with([' ', '>', '/']) {
+            continue;
+        }
+        let tag_end = rest.find('>')?;
+        let attributes = &rest[..tag_end];
+        if let Some(attributes) = attributes.strip_suffix('/') {
+            rest = &rest[tag_end + 1..];
+            return Some((attributes, ""));
+        }
+        let content_end = rest.find(&close)?;
+        let content = &rest[tag_end + 1..content_end];
+        rest = &rest[content_end + close.len()..];
+        return Some((attributes, content));
+    })
+}
+
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!(" {}=\"", name);
+    let start = attributes.find(&pattern)? + pattern.len();
+    attributes[start..].find('"').map(|end| &attributes[start..start + end])
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+fn part(archive: &[u8], name: &str) -> Result<String, EngineError> {
+    String::from_utf8(unzip(archive, name)?).map_err(|_| invalid(&format!("{} is not UTF-8", name)))
+}
+
+// The numbers in `range` of the named worksheet: one cell as a number, a range as a list.
+pub fn load(archive: &[u8], sheet: &str, range: &str) -> Result<Value, EngineError> {
+    let workbook = part(archive, "xl/workbook.xml")?;
+    let id = elements(&workbook, "sheet")
+        .find(|(attributes, _)| attribute(attributes, "name").map(unescape).as_deref() == Some(sheet))
+        .and_then(|(attributes, _)| attribute(attributes, "r:id"))
+        .ok_or_else(|| invalid(&format!("no sheet named '{}'", sheet)))?;
+    let relationships = part(archive, "xl/_rels/workbook.xml.rels")?;
+    let target = elements(&relationships, "Relationship")
+        .find(|(attributes, _)| attribute(attributes, "Id") == Some(id))
+        .and_then(|(attributes, _)| attribute(attributes, "Target"))
+        .ok_or_else(|| invalid(&format!("sheet '{}' has no part", sheet)))?;
+    let path = match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("xl/{}", target),
+    };
+
+    // Only cells in the range are kept, so a cell far out like XFD1048576 allocates nothing.
+    let (from, to) = range.split_once(':').unwrap_or((range, range));
+    let Some(((c1, r1), (c2, r2))) = csv::cell(from).zip(csv::cell(to)) else {
+        return Sheet::from_rows(vec![]).lookup(range);
+    };
+    let (columns, wanted_rows) = (c1.min(c2)..=c1.max(c2), r1.min(r2)..=r1.max(r2));
+
+    // Only numeric cells keep their text; anything else reads as text, which `lookup` rejects.
+    let mut rows: Vec<Vec<String>> = vec![];
+    for (attributes, content) in elements(&part(archive, &path)?, "c") {
+        let Some((column, row)) = attribute(attributes, "r").and_then(csv::cell) else {
+            continue;
+        };
+        if !columns.contains(&column) || !wanted_rows.contains(&row) {
+            continue;
+        }
+        let value = match (attribute(attributes, "t"), elements(content, "v").next()) {
+            (None | Some("n"), Some((_, value))) => value.to_string(),
+            (_, None) => String::new(),
+            _ => "text".to_string(),
+        };
+        if rows.len() <= row {
+            rows.resize(row + 1, vec![]);
+        }
+        if rows[row].len() <= column {
+            rows[row].resize(column + 1, String::new());
+        }
+        rows[row][column] = value;
+    }
+    Sheet::from_rows(rows).lookup(range)
+}